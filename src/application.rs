@@ -4,6 +4,8 @@
 // reading from USB is still synchronous. Multiple threads are used
 // with data being sent between threads via channels.
 use std::{
+    env, fs,
+    path::PathBuf,
     thread,
     time::{Duration, Instant},
 };
@@ -12,22 +14,48 @@ use iced::{
     executor, time, Application as IcedApplication, Clipboard, Column, Command, Container, Element,
     HorizontalAlignment, Length, Settings, Subscription, Text,
 };
-use iced_native::{subscription, window, Event};
+use iced_native::{keyboard, subscription, window, Event};
 use libant::Request;
-use log::{error, info};
+use log::{error, info, warn};
 
+mod activity;
+pub mod cli;
+mod compliance;
+mod ftp_test;
+mod history;
 mod library;
+mod power_curve;
+mod profile_settings;
+mod profile_store;
+mod sensors;
+mod session;
+mod stats;
+mod training_load;
 mod types;
 mod ui;
 mod user_profile;
-use library::{Library, ShadowLibrary};
-use ui::menubar::MenuBar;
+mod workout;
+mod zones;
+use history::History;
+use library::{FileStamp, Library, ShadowLibrary, Source};
+use profile_store::{ProfileId, ProfileStore};
+use sensors::Simulated;
+use session::Session;
+use stats::Stats;
+use ui::history as HistoryScreen;
+use ui::library as LibraryScreen;
+use ui::menubar::{MenuBar, ProfileChoice};
+use ui::ride as RideScreen;
 use ui::user_profile as UserProfileScreen;
 use user_profile::UserProfile;
+use workout::Workout;
 
 // Run() is the main function to call. This handles starting up all the
-// threads and configuring the channels.
-pub fn run() {
+// threads and configuring the channels. team_library is an optional directory
+// of shared workouts from a coach or team. simulate allows riding with
+// simulated sensors, the only sensors there are until libant
+// hands ANT+ data pages to sensors::Ant.
+pub fn run(team_library: Option<PathBuf>, simulate: bool) {
     // Used for sending messages to ANT+ devices. (Open channel, Close channel,
     // request data, etc.
     let (ant_request_tx, ant_request_rx) = libant::unbounded();
@@ -40,6 +68,8 @@ pub fn run() {
 
     let flags = AppFlags {
         ant_request_tx: Some(ant_request_tx),
+        team_library,
+        simulate,
     };
 
     let window_settings = iced::window::Settings {
@@ -62,7 +92,7 @@ pub fn run() {
 // Starting
 //   - Load user profiles
 //   - Load workouts
-//   - Load history (future)
+//   - Load history
 // Ready
 //   - Select user profile
 //   - Update user profile
@@ -91,6 +121,8 @@ enum AppState {
 enum ScreenState {
     UserProfile,
     Library,
+    History,
+    Ride,
     Devices,
 }
 // Main application structure for handling state changes and views of the
@@ -98,10 +130,10 @@ enum ScreenState {
 // TODO: (Future) Add history tracking of any selected workout from workouts
 // screen so user can be brought back to selected workout if they click
 // on another screen button.
-// (9/23) UserProfiles are created with a default "new" profile as profile 0.
-// This profile is never removed. It's there so there is a "New..." or "Create.."
-// field in the pick list (if pick list is maintained). First user profile will
-// always be 1.
+// (9/23) UserProfiles are kept in a ProfileStore. While a profile is being
+// created the User Profile screen shows a blank profile that isn't in the
+// store until it is saved. The pick list has a "New..." entry to start
+// creating a profile.
 // (10/6) Workouts should be changed to Library which is a collection of intervals
 // that can be used to create workouts and a collection of available workouts.
 // (Future) Possibly add a way to select intervals in the UI and create a workout from
@@ -111,45 +143,285 @@ struct Application {
     screen_state: ScreenState,
     should_exit: bool,
     ant_request_tx: libant::Sender<Request>,
-    active_user_profile: usize,
-    user_profiles: Vec<UserProfile>,
+    // Whether workouts can be ridden with simulated sensors. Without them
+    // there is nothing to ride with until ANT+ sensors are supported.
+    simulate: bool,
+    profiles: ProfileStore,
+    // Blank profile shown while creating a profile.
+    new_profile: UserProfile,
+    creating_profile: bool,
     library: Library,
+    // Directories the library was loaded from and the modified time of each
+    // file in them, used to reload the library when a file changes.
+    library_dirs: Vec<(Source, PathBuf)>,
+    library_files: Vec<FileStamp>,
+    // Errors from the last time the library was loaded.
+    library_diagnostics: Vec<String>,
+    history: History,
+    // The workout being ridden, or the summary of the last ride until the
+    // rider is done with it.
+    ride: Option<RideScreen::Ride>,
     menubar: MenuBar,
+    library_screen: LibraryScreen::State,
+    history_screen: HistoryScreen::State,
+    ride_screen: RideScreen::State,
     user_profile_screen: UserProfileScreen::State,
 }
 
 // Message enum for configuring subscriptions and updates in the application.
-// Tick will be used with a subscription to trigger refreshes of the
-// application view on a set.
+// Tick is used with a subscription to refresh the application view on a set
+// interval and to ride each second of a workout.
 // EventOccurred watches for different events (could be mouse, key, window, etc)
 // and acts on the events accordingly.
 #[derive(Debug, Clone)]
 pub enum Message {
     Loaded(Result<SavedState, LoadError>),
     Tick(Instant),
+    CheckLibrary(Instant),
+    LibraryChecked(Vec<FileStamp>),
+    LibraryReloaded(LibraryFiles),
     EventOccurred(Event),
     ShowLibrary,
+    ShowHistory,
+    ShowRide,
     ShowDevices,
     ShowUserProfile,
-    UserProfileScreenMessage(usize, UserProfileScreen::Message),
-    UserProfileSelected(UserProfile),
+    LibraryScreenMessage(LibraryScreen::Message),
+    HistoryScreenMessage(HistoryScreen::Message),
+    RideScreenMessage(RideScreen::Message),
+    // The profile being edited, or None when creating a profile.
+    UserProfileScreenMessage(Option<ProfileId>, UserProfileScreen::Message),
+    UserProfileSelected(ProfileChoice),
 }
 
 // AppFlags are used to pass channels into the application for communication
 // between the GUI and the backend threads that receive and send data
 // to ANT+ devices.
+// team_library is passed in from the command line to load workouts shared
+// by a team. simulate is whether workouts can be ridden with simulated
+// sensors.
 struct AppFlags {
     ant_request_tx: Option<libant::Sender<Request>>,
+    team_library: Option<PathBuf>,
+    simulate: bool,
 }
 
 impl Default for AppFlags {
     fn default() -> Self {
         Self {
             ant_request_tx: None,
+            team_library: None,
+            simulate: false,
         }
     }
 }
 
+impl Application {
+    // Saves every profile with the active profile marked as active.
+    fn save_profiles(&self) {
+        let profiles = self.profiles.to_profiles();
+        let result = profiles_file()
+            .ok_or_else(|| String::from("no home directory"))
+            .and_then(|path| user_profile::save_profiles(&path, &profiles));
+        if let Err(e) = result {
+            error!("Unable to save user profiles: {}", e);
+        }
+    }
+
+    // Builds a new library from the built-in templates and the library files.
+    // Templates from the user library take precedence over team templates
+    // which take precedence over built-in templates. The library is replaced
+    // as a whole so a workout in progress keeps the template it started with.
+    fn load_library(&mut self, library_files: LibraryFiles) {
+        let (library, diagnostics) = library_files.build();
+        for diagnostic in &diagnostics {
+            warn!("{}", diagnostic);
+        }
+        for s in &library.shadowed {
+            info!("{}", s);
+        }
+        self.library = library;
+        self.library_diagnostics = diagnostics;
+    }
+
+    // Profile the User Profile screen is editing.
+    fn editing_profile(&self) -> Option<ProfileId> {
+        match self.creating_profile {
+            true => None,
+            false => self.profiles.active_id(),
+        }
+    }
+
+    // Starts riding a workout as the active profile. Only one ride can be in
+    // progress so starting another goes back to the ride.
+    // TODO: Ride with sensors::Ant once libant hands over data pages, rides
+    // need simulated sensors until then.
+    fn start_ride(&mut self, name: &str) {
+        if !matches!(self.ride, Some(RideScreen::Ride::Riding { .. })) {
+            if !self.simulate {
+                warn!("Riding needs --simulate until ANT+ sensors are supported");
+                return;
+            }
+            let id = match (self.creating_profile, self.profiles.active_id()) {
+                (false, Some(id)) => id,
+                _ => {
+                    warn!("A profile is needed to ride {}", name);
+                    return;
+                }
+            };
+            let (profile, template) = match (self.profiles.get(id), self.library.workout(name)) {
+                (Some(profile), Some(template)) => (profile, template),
+                _ => {
+                    error!("Unable to ride {}", name);
+                    return;
+                }
+            };
+            // Targets and simulated power are worked out from FTP.
+            if profile.ftp == 0 {
+                warn!("{} needs an FTP to ride {}", profile.name, name);
+                return;
+            }
+            info!("Riding {} as {}", name, profile.name);
+            let mut session = Session::new(Workout::new(template, &profile.thresholds()));
+            session.set_erg(profile.settings.erg_mode);
+            self.ride = Some(RideScreen::Ride::Riding {
+                profile: id,
+                rider: Box::new(profile.clone()),
+                session,
+                sensors: Box::new(Simulated::new(profile.ftp / 2)),
+                next_step: Instant::now() + Duration::from_secs(1),
+            });
+        }
+        self.screen_state = ScreenState::Ride;
+    }
+
+    // Ends the ride, saves it and adds it to the history. The summary is shown
+    // on the Ride screen until the rider is done with it.
+    fn finish_ride(&mut self) {
+        let (id, rider, session) = match self.ride.take() {
+            Some(RideScreen::Ride::Riding {
+                profile,
+                rider,
+                session,
+                ..
+            }) => (profile, rider, session),
+            ride => {
+                self.ride = ride;
+                return;
+            }
+        };
+        let profile = match self.profiles.get(id) {
+            Some(profile) => profile.clone(),
+            None => {
+                warn!(
+                    "Profile riding no longer exists, saving ride as {}",
+                    rider.name
+                );
+                *rider
+            }
+        };
+        let ftp_test = session.workout.ftp_test;
+        let activity = session.finish(&profile);
+        if activity.records.is_empty() {
+            self.screen_state = ScreenState::Library;
+            return;
+        }
+        let ftp = activity
+            .date()
+            .map_or(profile.ftp, |date| profile.ftp_on(date));
+        let saved = activities_dir()
+            .ok_or_else(|| String::from("no home directory"))
+            .and_then(|dir| activity.save(&dir));
+        let mut records = Vec::new();
+        match (&saved, activities_dir()) {
+            (Ok(_), Some(dir)) => {
+                // Opening the history adds the ride to the index.
+                let (history, errors) = History::open(&dir, &self.profiles.to_profiles());
+                for e in errors {
+                    warn!("{}", e);
+                }
+                self.history = history;
+                if let Some(entry) = self.history.entry(&activity.file_name()) {
+                    records = self.history.personal_records(entry);
+                }
+            }
+            (Err(e), _) => error!("Unable to save ride: {}", e),
+            _ => {}
+        }
+        self.ride = Some(RideScreen::Ride::Finished(RideScreen::Summary {
+            profile: id,
+            workout: activity.workout.clone(),
+            date: activity.date(),
+            stats: Stats::new(&activity.records, ftp),
+            compliance: activity.compliance.as_ref().and_then(|c| c.overall),
+            saved,
+            records,
+            ftp_test: ftp_test.and_then(|test| test.ftp(&activity.records).map(|ftp| (test, ftp))),
+            ftp_updated: false,
+        }));
+    }
+
+    // Updates the FTP of the profile that rode an FTP test with the result.
+    fn update_ftp(&mut self) {
+        let summary = match &mut self.ride {
+            Some(RideScreen::Ride::Finished(summary)) if !summary.ftp_updated => summary,
+            _ => return,
+        };
+        let (test, ftp) = match summary.ftp_test {
+            Some(result) => result,
+            None => return,
+        };
+        let mut profile = match self.profiles.get(summary.profile) {
+            Some(profile) => profile.clone(),
+            None => return,
+        };
+        let date = summary
+            .date
+            .unwrap_or_else(|| chrono::Local::now().naive_local().date());
+        profile.set_ftp(ftp, date, test.source());
+        info!("Updating {}'s FTP to {}W", profile.name, ftp);
+        match self.profiles.update(summary.profile, profile) {
+            Ok(()) => {
+                summary.ftp_updated = true;
+                self.save_profiles();
+                self.update_history();
+            }
+            Err(e) => error!("Unable to update profile: {}", e),
+        }
+    }
+
+    // Changes the active profile and saves the profiles.
+    fn edit_active_profile(&mut self, edit: impl FnOnce(&mut UserProfile)) {
+        let id = match self.profiles.active_id() {
+            Some(id) => id,
+            None => return,
+        };
+        let mut profile = match self.profiles.get(id) {
+            Some(profile) => profile.clone(),
+            None => return,
+        };
+        edit(&mut profile);
+        match self.profiles.update(id, profile) {
+            Ok(()) => self.save_profiles(),
+            Err(e) => error!("Unable to update profile: {}", e),
+        }
+    }
+
+    // Brings the FTP and TSS of rides up to date after a profile changed.
+    fn update_history(&mut self) {
+        for e in self.history.update_profiles(&self.profiles.to_profiles()) {
+            warn!("{}", e);
+        }
+    }
+
+    fn create_profile(&mut self) {
+        self.creating_profile = true;
+        self.screen_state = ScreenState::UserProfile;
+        self.user_profile_screen
+            .update(UserProfileScreen::Message::Clear);
+    }
+}
+
 impl IcedApplication for Application {
     type Executor = executor::Default;
     type Message = Message;
@@ -164,13 +436,23 @@ impl IcedApplication for Application {
                 ant_request_tx: flags
                     .ant_request_tx
                     .expect("Error 001: Application misconfigured"),
-                user_profiles: vec![UserProfile::new(true)],
-                active_user_profile: 0,
+                simulate: flags.simulate,
+                profiles: ProfileStore::default(),
+                new_profile: UserProfile::new(true),
+                creating_profile: false,
                 library: Library::default(),
+                library_dirs: Vec::new(),
+                library_files: Vec::new(),
+                library_diagnostics: Vec::new(),
+                history: History::default(),
+                ride: None,
                 menubar: MenuBar::default(),
+                library_screen: LibraryScreen::State::default(),
+                history_screen: HistoryScreen::State::default(),
+                ride_screen: RideScreen::State::default(),
                 user_profile_screen: UserProfileScreen::State::default(),
             },
-            Command::perform(SavedState::load(), Message::Loaded),
+            Command::perform(SavedState::load(flags.team_library), Message::Loaded),
         )
     }
 
@@ -187,54 +469,19 @@ impl IcedApplication for Application {
                 Message::Loaded(Ok(state)) => {
                     // Load the library of workouts/intervals that were retrieved
                     // from local files.
-                    if let Some(intervals) = state.shadow_library.intervals {
-                        for interval in intervals {
-                            if let Err(_e) = interval.validate() {
-                                // log error
-                                continue;
-                            }
-                            let name = interval.name.clone().unwrap();
-                            match self.library.intervals.contains_key(&name) {
-                                true => continue, //log error for duplicate key
-                                false => self.library.intervals.insert(name, interval),
-                            };
-                        }
-                    }
+                    self.library_files = state.library_files;
+                    self.library_dirs = state.library_dirs;
+                    self.load_library(state.library);
+                    self.history = state.history;
 
-                    if let Some(workouts) = state.shadow_library.workouts {
-                        for mut shadow_workout in workouts {
-                            if let Err(_e) = shadow_workout.validate(&self.library.intervals) {
-                                // log error
-                                continue;
-                            }
-                            let workout = shadow_workout.build_workout_template();
-                            self.library.workouts.insert(workout.name.clone(), workout);
-                        }
-                    }
-
-                    if let Some(user_profiles) = state.user_profiles {
-                        self.user_profiles.extend_from_slice(&user_profiles);
-                        for (i, profile) in self.user_profiles.iter_mut().enumerate() {
-                            if profile.active {
-                                if self.active_user_profile != 0 {
-                                    error!("Multiple user profiles set as active. Leaving first profile set as active");
-                                    profile.active = false;
-                                }
-                                self.active_user_profile = i;
-                            }
-                        }
-                        // We loaded profiles from saved state, but no profiles
-                        // were set to active. Set first profile loaded to active
-                        if self.active_user_profile == 0 {
-                            self.active_user_profile = 1;
-                            self.user_profiles[1].active = true;
-                        }
+                    if let Some(profiles) = state.profiles {
+                        self.profiles = profiles;
                     }
                     // If no user profiles were loaded, set screen state to
                     // UserProfile so a user profile can be created
-                    if self.active_user_profile == 0 {
+                    if self.profiles.is_empty() {
                         info!("Setting screen_state to ScreenState::UserProfile");
-                        self.screen_state = ScreenState::UserProfile;
+                        self.create_profile();
                     }
                     self.state = AppState::Ready;
                 }
@@ -243,97 +490,207 @@ impl IcedApplication for Application {
             },
             AppState::Ready => {
                 match message {
-                    Message::Tick(_) => {} //do nothing for now
+                    Message::Tick(now) => {
+                        if let Some(RideScreen::Ride::Riding {
+                            session,
+                            sensors,
+                            next_step,
+                            ..
+                        }) = &mut self.ride
+                        {
+                            // Ticks come several times a second and the
+                            // session moves on a second at a time.
+                            if now >= *next_step {
+                                session.step(sensors.as_mut());
+                                *next_step += Duration::from_secs(1);
+                                if session.is_finished() {
+                                    self.finish_ride();
+                                }
+                            }
+                        }
+                    }
+                    // The directories are read off the update so a slow
+                    // disk doesn't hold up the screen.
+                    Message::CheckLibrary(_) => {
+                        return Command::perform(
+                            check_library(self.library_dirs.clone()),
+                            Message::LibraryChecked,
+                        );
+                    }
+                    Message::LibraryChecked(files) if files != self.library_files => {
+                        info!("Library files changed. Reloading library");
+                        self.library_files = files;
+                        return Command::perform(
+                            LibraryFiles::load(self.library_dirs.clone()),
+                            Message::LibraryReloaded,
+                        );
+                    }
+                    Message::LibraryReloaded(library) => self.load_library(library),
                     Message::EventOccurred(Event::Window(window::Event::CloseRequested)) => {
                         // May want to look into how to filter events before getting to this update
                         //if let Event::Window(window::Event::CloseRequested) = event {
                         log::info!("Exiting application");
+                        // A ride in progress is saved before exiting.
+                        self.finish_ride();
                         // Send quit request to ANT+ run thread
                         self.ant_request_tx.send(Request::Quit).unwrap();
                         thread::sleep(Duration::from_millis(500));
                         self.should_exit = true;
                         //}
                     }
+                    Message::EventOccurred(Event::Keyboard(keyboard::Event::KeyPressed {
+                        key_code,
+                        ..
+                    })) => {
+                        // Keys only control the ride while it's on screen so
+                        // they don't act on a ride from other screens.
+                        if let (ScreenState::Ride, Some(ride)) =
+                            (&self.screen_state, &mut self.ride)
+                        {
+                            if let RideScreen::Ride::Riding { profile, .. } = ride {
+                                // Keys without a name can't be bound.
+                                let action = self
+                                    .profiles
+                                    .get(*profile)
+                                    .zip(profile_settings::key_name(key_code))
+                                    .and_then(|(p, key)| p.settings.ride_keys.action(key));
+                                if let Some(action) = action {
+                                    self.ride_screen
+                                        .update(RideScreen::Message::from_action(action), ride);
+                                }
+                            }
+                        }
+                    }
                     Message::ShowUserProfile => self.screen_state = ScreenState::UserProfile,
                     Message::ShowLibrary => self.screen_state = ScreenState::Library,
+                    Message::ShowHistory => self.screen_state = ScreenState::History,
+                    Message::ShowRide => self.screen_state = ScreenState::Ride,
                     Message::ShowDevices => self.screen_state = ScreenState::Devices,
+                    Message::LibraryScreenMessage(LibraryScreen::Message::Ride(name)) => {
+                        self.start_ride(&name)
+                    }
+                    Message::LibraryScreenMessage(LibraryScreen::Message::Plan(name, date)) => {
+                        let today = chrono::Local::now().naive_local().date();
+                        self.edit_active_profile(|profile| profile.plan(date, &name, today));
+                        self.library_screen
+                            .update(LibraryScreen::Message::Plan(name, date));
+                    }
+                    Message::LibraryScreenMessage(library_message) => {
+                        self.library_screen.update(library_message)
+                    }
+                    Message::HistoryScreenMessage(HistoryScreen::Message::Unplan(i)) => {
+                        self.edit_active_profile(|profile| profile.unplan(i))
+                    }
+                    Message::RideScreenMessage(RideScreen::Message::End) => self.finish_ride(),
+                    Message::RideScreenMessage(RideScreen::Message::UpdateFtp) => self.update_ftp(),
+                    Message::RideScreenMessage(RideScreen::Message::Done) => {
+                        // The ride is shown in the history if it was saved.
+                        if let Some(RideScreen::Ride::Finished(summary)) = self.ride.take() {
+                            if let Ok(path) = summary.saved {
+                                let file = path
+                                    .file_name()
+                                    .map(|file| file.to_string_lossy().to_string())
+                                    .unwrap_or_default();
+                                self.history_screen.update(
+                                    HistoryScreen::Message::RideSelected(file),
+                                    &self.history,
+                                );
+                            }
+                            self.screen_state = ScreenState::History;
+                        }
+                    }
+                    Message::RideScreenMessage(ride_message) => {
+                        if let Some(ride) = &mut self.ride {
+                            self.ride_screen.update(ride_message, ride)
+                        }
+                    }
+                    Message::HistoryScreenMessage(history_message) => {
+                        self.history_screen.update(history_message, &self.history)
+                    }
                     Message::UserProfileScreenMessage(
-                        i,
-                        UserProfileScreen::Message::SaveProfile(name, ftp),
+                        id,
+                        UserProfileScreen::Message::SaveProfile(profile),
                     ) => {
                         // Check to see if we are creating or updating a profile.
-                        // i will be 0 when creating a profile.
-                        // TODO Validate name isn't empty. Return an error back
-                        // to UserProfile screen to display instead. Or disable
-                        // Save button unless input field has valid data.
-                        if i == 0 {
-                            // Create a new profile
-                            info!("Creating user profile {}", self.user_profiles.len());
-                            let mut profile = UserProfile::new(true);
-                            profile.name = name;
-                            profile.ftp = ftp;
-                            self.user_profiles.push(profile);
-                            self.active_user_profile = self.user_profiles.len() - 1;
-                        } else {
-                            info!("Saving user profile {}", i);
-                            if let Some(profile) = self.user_profiles.get_mut(i) {
-                                profile.name = name;
-                                profile.ftp = ftp;
+                        // The screen only sends profiles that pass validation,
+                        // but the store can still refuse a name already taken.
+                        let result = match id {
+                            None => {
+                                info!("Creating user profile {}", profile.name);
+                                self.profiles.add(*profile).map(|_| ())
+                            }
+                            Some(id) => {
+                                info!("Saving user profile {}", profile.name);
+                                self.profiles.update(id, *profile)
+                            }
+                        };
+                        match result {
+                            Ok(()) => {
+                                self.creating_profile = false;
+                                self.user_profile_screen
+                                    .update(UserProfileScreen::Message::Editing(false));
+                                self.save_profiles();
+                                self.update_history();
                             }
+                            Err(e) => self
+                                .user_profile_screen
+                                .update(UserProfileScreen::Message::SaveFailed(e)),
                         }
-                        self.user_profile_screen
-                            .update(UserProfileScreen::Message::Editing(false));
                     }
                     Message::UserProfileScreenMessage(
-                        i,
+                        id,
                         UserProfileScreen::Message::DeleteProfile,
                     ) => {
-                        // We delete the requested profile. If this leaves no profiles,
+                        // We delete the requested profile and the store picks
+                        // the next active profile. If this leaves no profiles,
                         // then we create a new profile. The application requires
                         // a profile in order to function
-                        // TODO: Decide if we should show a list of available profiles
-                        // and allow for actions to occur outside of current
-                        // active profile
-                        // TODO: Disable delete button when creating a profile. For now
-                        // just add a check to remove later.
-                        if i == 0 {
-                            error!("Trying to delete default profile");
-                            self.user_profile_screen
-                                .update(UserProfileScreen::Message::Clear);
-                            return Command::none();
+                        let id = match id {
+                            Some(id) => id,
+                            None => {
+                                error!("Trying to delete a profile that hasn't been saved");
+                                return Command::none();
+                            }
+                        };
+                        // The ride would be left without its profile.
+                        if let Some(RideScreen::Ride::Riding { profile, .. }) = &self.ride {
+                            if *profile == id {
+                                self.user_profile_screen.update(
+                                    UserProfileScreen::Message::SaveFailed(
+                                        "A profile can't be deleted while it's riding",
+                                    ),
+                                );
+                                return Command::none();
+                            }
                         }
-                        info!("Removing user profile {}", i);
-                        // We always set the active profile to the first available
-                        // profile after deletion. We are always deleting the current
-                        // active profile (in current iteration)
-                        self.user_profiles.remove(i);
-                        if self.user_profiles.len() == 1 {
-                            // All user profiles have been deleted
-                            self.active_user_profile = 0;
-                        } else {
-                            self.active_user_profile = 1;
+                        match self.profiles.delete(id) {
+                            Ok(profile) => info!("Removing user profile {}", profile.name),
+                            Err(e) => error!("Unable to delete user profile: {}", e),
                         }
                         self.user_profile_screen
                             .update(UserProfileScreen::Message::Clear);
+                        if self.profiles.is_empty() {
+                            self.create_profile();
+                        }
+                        self.save_profiles();
                     }
                     Message::UserProfileScreenMessage(_, user_profile_message) => {
                         self.user_profile_screen.update(user_profile_message)
                     }
-                    Message::UserProfileSelected(profile) => {
-                        // May be a better way to do this to get index of profile
-                        // sent with profile because we only care about the index.
-                        // Find user profile. Set active_user_profile to selected profile.
-                        for (i, p) in self.user_profiles.iter().enumerate() {
-                            if profile == *p {
+                    Message::UserProfileSelected(ProfileChoice::New) => self.create_profile(),
+                    Message::UserProfileSelected(ProfileChoice::Profile(id, _)) => {
+                        match self.profiles.activate(id) {
+                            Ok(()) => {
+                                self.creating_profile = false;
                                 self.user_profile_screen
                                     .update(UserProfileScreen::Message::Clear);
-                                self.active_user_profile = i;
-                                // If profile is default profile, change screen state
-                                // for a user to be created.
-                                if i == 0 {
-                                    self.screen_state = ScreenState::UserProfile;
-                                }
+                                // A ride from the last profile shouldn't be
+                                // left showing.
+                                self.history_screen
+                                    .update(HistoryScreen::Message::Back, &self.history);
+                                self.save_profiles();
                             }
+                            Err(e) => error!("Unable to select user profile: {}", e),
                         }
                     }
                     _ => {}
@@ -348,10 +705,16 @@ impl IcedApplication for Application {
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        Subscription::batch(vec![
+        let mut subscriptions = vec![
             time::every(Duration::from_millis(125)).map(Message::Tick),
             subscription::events().map(Message::EventOccurred),
-        ])
+        ];
+        // Polling every couple of seconds is quick enough to pick up changes
+        // saved from an editor without scanning the directories every tick.
+        if !self.library_dirs.is_empty() {
+            subscriptions.push(time::every(Duration::from_secs(2)).map(Message::CheckLibrary));
+        }
+        Subscription::batch(subscriptions)
     }
 
     fn view(&mut self) -> Element<Message> {
@@ -371,28 +734,55 @@ impl IcedApplication for Application {
                 // Each page returns a Container::new() that will be sized to
                 // fill all the available space.
                 //
+                let editing = self.editing_profile();
+                // The blank profile is shown while creating a profile or when
+                // there are no profiles yet.
+                let active = match (self.creating_profile, self.profiles.active()) {
+                    (false, Some(profile)) => profile,
+                    _ => &self.new_profile,
+                };
                 let main_screen = match self.screen_state {
                     ScreenState::UserProfile => {
-                        // By default, we create a dummy user account if none
-                        // are found in the SavedState. When loading from SavedState,
-                        // at least one profile should be set as active. We'll
-                        // unwrap this value to throw a panic to show we missed
-                        // something in our code.
-                        //let active_user_profile = self
-                        //    .active_user_profile
-                        //    .expect("Active user profile should not be set to none at this point");
-                        let active_user_profile = self.active_user_profile;
+                        // The blank profile is edited when creating a profile
+                        // or when there are no profiles.
+                        let profile = match editing {
+                            Some(id) => self.profiles.get(id).unwrap_or(&self.new_profile),
+                            None => &self.new_profile,
+                        };
                         self.user_profile_screen
-                            .view(&self.user_profiles[active_user_profile])
-                            .map(move |message| {
-                                Message::UserProfileScreenMessage(active_user_profile, message)
-                            })
-                        //self.user_profiles[active_user_profile]
-                        //    .view()
-                        //    .map(move |message| {
-                        //        Message::UserProfileMessage(active_user_profile, message)
-                        //    })
+                            .view(profile)
+                            .map(move |message| Message::UserProfileScreenMessage(editing, message))
                     }
+                    ScreenState::Library => self
+                        .library_screen
+                        .view(
+                            &self.library,
+                            active,
+                            &self.library_diagnostics,
+                            self.simulate,
+                        )
+                        .map(Message::LibraryScreenMessage),
+                    ScreenState::History => self
+                        .history_screen
+                        .view(&self.history, active, &self.library)
+                        .map(Message::HistoryScreenMessage),
+                    ScreenState::Ride => match &self.ride {
+                        Some(ride) => {
+                            // The ride is shown for the profile riding even
+                            // if another profile has been selected since.
+                            let id = match ride {
+                                RideScreen::Ride::Riding { profile, .. } => *profile,
+                                RideScreen::Ride::Finished(summary) => summary.profile,
+                            };
+                            let profile = self.profiles.get(id).unwrap_or(active);
+                            self.ride_screen
+                                .view(ride, profile)
+                                .map(Message::RideScreenMessage)
+                        }
+                        None => Container::new(Text::new("No ride in progress").size(30))
+                            .padding(20)
+                            .into(),
+                    },
                     _ => Container::new(
                         Column::new().push(Text::new("This shouldn't be seen yet").size(40)),
                     )
@@ -404,7 +794,7 @@ impl IcedApplication for Application {
                     .height(Length::Fill)
                     .push(
                         self.menubar
-                            .view(&self.user_profiles, self.active_user_profile),
+                            .view(&self.profiles, editing, self.ride.is_some()),
                     )
                     .push(main_screen)
                     .into()
@@ -430,9 +820,8 @@ fn initializing_message<'a>() -> Element<'a, Message> {
 // $HOME_DIR/2by20/{workouts, profiles, activities}
 // If the directories are not there, they will be created. If the directories
 // are there, workouts and profiles will be loaded.
-// (Future) Acitivity history will probably be stored in an internal DB to
-// easily load and save. Profiles may or may not move to the internal DB as well
-// but for now they will just be a toml file.
+// Activity history is a JSON file per ride in the activities directory with an
+// index of every ride, see history.rs. Profiles are a toml file.
 //
 // Example
 // [[profile]]
@@ -446,8 +835,60 @@ fn initializing_message<'a>() -> Element<'a, Message> {
 
 #[derive(Debug, Clone)]
 pub struct SavedState {
-    user_profiles: Option<Vec<UserProfile>>,
-    shadow_library: ShadowLibrary,
+    profiles: Option<ProfileStore>,
+    history: History,
+    library_dirs: Vec<(Source, PathBuf)>,
+    library_files: Vec<FileStamp>,
+    library: LibraryFiles,
+}
+
+// Intervals and workouts read from the library directories along with any
+// files that couldn't be read.
+#[derive(Debug, Clone)]
+pub struct LibraryFiles {
+    shadow_libraries: Vec<(Source, ShadowLibrary)>,
+    errors: Vec<String>,
+}
+
+impl LibraryFiles {
+    async fn load(library_dirs: Vec<(Source, PathBuf)>) -> LibraryFiles {
+        Self::read(library_dirs)
+    }
+
+    fn read(library_dirs: Vec<(Source, PathBuf)>) -> LibraryFiles {
+        let mut library = LibraryFiles {
+            shadow_libraries: Vec::new(),
+            errors: Vec::new(),
+        };
+        for (source, dir) in library_dirs {
+            let (shadow_library, errors) = ShadowLibrary::from_dir(&dir);
+            for (path, e) in errors {
+                library
+                    .errors
+                    .push(format!("Skipping library file {}: {}", path.display(), e));
+            }
+            library.shadow_libraries.push((source, shadow_library));
+        }
+        library
+    }
+
+    // Builds a library from the built-in templates and the library files,
+    // returning any files and templates that couldn't be loaded.
+    fn build(self) -> (Library, Vec<String>) {
+        let mut library = Library::default();
+        let mut diagnostics = self.errors;
+        for (source, shadow_library) in self.shadow_libraries {
+            for e in library.load(shadow_library, source) {
+                match &e.path {
+                    Some(path) => {
+                        diagnostics.push(format!("Unable to load {} from {}", e, path.display()))
+                    }
+                    None => diagnostics.push(format!("Unable to load {}", e)),
+                }
+            }
+        }
+        (library, diagnostics)
+    }
 }
 
 // TODO: Implement application error logic. Doing this for now.
@@ -464,15 +905,43 @@ impl SavedState {
     // $HOME_DIR/Documents/2by20/activities
     // At some point may also include applications settings.
     // fn init()
-    async fn load() -> Result<SavedState, LoadError> {
+    async fn load(team_library: Option<PathBuf>) -> Result<SavedState, LoadError> {
         // - Call init which will verify the directories exist, and if they
         // don't exist created them.
         // - Load profiles from $HOME_DIR/Documents/2by20/profiles
         // - Load workouts from $HOME_DIR/Documents/2by20/workouts
-        let sl = ShadowLibrary::default();
+        // - Load team workouts from the directory given on the command line
+        // - Load ride history from $HOME_DIR/Documents/2by20/activities
+        let library_dirs = library_dirs(team_library)?;
+        let profiles = profiles_file().ok_or(LoadError::DirectoryError)?;
+        // A profiles file that can't be read is logged rather than keeping the
+        // application from starting.
+        let user_profiles = match profiles.exists() {
+            true => match ProfileStore::load(&profiles) {
+                Ok(user_profiles) => Some(user_profiles),
+                Err(e) => {
+                    error!("Unable to load {}: {}", profiles.display(), e);
+                    None
+                }
+            },
+            false => None,
+        };
+        let activities = activities_dir().ok_or(LoadError::DirectoryError)?;
+        let (history, errors) = History::open(
+            &activities,
+            &user_profiles
+                .as_ref()
+                .map_or_else(Vec::new, ProfileStore::to_profiles),
+        );
+        for e in errors {
+            warn!("{}", e);
+        }
         Ok(SavedState {
-            user_profiles: None,
-            shadow_library: sl,
+            profiles: user_profiles,
+            history,
+            library_files: check_library(library_dirs.clone()).await,
+            library: LibraryFiles::load(library_dirs.clone()).await,
+            library_dirs,
         })
     }
 
@@ -482,3 +951,73 @@ impl SavedState {
     // save_activity will save .fit file to file system
     // fn save_activity()
 }
+
+// $HOME_DIR/Documents/2by20
+fn data_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join("Documents").join("2by20"))
+}
+
+// $HOME_DIR/Documents/2by20/profiles/profiles.toml
+fn profiles_file() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("profiles").join("profiles.toml"))
+}
+
+// $HOME_DIR/Documents/2by20/activities
+fn activities_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("activities"))
+}
+
+// Directories to load the library from in order of precedence. The user
+// library directory is created if it doesn't exist.
+fn library_dirs(team_library: Option<PathBuf>) -> Result<Vec<(Source, PathBuf)>, LoadError> {
+    let mut library_dirs = Vec::new();
+    if let Some(dir) = team_library {
+        library_dirs.push((Source::Team, dir));
+    }
+    let dir = data_dir()
+        .ok_or(LoadError::DirectoryError)?
+        .join("workouts");
+    fs::create_dir_all(&dir).map_err(|_| LoadError::DirectoryError)?;
+    library_dirs.push((Source::User, dir));
+    Ok(library_dirs)
+}
+
+// Stamps of the library files, compared with the stamps from when the library
+// was last loaded to tell if it needs reloading.
+async fn check_library(library_dirs: Vec<(Source, PathBuf)>) -> Vec<FileStamp> {
+    let dirs: Vec<PathBuf> = library_dirs.into_iter().map(|(_, dir)| dir).collect();
+    library::file_stamps(&dirs)
+}
+
+// A directory for the files of a test. It starts out empty and is removed
+// when the test ends, whether or not the test passed.
+#[cfg(test)]
+struct TestDir(PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("2by20-{}-test-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,128 @@
+// An Activity is a finished ride. Activities are saved as JSON in
+// $HOME_DIR/Documents/2by20/activities with one file per ride.
+// TODO: Export activities as .fit files.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::application::compliance::WorkoutCompliance;
+use crate::application::profile_store::ProfileId;
+use crate::application::session::Record;
+use crate::application::stats::LapSummary;
+use crate::application::types::Weight;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Activity {
+    pub workout: String,
+    // Name of the profile that rode it when it was saved.
+    pub profile: String,
+    // ID of the profile that rode it. Rides saved before profiles had IDs
+    // don't have one and are matched to a profile by name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_id: Option<ProfileId>,
+    // Rider's weight at the time of the ride so W/kg stays right after the
+    // profile's weight changes. Rides saved before weights were recorded
+    // don't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<Weight>,
+    // Start of the ride as an RFC 3339 timestamp.
+    pub started: String,
+    // Seconds ridden, not counting time paused or segments skipped.
+    pub duration: u32,
+    // Start time of each lap in seconds.
+    pub laps: Vec<u32>,
+    // How closely each segment of the workout was ridden. Rides saved before
+    // compliance was scored don't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compliance: Option<WorkoutCompliance>,
+    pub records: Vec<Record>,
+}
+
+impl Activity {
+    // File name is the start time and workout name, i.e.
+    // 2021-10-18T06-30-00-Metcalfe.json
+    pub fn file_name(&self) -> String {
+        let started: String = self
+            .started
+            .chars()
+            .take(19)
+            .map(|c| if c == ':' { '-' } else { c })
+            .collect();
+        let workout: String = self
+            .workout
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("{}-{}.json", started, workout)
+    }
+
+    // Local date the ride started on.
+    pub fn date(&self) -> Option<chrono::NaiveDate> {
+        chrono::DateTime::parse_from_rfc3339(&self.started)
+            .ok()
+            .map(|started| started.naive_local().date())
+    }
+
+    // Summary of each lap against the FTP in effect on the day of the ride.
+    // Laps are worked out from the records rather than saved with the ride so
+    // they follow changes to the rider's FTP history.
+    pub fn lap_summaries(&self, ftp: u16) -> Vec<LapSummary> {
+        LapSummary::laps(&self.records, ftp)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, String> {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let path = dir.join(self.file_name());
+        let contents = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(&path, contents).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&contents).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::session::{RecordBuilder, Sample};
+    use crate::application::TestDir;
+
+    #[test]
+    fn test_activity_save_load() {
+        let activity = Activity {
+            workout: String::from("30/30s @ MAP"),
+            profile: String::from("Justina"),
+            profile_id: None,
+            weight: Weight::new(72.5, crate::application::types::WeightUnit::Kg).ok(),
+            started: String::from("2021-10-18T06:30:00-05:00"),
+            duration: 1,
+            laps: vec![0],
+            compliance: None,
+            records: RecordBuilder::default()
+                .lap(
+                    1,
+                    Some(200),
+                    Sample {
+                        power: Some(198),
+                        heart_rate: None,
+                        cadence: Some(90),
+                    },
+                )
+                .build(),
+        };
+        assert_eq!(activity.date(), "2021-10-18".parse().ok());
+        assert_eq!(
+            activity.file_name(),
+            "2021-10-18T06-30-00-30_30s___MAP.json"
+        );
+
+        let dir = TestDir::new("activity");
+        let path = activity.save(&dir).unwrap();
+        let loaded = Activity::load(&path);
+        assert_eq!(loaded.unwrap(), activity);
+    }
+}
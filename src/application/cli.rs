@@ -0,0 +1,820 @@
+// Commands run from the command line without starting the GUI.
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+
+use serde_derive::Serialize;
+
+use crate::application::compliance::{Compliance, SegmentCompliance, SegmentStatus};
+use crate::application::history::History;
+use crate::application::library::{Library, ShadowLibrary, Source};
+use crate::application::power_curve::duration_label;
+use crate::application::profile_settings::RideMetric;
+use crate::application::profile_store::{ProfileId, ProfileStore};
+use crate::application::sensors::{Sensors, Simulated};
+use crate::application::session::{Sample, Session};
+use crate::application::stats::{LapSummary, Stats};
+use crate::application::training_load;
+use crate::application::types::{Duration, HeartRateTarget, Thresholds};
+use crate::application::user_profile::{save_profiles, UserProfile};
+use crate::application::workout::{Workout, WorkoutSegment};
+use crate::application::zones;
+use crate::application::{activities_dir, library_dirs, profiles_file, LibraryFiles};
+
+// Validates library files the same way the application loads them. All files
+// are validated together along with the built-in library so templates can
+// reference templates in other files. Returns the process exit code.
+pub fn validate(files: &[PathBuf]) -> i32 {
+    let errors = validate_files(files);
+    for e in &errors {
+        println!("{}", e);
+    }
+    if errors.is_empty() {
+        println!("{} file(s) are valid", files.len());
+        0
+    } else {
+        1
+    }
+}
+
+// Returns an error line for every file that can't be parsed and every
+// template that fails to validate.
+fn validate_files(files: &[PathBuf]) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut shadow_library = ShadowLibrary::default();
+    for file in files {
+        match ShadowLibrary::from_file(file) {
+            Ok(sl) => shadow_library.merge(sl),
+            Err(e) => errors.push(format!("{}: {}", file.display(), e)),
+        }
+    }
+
+    let mut library = Library::default();
+    for e in library.load(shadow_library, Source::User) {
+        let file = e.path.as_ref().map(|p| p.display().to_string());
+        errors.push(format!(
+            "{}: {}: {}",
+            file.unwrap_or_default(),
+            e.name,
+            e.error
+        ));
+    }
+    errors
+}
+
+// Loads the library the same way the application does. Anything that can't be
+// loaded is reported on stderr so the output can still be piped.
+fn load_library(team_library: Option<PathBuf>) -> Library {
+    let dirs = library_dirs(team_library).unwrap_or_else(|_| {
+        eprintln!("Unable to find the user library directory");
+        Vec::new()
+    });
+    let (library, diagnostics) = LibraryFiles::read(dirs).build();
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    library
+}
+
+#[derive(Serialize)]
+struct ListEntry {
+    name: String,
+    source: String,
+    duration: u32,
+    description: Option<String>,
+}
+
+#[derive(Serialize)]
+struct List {
+    intervals: Vec<ListEntry>,
+    workouts: Vec<ListEntry>,
+}
+
+// Prints the intervals and workouts in the library.
+pub fn list(team_library: Option<PathBuf>, json: bool) -> i32 {
+    let library = load_library(team_library);
+    let list = List {
+        intervals: library
+            .intervals
+            .iter()
+            .map(|(name, i)| ListEntry {
+                name: name.clone(),
+                source: i.source.to_string(),
+                duration: i.duration.0,
+                description: i.description.clone(),
+            })
+            .collect(),
+        workouts: library
+            .workouts
+            .iter()
+            .map(|(name, w)| ListEntry {
+                name: name.clone(),
+                source: w.source.to_string(),
+                duration: w.duration.0,
+                description: Some(w.description.clone()),
+            })
+            .collect(),
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&list).unwrap());
+        return 0;
+    }
+    for (title, entries) in [("Intervals", &list.intervals), ("Workouts", &list.workouts)].iter() {
+        println!("{}", title);
+        let rows = entries
+            .iter()
+            .map(|e| {
+                vec![
+                    e.name.clone(),
+                    e.source.clone(),
+                    Duration(e.duration).to_string(),
+                    e.description.clone().unwrap_or_default(),
+                ]
+            })
+            .collect();
+        print_table(&["NAME", "SOURCE", "DURATION", "DESCRIPTION"], rows);
+        println!();
+    }
+    0
+}
+
+#[derive(Serialize)]
+struct Second {
+    second: u32,
+    watts: Option<u16>,
+    lap: Option<usize>,
+}
+
+// Prints a workout expanded for the given FTP and LTHR. Percentage targets
+// don't have watts or bpm without them. With seconds, the target for every
+// second of the workout is printed instead of each segment.
+pub fn show(
+    name: &str,
+    team_library: Option<PathBuf>,
+    ftp: Option<u16>,
+    lthr: Option<u8>,
+    json: bool,
+    seconds: bool,
+) -> i32 {
+    let library = load_library(team_library);
+    let template = match library.workout(name) {
+        Some(template) => template,
+        None => {
+            eprintln!("Workout {} can't be found", name);
+            return 1;
+        }
+    };
+    let thresholds = Thresholds {
+        ftp: ftp.unwrap_or(0),
+        lthr,
+        ..Default::default()
+    };
+    let workout = Workout::new(template, &thresholds);
+
+    if seconds {
+        let targets: Vec<Second> = (0..workout.duration)
+            .map(|second| Second {
+                second,
+                watts: workout.watts_at(second),
+                lap: workout.lap_at(second),
+            })
+            .collect();
+        if json {
+            println!("{}", serde_json::to_string_pretty(&targets).unwrap());
+        } else {
+            let rows = targets
+                .iter()
+                .map(|t| {
+                    vec![
+                        t.second.to_string(),
+                        optional(t.watts),
+                        optional(t.lap.map(|lap| lap + 1)),
+                    ]
+                })
+                .collect();
+            print_table(&["SECOND", "WATTS", "LAP"], rows);
+        }
+        return 0;
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&workout).unwrap());
+        return 0;
+    }
+
+    println!(
+        "{} ({}) {}",
+        workout.name,
+        template.source,
+        Duration(workout.duration)
+    );
+    println!("{}", workout.description);
+    println!();
+    let rows = workout.segments.iter().map(segment_row).collect();
+    print_table(
+        &[
+            "LAP", "START", "DURATION", "INTERVAL", "TARGET", "WATTS", "CADENCE", "HR", "RPE",
+        ],
+        rows,
+    );
+    0
+}
+
+// Rides a workout in the terminal. A line with the target and what the sensors
+// are reading is updated every second. Typing p and enter pauses or resumes
+// the ride and q and enter ends it early. The ride is saved to the activities
+// directory when it ends. After an FTP test the rider is asked whether to
+// update the profile's FTP with the result. Only simulated sensors can be
+// ridden until ANT+ sensors are supported.
+pub fn ride(name: &str, profile: &str, team_library: Option<PathBuf>, simulate: bool) -> i32 {
+    let library = load_library(team_library);
+    let template = match library.workout(name) {
+        Some(template) => template,
+        None => {
+            eprintln!("Workout {} can't be found", name);
+            return 1;
+        }
+    };
+    let (mut profiles, id, profile) = match find_profile(profile) {
+        Ok(found) => found,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let mut sensors: Box<dyn Sensors> = match simulate {
+        true => Box::new(Simulated::new(profile.ftp / 2)),
+        false => {
+            eprintln!(
+                "ANT+ sensors aren't supported yet. Ride with --simulate to use simulated sensors"
+            );
+            return 1;
+        }
+    };
+
+    let mut session = Session::new(Workout::new(template, &profile.thresholds()));
+    session.set_erg(profile.settings.erg_mode);
+    println!(
+        "Riding {} ({}) as {}. Type p and enter to pause or resume, l and enter to start a lap, e and enter to switch ERG mode on or off, n and enter to skip to the next segment, q and enter to stop.",
+        session.workout.name,
+        Duration(session.workout.duration),
+        profile.name
+    );
+    let commands = ride_commands();
+    let mut next = Instant::now();
+    let mut lap = session.lap();
+    let mut segment = session.segment();
+    while !session.is_finished() {
+        match commands.try_recv().as_deref() {
+            Ok("p") => session.toggle_pause(),
+            Ok("l") => session.new_lap(),
+            Ok("e") => session.set_erg(!session.is_erg()),
+            Ok("n") => session.skip_segment(),
+            Ok("q") => break,
+            _ => {}
+        }
+        let target = session.target();
+        let sample = session.step(sensors.as_mut());
+        // Each lap is summarised on its own line as it ends.
+        if session.lap() != lap {
+            if let Some(summary) = session.lap_summary(lap) {
+                println!("\r{}", lap_line(&summary));
+            }
+            lap = session.lap();
+        }
+        // Segments with a target are scored as they end.
+        if session.segment() != segment {
+            if let Some(i) = segment {
+                let compliance = session.segment_compliance(i);
+                if compliance.compliance.is_some() {
+                    println!("\r{}", segment_line(&compliance));
+                }
+            }
+            segment = session.segment();
+        }
+        print!("\r{}", readout(&session, &profile, target, sample));
+        io::stdout().flush().ok();
+        next += std::time::Duration::from_secs(1);
+        thread::sleep(next.saturating_duration_since(Instant::now()));
+    }
+    println!();
+
+    let ftp_test = session.workout.ftp_test;
+    let activity = session.finish(&profile);
+    if activity.records.is_empty() {
+        return 0;
+    }
+    let power = activity.records.iter().map(|r| r.sample.power);
+    let ftp = activity
+        .date()
+        .map_or(profile.ftp, |date| profile.ftp_on(date));
+    let time_in_zone = profile.power_zones.time_in_zone(power, ftp);
+    let rows = profile
+        .power_zones
+        .zones()
+        .iter()
+        .zip(time_in_zone)
+        .enumerate()
+        .map(|(i, (zone, seconds))| {
+            vec![
+                format!("Z{}", i + 1),
+                zone.name.clone(),
+                Duration(seconds).to_string(),
+            ]
+        })
+        .collect();
+    print_table(&["ZONE", "NAME", "TIME"], rows);
+    println!();
+    let overall = activity.compliance.as_ref().and_then(|c| c.overall);
+    let mut rows = vec![stats_row(
+        String::from("Ride"),
+        &Stats::new(&activity.records, ftp),
+        None,
+        overall.as_ref(),
+    )];
+    for lap in activity.lap_summaries(ftp) {
+        rows.push(stats_row(
+            format!("Lap {}", lap.lap + 1),
+            &lap.stats,
+            Some(&lap),
+            lap.compliance.as_ref(),
+        ));
+    }
+    print_table(
+        &[
+            "",
+            "TIME",
+            "AVG",
+            "MAX",
+            "NP",
+            "IF",
+            "TSS",
+            "KJ",
+            "VI",
+            "HR",
+            "MAX HR",
+            "CADENCE",
+            "TARGET",
+            "OF TARGET",
+            "IN BAND",
+            "OFF BY",
+        ],
+        rows,
+    );
+    if let Some(compliance) = &activity.compliance {
+        for segment in compliance.incomplete() {
+            println!("{}", segment_line(segment));
+        }
+    }
+    if let (Some(max_hr), Some(resting_hr)) = (profile.max_hr, profile.resting_hr) {
+        let heart_rate = activity.records.iter().map(|r| r.sample.heart_rate);
+        if let Some(trimp) = zones::trimp(heart_rate, max_hr, resting_hr) {
+            println!("TRIMP {:.0}", trimp);
+        }
+    }
+    let dir = match activities_dir() {
+        Some(dir) => dir,
+        None => {
+            eprintln!("Unable to save ride: no home directory");
+            return 1;
+        }
+    };
+    let code = match activity.save(&dir) {
+        Ok(path) => {
+            println!("Saved ride to {}", path.display());
+            // Opening the history adds the ride to the index.
+            let (history, errors) = History::open(&dir, &profiles.to_profiles());
+            for e in errors {
+                eprintln!("{}", e);
+            }
+            if let Some(entry) = history.entry(&activity.file_name()) {
+                for (seconds, watts) in history.personal_records(entry) {
+                    println!("New {} best! {}W", duration_label(seconds), watts);
+                }
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Unable to save ride: {}", e);
+            1
+        }
+    };
+
+    let (test, ftp) = match ftp_test.and_then(|t| t.ftp(&activity.records).map(|ftp| (t, ftp))) {
+        Some(result) => result,
+        None => return code,
+    };
+    println!(
+        "FTP test result is {}W, {}'s FTP is {}W. Type y and enter to update it.",
+        ftp, profile.name, profile.ftp
+    );
+    if commands.recv().as_deref() != Ok("y") {
+        return code;
+    }
+    let date = activity
+        .date()
+        .unwrap_or_else(|| chrono::Local::now().naive_local().date());
+    let mut updated = profile.clone();
+    updated.set_ftp(ftp, date, test.source());
+    if let Err(e) = profiles.update(id, updated) {
+        eprintln!("Unable to update profile: {}", e);
+        return 1;
+    }
+    match profiles_file().map(|path| save_profiles(&path, &profiles.to_profiles())) {
+        Some(Ok(())) => {
+            println!("Updated {}'s FTP to {}W", profile.name, ftp);
+            code
+        }
+        Some(Err(e)) => {
+            eprintln!("Unable to save profiles: {}", e);
+            1
+        }
+        None => 1,
+    }
+}
+
+// Prints fitness (CTL), fatigue (ATL) and form (TSB) for the last number of
+// days. The workouts the profile has planned, and any given as DATE=WORKOUT,
+// are added at the TSS of the workout ridden on target to project load
+// forward.
+pub fn load(profile: &str, team_library: Option<PathBuf>, days: u32, plan: &[&str]) -> i32 {
+    let (profiles, _, profile) = match find_profile(profile) {
+        Ok(found) => found,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let library = load_library(team_library);
+    let today = chrono::Local::now().naive_local().date();
+    let mut planned = training_load::planned_tss(&profile, &library, today);
+    for entry in plan {
+        let (date, workout) = match entry.split_once('=') {
+            Some((date, workout)) => (date.parse::<chrono::NaiveDate>(), workout),
+            None => {
+                eprintln!("Planned workouts are DATE=WORKOUT, not {}", entry);
+                return 1;
+            }
+        };
+        let date = match date {
+            Ok(date) if date > today => date,
+            Ok(_) => {
+                eprintln!("Planned workouts have to be after today, not {}", entry);
+                return 1;
+            }
+            Err(e) => {
+                eprintln!("Unable to read date in {}: {}", entry, e);
+                return 1;
+            }
+        };
+        let template = match library.workout(workout) {
+            Some(template) => template,
+            None => {
+                eprintln!("Workout {} can't be found", workout);
+                return 1;
+            }
+        };
+        let workout = Workout::new(template, &profile.thresholds());
+        planned.push((date, workout.tss(profile.ftp).unwrap_or(0.0)));
+    }
+
+    let (history, errors) = match activities_dir() {
+        Some(dir) => History::open(&dir, &profiles.to_profiles()),
+        None => (History::default(), Vec::new()),
+    };
+    for e in errors {
+        eprintln!("{}", e);
+    }
+    let start = today - chrono::Duration::days(i64::from(days) - 1);
+    let end = planned
+        .iter()
+        .map(|(date, _)| *date)
+        .max()
+        .unwrap_or(today)
+        .max(today);
+    let mut tss = history.daily_tss(&profile);
+    tss.extend(planned.iter());
+    let rows = training_load::daily_load(&tss, start, end)
+        .iter()
+        .map(|day| {
+            vec![
+                day.date.to_string(),
+                format!("{:.0}", day.tss),
+                format!("{:.0}", day.ctl),
+                format!("{:.0}", day.atl),
+                format!("{:.0}", day.tsb),
+                match planned.iter().any(|(date, _)| *date == day.date) {
+                    true => String::from("planned"),
+                    false => String::new(),
+                },
+            ]
+        })
+        .collect();
+    print_table(&["DATE", "TSS", "CTL", "ATL", "TSB", ""], rows);
+    0
+}
+
+// Loads the profiles and finds a profile by name.
+fn find_profile(name: &str) -> Result<(ProfileStore, ProfileId, UserProfile), String> {
+    let profiles = match profiles_file().map(|path| ProfileStore::load(&path)) {
+        Some(Ok(profiles)) => profiles,
+        Some(Err(e)) => return Err(format!("Unable to load profiles: {}", e)),
+        None => ProfileStore::default(),
+    };
+    let found = profiles
+        .find(name)
+        .and_then(|id| Some((id, profiles.get(id)?.clone())));
+    match found {
+        Some((id, profile)) => Ok((profiles, id, profile)),
+        None => Err(format!("Profile {} can't be found", name)),
+    }
+}
+
+// Lines typed while riding, read on their own thread so the ride doesn't wait
+// on input.
+fn ride_commands() -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line.trim().to_lowercase(),
+                Err(_) => break,
+            };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+// 12:34  47:26 left  lap 2  target 213W  power 210W  hr 142  cadence 91
+//
+// Only the metrics in the profile's settings are shown, in the order they're
+// listed. Power is averaged over the profile's power smoothing.
+fn readout(
+    session: &Session,
+    profile: &UserProfile,
+    target: Option<u16>,
+    sample: Sample,
+) -> String {
+    let watts = |w: Option<u16>| w.map_or_else(|| String::from("-"), |w| format!("{}W", w));
+    let power = session.smoothed_power(profile.settings.power_smoothing.seconds());
+    let metrics: Vec<String> = profile
+        .settings
+        .ride_metrics
+        .iter()
+        .map(|metric| match metric {
+            RideMetric::Elapsed => format!("{:>7}", Duration(session.elapsed()).to_string()),
+            RideMetric::Remaining => {
+                format!("{:>7} left", Duration(session.remaining()).to_string())
+            }
+            RideMetric::Lap => format!("lap {:<3}", session.lap() + 1),
+            RideMetric::LapPower => format!(
+                "lap avg {:<6}",
+                watts(
+                    session
+                        .lap_summary(session.lap())
+                        .and_then(|lap| lap.stats.average_power)
+                )
+            ),
+            RideMetric::Target => format!("target {:<6}", watts(target)),
+            RideMetric::Power => format!("power {:<6}", watts(power)),
+            RideMetric::WattsPerKg => format!(
+                "{:<4} W/kg",
+                optional(
+                    power
+                        .and_then(|p| profile.watts_per_kg(p))
+                        .map(|w| format!("{:.1}", w))
+                )
+            ),
+            RideMetric::HeartRate => format!("hr {:<4}", optional(sample.heart_rate)),
+            RideMetric::Cadence => format!("cadence {:<4}", optional(sample.cadence)),
+            RideMetric::WPrimeBalance => format!(
+                "W'bal {:<6}",
+                optional(
+                    session
+                        .w_prime_balance(profile.cp, profile.w_prime)
+                        .map(|joules| format!("{:.1}kJ", joules / 1000.0))
+                )
+            ),
+        })
+        .collect();
+    let status = match (session.is_paused(), session.is_erg()) {
+        (true, _) => "  PAUSED",
+        (false, false) => "  ERG OFF",
+        (false, true) => "         ",
+    };
+    format!("{}{}", metrics.join("  "), status)
+}
+
+// Lap 2  5:00  avg 312W  max 340W  np 315W  hr 168  cadence 98  target 310W 101%  in band 88%
+fn lap_line(summary: &LapSummary) -> String {
+    let watts = |w: Option<u16>| w.map_or_else(|| String::from("-"), |w| format!("{}W", w));
+    let target = match (summary.average_target, summary.target_percentage()) {
+        (Some(target), Some(percentage)) => format!("  target {}W {:.0}%", target, percentage),
+        _ => String::new(),
+    };
+    let in_band = summary
+        .compliance
+        .map_or_else(String::new, |c| format!("  in band {:.0}%", c.in_band));
+    format!(
+        "Lap {}  {}  avg {}  max {}  np {}  hr {}  cadence {}{}{}",
+        summary.lap + 1,
+        Duration(summary.stats.duration),
+        watts(summary.stats.average_power),
+        watts(summary.stats.max_power),
+        watts(summary.stats.normalized_power),
+        optional(summary.stats.average_heart_rate),
+        optional(summary.stats.average_cadence),
+        target,
+        in_band
+    )
+}
+
+// Segment 3  4:00  92% in band  off by 6W
+// Segment 4  0:30 of 4:00 cut short  75% in band  off by 9W
+// Segment 5  skipped
+fn segment_line(segment: &SegmentCompliance) -> String {
+    let ridden = match segment.status {
+        SegmentStatus::Completed => Duration(segment.duration).to_string(),
+        SegmentStatus::CutShort => format!(
+            "{} of {} cut short",
+            Duration(segment.ridden),
+            Duration(segment.duration)
+        ),
+        SegmentStatus::Skipped => String::from("skipped"),
+    };
+    let compliance = segment.compliance.map_or_else(String::new, |c| {
+        format!(
+            "  {:.0}% in band  off by {:.0}W",
+            c.in_band, c.average_deviation
+        )
+    });
+    format!("Segment {}  {}{}", segment.segment + 1, ridden, compliance)
+}
+
+// Target and power against the target come from the lap summary, the whole
+// ride doesn't have them.
+fn stats_row(
+    label: String,
+    stats: &Stats,
+    lap: Option<&LapSummary>,
+    compliance: Option<&Compliance>,
+) -> Vec<String> {
+    let decimal = |value: Option<f32>, places: usize| {
+        value.map_or_else(|| String::from("-"), |v| format!("{:.*}", places, v))
+    };
+    vec![
+        label,
+        Duration(stats.duration).to_string(),
+        optional(stats.average_power),
+        optional(stats.max_power),
+        optional(stats.normalized_power),
+        decimal(stats.intensity_factor, 2),
+        decimal(stats.tss, 0),
+        decimal(stats.work, 0),
+        decimal(stats.variability_index, 2),
+        optional(stats.average_heart_rate),
+        optional(stats.max_heart_rate),
+        optional(stats.average_cadence),
+        optional(lap.and_then(|lap| lap.average_target)),
+        optional(
+            lap.and_then(|lap| lap.target_percentage())
+                .map(|percentage| format!("{:.0}%", percentage)),
+        ),
+        optional(compliance.map(|c| format!("{:.0}%", c.in_band))),
+        optional(compliance.map(|c| format!("{:.0}W", c.average_deviation))),
+    ]
+}
+
+fn segment_row(segment: &WorkoutSegment) -> Vec<String> {
+    let ramp = |start: String, end: String| match start == end {
+        true => start,
+        false => format!("{} > {}", start, end),
+    };
+    vec![
+        (segment.lap + 1).to_string(),
+        Duration(segment.start).to_string(),
+        Duration(segment.duration).to_string(),
+        segment.interval.clone().unwrap_or_default(),
+        ramp(
+            segment.power_start.to_string(),
+            segment.power_end.to_string(),
+        ),
+        ramp(optional(segment.watts_start), optional(segment.watts_end)),
+        optional(segment.cadence),
+        heart_rate(segment),
+        optional(segment.rpe.map(|rpe| rpe.0)),
+    ]
+}
+
+// Heart rate targets relative to LTHR also show the bpm they resolve to.
+fn heart_rate(segment: &WorkoutSegment) -> String {
+    match (segment.heart_rate, segment.heart_rate_bpm) {
+        (Some(HeartRateTarget::Lthr(_)), Some((bpm, _))) => {
+            format!("{} ({}bpm)", optional(segment.heart_rate), bpm)
+        }
+        (Some(HeartRateTarget::LthrRange(..)), Some((low, high))) => {
+            format!("{} ({}-{}bpm)", optional(segment.heart_rate), low, high)
+        }
+        (target, _) => optional(target),
+    }
+}
+
+fn optional<T: std::fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| String::from("-"), |v| v.to_string())
+}
+
+// Prints rows in columns padded to the widest value in each column.
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+    let print_row = |row: Vec<String>| {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+    print_row(headers.iter().map(|h| h.to_string()).collect());
+    for row in rows {
+        print_row(row);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::TestDir;
+    use std::fs;
+
+    #[test]
+    fn test_validate_files() {
+        let dir = TestDir::new("cli");
+        let intervals = dir.join("intervals.toml");
+        let workouts = dir.join("workouts.toml");
+        let broken = dir.join("broken.toml");
+        let duplicate = dir.join("duplicate.toml");
+        fs::write(
+            &intervals,
+            "[[ intervals ]]\nname = 'Openers'\nduration = '1m'\nsegments = ['1m @ 1.1']",
+        )
+        .unwrap();
+        fs::write(
+            &workouts,
+            r#"
+            [[ workouts ]]
+            name = "Openers"
+            description = "Openers after a warmup"
+            duration = "11m"
+            lap_each_interval = true
+            intervals = ['Warmup', 'Openers']
+
+            [[ workouts ]]
+            name = "Missing"
+            description = "Uses an interval that doesn't exist"
+            duration = "1m"
+            lap_each_interval = true
+            intervals = ['Missing']
+            "#,
+        )
+        .unwrap();
+        fs::write(&broken, "[[ intervals ]]\nname = ").unwrap();
+        // Redefines Openers, which is reported against this file rather than
+        // the first file defining it.
+        fs::write(
+            &duplicate,
+            "[[ intervals ]]\nname = 'Openers'\nduration = '2m'\nsegments = ['2m @ 1.1']",
+        )
+        .unwrap();
+
+        let valid = validate_files(std::slice::from_ref(&intervals));
+        let errors = validate_files(&[
+            intervals,
+            workouts.clone(),
+            broken.clone(),
+            duplicate.clone(),
+        ]);
+        assert!(valid.is_empty());
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with(&format!("{}: ", broken.display())));
+        assert_eq!(
+            errors[1],
+            format!(
+                "{}: Openers: interval with the same name has already been loaded",
+                duplicate.display()
+            )
+        );
+        assert_eq!(
+            errors[2],
+            format!("{}: Missing: interval can't be found", workouts.display())
+        );
+    }
+}
@@ -0,0 +1,216 @@
+// Compliance scores how closely a workout was ridden to its targets. Each
+// second with a target and a power reading is in the band when power is
+// within the range of a range target, or within 5% of a single target (5W for
+// low targets). Seconds where the power meter dropped out aren't scored, and
+// free ride seconds don't have a target to score against.
+//
+// Segments are scored on their own as well as the whole workout, and any
+// segment ridden for less than its duration is flagged as cut short, or
+// skipped when none of it was ridden.
+use serde_derive::{Deserialize, Serialize};
+
+use crate::application::session::Record;
+use crate::application::workout::Workout;
+
+const TOLERANCE: f32 = 0.05;
+const MIN_TOLERANCE_WATTS: f32 = 5.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Compliance {
+    // Seconds scored.
+    pub seconds: u32,
+    // Percentage of the seconds scored that were in the band.
+    pub in_band: f32,
+    // Average watts power was off the target by, over or under. Power inside
+    // a range target isn't off the target.
+    pub average_deviation: f32,
+}
+
+impl Compliance {
+    // None when there are no seconds to score.
+    pub fn new(records: &[Record]) -> Option<Self> {
+        // (low, high, tolerance, power)
+        let scored: Vec<(f32, f32, f32, f32)> = records
+            .iter()
+            .filter_map(|r| {
+                let target = r.target?;
+                let (low, high) = r.band.unwrap_or((target, target));
+                let tolerance = match r.band {
+                    Some(_) => 0.0,
+                    None => (f32::from(target) * TOLERANCE).max(MIN_TOLERANCE_WATTS),
+                };
+                Some((
+                    f32::from(low),
+                    f32::from(high),
+                    tolerance,
+                    f32::from(r.sample.power?),
+                ))
+            })
+            .collect();
+        if scored.is_empty() {
+            return None;
+        }
+        let off = |low: f32, high: f32, power: f32| (low - power).max(power - high).max(0.0);
+        let in_band = scored
+            .iter()
+            .filter(|(low, high, tolerance, power)| off(*low, *high, *power) <= *tolerance)
+            .count();
+        let deviation: f32 = scored
+            .iter()
+            .map(|(low, high, _, power)| off(*low, *high, *power))
+            .sum();
+        Some(Self {
+            seconds: scored.len() as u32,
+            in_band: (in_band * 100) as f32 / scored.len() as f32,
+            average_deviation: deviation / scored.len() as f32,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentStatus {
+    Completed,
+    CutShort,
+    Skipped,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SegmentCompliance {
+    // Index of the segment in the workout.
+    pub segment: usize,
+    pub duration: u32,
+    // Seconds of the segment ridden.
+    pub ridden: u32,
+    pub status: SegmentStatus,
+    pub compliance: Option<Compliance>,
+}
+
+impl SegmentCompliance {
+    // Scores a segment of the workout from the records of the ride.
+    pub fn new(workout: &Workout, segment: usize, records: &[Record]) -> Self {
+        let s = &workout.segments[segment];
+        let ridden: Vec<Record> = records
+            .iter()
+            .filter(|r| s.start <= r.elapsed && r.elapsed < s.end())
+            .copied()
+            .collect();
+        let status = match ridden.len() as u32 {
+            n if n >= s.duration => SegmentStatus::Completed,
+            0 => SegmentStatus::Skipped,
+            _ => SegmentStatus::CutShort,
+        };
+        Self {
+            segment,
+            duration: s.duration,
+            ridden: ridden.len() as u32,
+            status,
+            compliance: Compliance::new(&ridden),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct WorkoutCompliance {
+    pub overall: Option<Compliance>,
+    pub segments: Vec<SegmentCompliance>,
+}
+
+impl WorkoutCompliance {
+    pub fn new(workout: &Workout, records: &[Record]) -> Self {
+        Self {
+            overall: Compliance::new(records),
+            segments: (0..workout.segments.len())
+                .map(|i| SegmentCompliance::new(workout, i, records))
+                .collect(),
+        }
+    }
+
+    // Segments cut short or skipped.
+    pub fn incomplete(&self) -> impl Iterator<Item = &SegmentCompliance> {
+        self.segments
+            .iter()
+            .filter(|s| s.status != SegmentStatus::Completed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::library::Library;
+    use crate::application::session::RecordBuilder;
+    use crate::application::types::Thresholds;
+
+    #[test]
+    fn test_compliance() {
+        let records = RecordBuilder::default()
+            .power(1, Some(200), Some(200))
+            .power(1, Some(200), Some(210))
+            .power(1, Some(200), Some(211))
+            .power(1, Some(200), Some(170))
+            // Dropouts and free riding aren't scored.
+            .power(1, Some(200), None)
+            .power(1, None, Some(300))
+            // Low targets have a band of at least 5W.
+            .power(1, Some(60), Some(65))
+            .build();
+        let compliance = Compliance::new(&records).unwrap();
+        assert_eq!(compliance.seconds, 5);
+        assert_eq!(compliance.in_band, 60.0);
+        assert_eq!(
+            compliance.average_deviation,
+            (0.0 + 10.0 + 11.0 + 30.0 + 5.0) / 5.0
+        );
+        assert_eq!(Compliance::new(&records[4..6]), None);
+    }
+
+    #[test]
+    fn test_compliance_range() {
+        // Sweet spot at 220-235W is scored against the range rather than 5%
+        // around the middle of it.
+        let mut records = RecordBuilder::default()
+            .power(1, Some(228), Some(220))
+            .power(1, Some(228), Some(235))
+            .power(1, Some(228), Some(216))
+            .power(1, Some(228), Some(240))
+            .build();
+        for r in records.iter_mut() {
+            r.band = Some((220, 235));
+        }
+        let compliance = Compliance::new(&records).unwrap();
+        assert_eq!(compliance.in_band, 50.0);
+        assert_eq!(compliance.average_deviation, (4.0 + 5.0) / 4.0);
+    }
+
+    #[test]
+    fn test_workout_compliance() {
+        let library = Library::default();
+        let thresholds = Thresholds {
+            ftp: 250,
+            ..Default::default()
+        };
+        // Metcalfe's warmup is 5 segments over the first 10 minutes.
+        let workout = Workout::new(&library.workouts["Metcalfe"], &thresholds);
+        let first = &workout.segments[0];
+        let second = &workout.segments[1];
+        // The first segment ridden on target, the second cut short 30
+        // seconds in and the rest not ridden.
+        let records = (0..first.end() + 30)
+            .fold(RecordBuilder::default(), |records, elapsed| {
+                let target = workout.watts_at(elapsed);
+                records.power(1, target, target)
+            })
+            .build();
+        let compliance = WorkoutCompliance::new(&workout, &records);
+        assert_eq!(compliance.segments.len(), workout.segments.len());
+        assert_eq!(compliance.segments[0].status, SegmentStatus::Completed);
+        assert_eq!(compliance.segments[0].compliance.unwrap().in_band, 100.0);
+        assert_eq!(compliance.segments[1].status, SegmentStatus::CutShort);
+        assert_eq!(compliance.segments[1].ridden, 30);
+        assert_eq!(compliance.segments[1].duration, second.duration);
+        assert_eq!(compliance.segments[2].status, SegmentStatus::Skipped);
+        assert_eq!(compliance.segments[2].compliance, None);
+        assert_eq!(compliance.incomplete().count(), workout.segments.len() - 1);
+        assert_eq!(compliance.overall.unwrap().average_deviation, 0.0);
+    }
+}
@@ -0,0 +1,125 @@
+// FTP tests are workouts with a protocol for working out FTP from the power
+// ridden. A workout is marked as an FTP test in the library with ftp_test.
+//
+// [[ workouts ]]
+// name = "Ramp Test"
+// ftp_test = "ramp"
+//
+// The ramp test keeps stepping up until the rider can't hold the target, so
+// it ends on failure rather than when the workout runs out. The 20 and 8
+// minute tests are ridden all out to the end.
+use serde_derive::{Deserialize, Serialize};
+
+use crate::application::power_curve::best_average;
+use crate::application::session::Record;
+use crate::application::user_profile::FtpSource;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FtpTest {
+    Ramp,
+    TwentyMinute,
+    EightMinute,
+}
+
+// Seconds in a row the rider has to be under half the target for the ramp
+// test to end.
+const RAMP_FAILURE_SECONDS: usize = 15;
+
+impl FtpTest {
+    pub fn source(self) -> FtpSource {
+        match self {
+            Self::Ramp => FtpSource::RampTest,
+            Self::TwentyMinute => FtpSource::TwentyMinuteTest,
+            Self::EightMinute => FtpSource::EightMinuteTest,
+        }
+    }
+
+    // Seconds of the best effort the FTP is taken from and the percentage of
+    // it that's FTP. 75% of the best minute for the ramp test, 95% of the
+    // best 20 minutes for the 20 minute test and 90% of the best 8 minutes
+    // for the 8 minute test.
+    fn protocol(self) -> (usize, f32) {
+        match self {
+            Self::Ramp => (60, 0.75),
+            Self::TwentyMinute => (20 * 60, 0.95),
+            Self::EightMinute => (8 * 60, 0.9),
+        }
+    }
+
+    // FTP from the power ridden in the test. None if the test wasn't ridden
+    // long enough.
+    pub fn ftp(self, records: &[Record]) -> Option<u16> {
+        let (seconds, percentage) = self.protocol();
+        let power: Vec<Option<u16>> = records.iter().map(|r| r.sample.power).collect();
+        best_average(&power, seconds).map(|watts| (watts * percentage).round() as u16)
+    }
+
+    // Intensity the all out efforts of the test are expected to be ridden at
+    // as a fraction of FTP, i.e. 105% for the 20 minute test.
+    pub fn effort_intensity(self) -> f32 {
+        1.0 / self.protocol().1
+    }
+
+    // The ramp test has failed once the rider has been under half of the
+    // target for 15 seconds in a row. The warmup in the first lap doesn't
+    // count. Other tests never fail.
+    pub fn is_failed(self, records: &[Record]) -> bool {
+        if self != Self::Ramp || records.len() < RAMP_FAILURE_SECONDS {
+            return false;
+        }
+        records[records.len() - RAMP_FAILURE_SECONDS..]
+            .iter()
+            .all(|r| match r.target {
+                Some(target) if r.lap > 0 => r.sample.power.unwrap_or(0) * 2 < target,
+                _ => false,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::session::RecordBuilder;
+
+    #[test]
+    fn test_ftp_test_results() {
+        let ramp = RecordBuilder::default()
+            .power(600, Some(100), Some(100))
+            .power(60, Some(380), Some(380))
+            .build();
+        assert_eq!(FtpTest::Ramp.ftp(&ramp), Some(285));
+        let twenty = RecordBuilder::default()
+            .power(600, Some(100), Some(100))
+            .power(1200, Some(300), Some(300))
+            .build();
+        assert_eq!(FtpTest::TwentyMinute.ftp(&twenty), Some(285));
+        let eight = RecordBuilder::default()
+            .power(480, Some(300), Some(320))
+            .power(480, Some(300), Some(300))
+            .build();
+        assert_eq!(FtpTest::EightMinute.ftp(&eight), Some(288));
+        assert_eq!(FtpTest::TwentyMinute.ftp(&ramp), None);
+    }
+
+    #[test]
+    fn test_ramp_test_failure() {
+        // Coasting in the warmup doesn't end the test.
+        let warmup = RecordBuilder::default()
+            .power(20, Some(100), Some(0))
+            .build();
+        assert!(!FtpTest::Ramp.is_failed(&warmup));
+        let holding = RecordBuilder::default()
+            .power(600, Some(100), Some(100))
+            .power(60, Some(380), Some(370))
+            .build();
+        assert!(!FtpTest::Ramp.is_failed(&holding));
+        let failed = RecordBuilder::default()
+            .power(600, Some(100), Some(100))
+            .power(14, Some(400), Some(150))
+            .power(1, Some(400), None)
+            .build();
+        assert!(FtpTest::Ramp.is_failed(&failed));
+        assert!(!FtpTest::TwentyMinute.is_failed(&failed));
+    }
+}
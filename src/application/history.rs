@@ -0,0 +1,454 @@
+// History is the index of rides saved in the activities directory. Each ride
+// is saved in its own file and index.json keeps a summary of every ride so
+// rides can be listed without reading every file. The index is brought up to
+// date with the directory when it's opened, so rides saved before there was
+// an index or copied in from another computer are picked up, and rides whose
+// file was deleted are dropped. Rides are kept by the ID of the profile that
+// rode them so they stay with a profile that's renamed. Rides saved before
+// profiles had IDs are given the ID of the profile with their rider's name
+// when they're indexed.
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::application::activity::Activity;
+use crate::application::power_curve::PowerCurve;
+use crate::application::profile_store::ProfileId;
+use crate::application::stats::Stats;
+use crate::application::user_profile::UserProfile;
+
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    // File name of the ride in the activities directory.
+    pub file: String,
+    // Name of the profile when the ride was saved.
+    pub profile: String,
+    // None when the ride was saved before profiles had IDs and no profile had
+    // its rider's name when it was indexed.
+    #[serde(default)]
+    pub profile_id: Option<ProfileId>,
+    pub workout: String,
+    // Start of the ride as an RFC 3339 timestamp.
+    pub started: String,
+    pub duration: u32,
+    // FTP in effect on the day of the ride. Rides by a profile that no
+    // longer exists don't have one.
+    pub ftp: Option<u16>,
+    pub average_power: Option<u16>,
+    pub normalized_power: Option<u16>,
+    pub tss: Option<f32>,
+    // Rides indexed before power curves were kept don't have one until
+    // they're indexed again.
+    #[serde(default)]
+    pub power_curve: Option<PowerCurve>,
+    // Percentage of the ride in the band around the target. Free rides and
+    // rides saved before compliance was scored don't have one.
+    #[serde(default)]
+    pub in_band: Option<f32>,
+}
+
+impl HistoryEntry {
+    pub fn new(file: String, activity: &Activity, ftp: Option<u16>) -> Self {
+        let stats = Stats::new(&activity.records, ftp.unwrap_or(0));
+        Self {
+            file,
+            profile: activity.profile.clone(),
+            profile_id: activity.profile_id,
+            workout: activity.workout.clone(),
+            started: activity.started.clone(),
+            duration: activity.duration,
+            ftp,
+            average_power: stats.average_power,
+            normalized_power: stats.normalized_power,
+            tss: stats.tss,
+            power_curve: Some(PowerCurve::new(&activity.records)),
+            in_band: activity
+                .compliance
+                .as_ref()
+                .and_then(|compliance| compliance.overall)
+                .map(|overall| overall.in_band),
+        }
+    }
+
+    // Local date the ride started on.
+    pub fn date(&self) -> Option<chrono::NaiveDate> {
+        chrono::DateTime::parse_from_rfc3339(&self.started)
+            .ok()
+            .map(|started| started.naive_local().date())
+    }
+
+    // Local date and time the ride started, i.e. 2021-10-18 06:30.
+    pub fn started_at(&self) -> String {
+        chrono::DateTime::parse_from_rfc3339(&self.started)
+            .map(|started| started.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|_| self.started.clone())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    dir: PathBuf,
+    // Newest ride first.
+    entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    // Opens the history in the activities directory. Rides missing from the
+    // index are added using the FTP their rider had on the day, and the index
+    // is saved if anything changed. Rides that can't be read are left out and
+    // returned as errors.
+    pub fn open(dir: &Path, profiles: &[UserProfile]) -> (Self, Vec<String>) {
+        let mut errors = Vec::new();
+        let index = dir.join(INDEX_FILE);
+        let mut entries: Vec<HistoryEntry> = match fs::read_to_string(&index) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                errors.push(format!("Rebuilding {}: {}", index.display(), e));
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+
+        let mut files = ride_files(dir);
+        let indexed = entries.len();
+        entries.retain(|entry| files.contains(&entry.file) && entry.power_curve.is_some());
+        let mut changed = entries.len() != indexed;
+        files.retain(|file| !entries.iter().any(|entry| entry.file == *file));
+        for file in files {
+            match Activity::load(&dir.join(&file)) {
+                Ok(activity) => {
+                    let rider = rider(&activity, profiles);
+                    let ftp = rider.map(|p| activity.date().map_or(p.ftp, |date| p.ftp_on(date)));
+                    let mut entry = HistoryEntry::new(file, &activity, ftp);
+                    entry.profile_id = entry.profile_id.or_else(|| rider?.id);
+                    entries.push(entry);
+                    changed = true;
+                }
+                Err(e) => errors.push(format!("Unable to read ride {}: {}", file, e)),
+            }
+        }
+        // Rides indexed before profiles had IDs.
+        for entry in entries.iter_mut().filter(|e| e.profile_id.is_none()) {
+            entry.profile_id = profiles
+                .iter()
+                .find(|p| p.name == entry.profile)
+                .and_then(|p| p.id);
+            changed |= entry.profile_id.is_some();
+        }
+        entries.sort_by_key(|entry| {
+            Reverse(chrono::DateTime::parse_from_rfc3339(&entry.started).ok())
+        });
+
+        let mut history = Self {
+            dir: dir.to_path_buf(),
+            entries,
+        };
+        let (rescored, rescore_errors) = history.rescore(profiles);
+        errors.extend(rescore_errors);
+        if changed || rescored {
+            if let Err(e) = history.save() {
+                errors.push(format!("Unable to save {}: {}", index.display(), e));
+            }
+        }
+        (history, errors)
+    }
+
+    // Rides by a profile, newest first. A profile that hasn't been added to
+    // the store yet has no rides.
+    pub fn entries(&self, profile: &UserProfile) -> impl Iterator<Item = &HistoryEntry> {
+        self.rides_by(profile.id)
+    }
+
+    fn rides_by(&self, id: Option<ProfileId>) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| id.is_some() && entry.profile_id == id)
+    }
+
+    pub fn entry(&self, file: &str) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|entry| entry.file == file)
+    }
+
+    // Best power for each duration across every ride by a profile.
+    pub fn power_curve(&self, profile: &UserProfile) -> PowerCurve {
+        best_power(self.entries(profile))
+    }
+
+    // TSS of each ride by a profile with the day it was ridden. Rides without
+    // a TSS are left out.
+    pub fn daily_tss(&self, profile: &UserProfile) -> Vec<(chrono::NaiveDate, f32)> {
+        self.entries(profile)
+            .filter_map(|entry| Some((entry.date()?, entry.tss?)))
+            .collect()
+    }
+
+    // Durations where a ride beat the best power of every earlier ride by the
+    // same profile.
+    pub fn personal_records(&self, entry: &HistoryEntry) -> Vec<(u32, u16)> {
+        let curve = match &entry.power_curve {
+            Some(curve) => curve,
+            None => return Vec::new(),
+        };
+        let started = chrono::DateTime::parse_from_rfc3339(&entry.started).ok();
+        let earlier = self.rides_by(entry.profile_id).filter(|e| {
+            e.file != entry.file && chrono::DateTime::parse_from_rfc3339(&e.started).ok() < started
+        });
+        curve.records(&best_power(earlier))
+    }
+
+    // Reads every record of a ride.
+    pub fn load(&self, entry: &HistoryEntry) -> Result<Activity, String> {
+        Activity::load(&self.dir.join(&entry.file))
+    }
+
+    // Brings the FTP and TSS of every ride up to date with the profiles after
+    // a profile's FTP history changed, saving the index if anything changed.
+    // Rides that can't be read are returned as errors.
+    pub fn update_profiles(&mut self, profiles: &[UserProfile]) -> Vec<String> {
+        let (changed, mut errors) = self.rescore(profiles);
+        if changed {
+            if let Err(e) = self.save() {
+                errors.push(format!("Unable to save {}: {}", INDEX_FILE, e));
+            }
+        }
+        errors
+    }
+
+    // Works out FTP and TSS again for rides whose rider had a different FTP on
+    // the day than the ride was indexed with, i.e. after an FTP is backdated.
+    // Rides by a profile that no longer exists are left as they are. Returns
+    // whether any ride changed along with rides that couldn't be read.
+    fn rescore(&mut self, profiles: &[UserProfile]) -> (bool, Vec<String>) {
+        let mut changed = false;
+        let mut errors = Vec::new();
+        for entry in self.entries.iter_mut() {
+            let profile = match profiles
+                .iter()
+                .find(|p| p.id.is_some() && p.id == entry.profile_id)
+            {
+                Some(profile) => profile,
+                None => continue,
+            };
+            let ftp = entry
+                .date()
+                .map_or(profile.ftp, |date| profile.ftp_on(date));
+            if entry.ftp == Some(ftp) {
+                continue;
+            }
+            match Activity::load(&self.dir.join(&entry.file)) {
+                Ok(activity) => {
+                    *entry = HistoryEntry {
+                        profile_id: entry.profile_id,
+                        ..HistoryEntry::new(entry.file.clone(), &activity, Some(ftp))
+                    };
+                    changed = true;
+                }
+                Err(e) => errors.push(format!("Unable to read ride {}: {}", entry.file, e)),
+            }
+        }
+        (changed, errors)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let contents = serde_json::to_string(&self.entries).map_err(|e| e.to_string())?;
+        fs::write(self.dir.join(INDEX_FILE), contents).map_err(|e| e.to_string())
+    }
+}
+
+fn best_power<'a>(entries: impl Iterator<Item = &'a HistoryEntry>) -> PowerCurve {
+    let mut best = PowerCurve::default();
+    for curve in entries.filter_map(|entry| entry.power_curve.as_ref()) {
+        best.merge(curve);
+    }
+    best
+}
+
+// Profile that rode a ride. Rides saved before profiles had IDs are matched to
+// a profile by name.
+fn rider<'a>(activity: &Activity, profiles: &'a [UserProfile]) -> Option<&'a UserProfile> {
+    match activity.profile_id {
+        Some(id) => profiles.iter().find(|p| p.id == Some(id)),
+        None => profiles.iter().find(|p| p.name == activity.profile),
+    }
+}
+
+// Names of the ride files in the activities directory.
+fn ride_files(dir: &Path) -> Vec<String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|file| file.ends_with(".json") && file != INDEX_FILE)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::profile_store::ProfileStore;
+    use crate::application::session::RecordBuilder;
+    use crate::application::user_profile::FtpSource;
+    use crate::application::TestDir;
+
+    // Profiles with IDs handed out by the store.
+    fn profiles(names: &[&str]) -> Vec<UserProfile> {
+        let profiles = names
+            .iter()
+            .map(|name| UserProfile {
+                name: name.to_string(),
+                ftp: 250,
+                ..UserProfile::new(false)
+            })
+            .collect();
+        ProfileStore::from_profiles(profiles).to_profiles()
+    }
+
+    fn activity(profile: &UserProfile, started: &str, power: u16) -> Activity {
+        Activity {
+            workout: String::from("Metcalfe"),
+            profile: profile.name.clone(),
+            profile_id: profile.id,
+            weight: None,
+            started: started.to_string(),
+            duration: 3600,
+            laps: vec![0],
+            compliance: None,
+            records: RecordBuilder::default()
+                .power(3600, Some(power), Some(power))
+                .build(),
+        }
+    }
+
+    #[test]
+    fn test_history_open() {
+        let dir = TestDir::new("history");
+        let profiles = profiles(&["Justina"]);
+        let justina = &profiles[0];
+        let first = activity(justina, "2021-10-17T06:30:00-05:00", 250)
+            .save(&dir)
+            .unwrap();
+        activity(justina, "2021-10-18T06:30:00-05:00", 200)
+            .save(&dir)
+            .unwrap();
+        let sam = activity(
+            &UserProfile {
+                name: String::from("Sam"),
+                ..UserProfile::default()
+            },
+            "2021-10-18T07:30:00-05:00",
+            200,
+        );
+        sam.save(&dir).unwrap();
+        fs::write(dir.join("broken.json"), "{").unwrap();
+
+        let (history, errors) = History::open(&dir, &profiles);
+        assert_eq!(errors.len(), 1);
+        let entries: Vec<&HistoryEntry> = history.entries(justina).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].started_at(), "2021-10-18 06:30");
+        assert_eq!(entries[0].normalized_power, Some(200));
+        assert_eq!(entries[1].tss, Some(100.0));
+        assert_eq!(
+            history.daily_tss(justina)[1],
+            ("2021-10-17".parse().unwrap(), 100.0)
+        );
+        // Sam doesn't have a profile so there's no FTP to work out TSS.
+        let sam = history.entry(&sam.file_name()).unwrap();
+        assert_eq!((sam.average_power, sam.tss), (Some(200), None));
+        assert_eq!(history.load(entries[1]).unwrap().records.len(), 3600);
+        // A profile that hasn't been added to the store has no rides.
+        assert_eq!(history.entries(&UserProfile::default()).count(), 0);
+
+        // The saved index is used the next time and rides deleted since are
+        // dropped.
+        fs::remove_file(&first).unwrap();
+        let (reopened, _) = History::open(&dir, &[]);
+        let index = fs::read_to_string(dir.join(INDEX_FILE));
+        assert_eq!(reopened.entries(justina).count(), 1);
+        assert_eq!(
+            reopened.entries(justina).next().unwrap().tss,
+            entries[0].tss
+        );
+        assert!(index.unwrap().contains("Sam"));
+    }
+
+    #[test]
+    fn test_history_update_profiles() {
+        let dir = TestDir::new("rescore");
+        let mut profiles = profiles(&["Justina"]);
+        activity(&profiles[0], "2021-10-17T06:30:00-05:00", 250)
+            .save(&dir)
+            .unwrap();
+        let (mut history, _) = History::open(&dir, &profiles);
+        assert_eq!(history.daily_tss(&profiles[0])[0].1, 100.0);
+
+        // Backdating an FTP rescores the rides it covers.
+        profiles[0].set_ftp(200, "2021-10-01".parse().unwrap(), FtpSource::Manual);
+        assert!(history.update_profiles(&profiles).is_empty());
+        let entry = history.entries(&profiles[0]).next().unwrap();
+        assert_eq!((entry.ftp, entry.tss), (Some(200), Some(156.25)));
+
+        // Rides stay with a renamed profile, even when the index is rebuilt.
+        profiles[0].name = String::from("Jess");
+        assert_eq!(history.entries(&profiles[0]).count(), 1);
+        fs::remove_file(dir.join(INDEX_FILE)).unwrap();
+        let (rebuilt, _) = History::open(&dir, &profiles);
+        assert_eq!(rebuilt.entries(&profiles[0]).count(), 1);
+        assert_eq!(rebuilt.daily_tss(&profiles[0])[0].1, 156.25);
+    }
+
+    #[test]
+    fn test_history_rides_without_ids() {
+        let dir = TestDir::new("ids");
+        let profiles = profiles(&["Justina", "Sam"]);
+        let ride = Activity {
+            profile_id: None,
+            ..activity(&profiles[1], "2021-10-17T06:30:00-05:00", 250)
+        };
+        ride.save(&dir).unwrap();
+
+        // Rides saved before profiles had IDs go to the profile with their
+        // rider's name, and keep its ID in the index.
+        let (history, _) = History::open(&dir, &profiles);
+        assert_eq!(history.entries(&profiles[0]).count(), 0);
+        assert_eq!(history.entries(&profiles[1]).count(), 1);
+        let (reopened, _) = History::open(&dir, &[]);
+        assert_eq!(reopened.entries(&profiles[1]).count(), 1);
+    }
+
+    #[test]
+    fn test_history_personal_records() {
+        let dir = TestDir::new("records");
+        let profiles = profiles(&["Justina", "Sam"]);
+        let (justina, sam) = (&profiles[0], &profiles[1]);
+        activity(justina, "2021-10-17T06:30:00-05:00", 250)
+            .save(&dir)
+            .unwrap();
+        activity(justina, "2021-10-18T06:30:00-05:00", 260)
+            .save(&dir)
+            .unwrap();
+        activity(sam, "2021-10-19T06:30:00-05:00", 300)
+            .save(&dir)
+            .unwrap();
+        let (history, _) = History::open(&dir, &[]);
+
+        let entries: Vec<&HistoryEntry> = history.entries(justina).collect();
+        // Every duration of the second ride beats the first.
+        assert_eq!(history.personal_records(entries[0]).len(), 14);
+        assert_eq!(history.personal_records(entries[0])[0], (1, 260));
+        // Nothing to beat on the first ride, and Sam's ride isn't compared
+        // with Justina's.
+        assert!(history.personal_records(entries[1]).is_empty());
+        let sam = history.entries(sam).next().unwrap();
+        assert!(history.personal_records(sam).is_empty());
+        assert_eq!(history.power_curve(justina).best(3600), Some(260));
+        // The history was opened without profiles so there's no FTP for TSS.
+        assert!(history.daily_tss(justina).is_empty());
+    }
+}
@@ -90,22 +90,106 @@
 // prior to importing workouts.
 //
 #![allow(dead_code)]
-use crate::application::types::{Duration, PowerTarget, Quantity, StartTime};
+use crate::application::ftp_test::FtpTest;
+use crate::application::types::{
+    CadenceTarget, Duration, HeartRateTarget, PowerTarget, Quantity, Rpe, StartTime,
+};
 
 use serde::de::Unexpected;
 use serde_derive::Deserialize;
 
 use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::SystemTime;
 
+// A segment has a duration and a power target. Cadence, heart rate and RPE
+// targets are optional and can be given alongside or instead of power, i.e.
+// '10m @ 0.75 90rpm', '20m @ 140-150bpm' or '5m @ free 7rpe'. A segment without
+// a power target is ridden as PowerTarget::Free.
 #[derive(Clone, Debug)]
-struct Segment {
-    duration: Duration,
-    power_start: PowerTarget,
-    power_end: PowerTarget,
+pub struct Segment {
+    pub duration: Duration,
+    pub power_start: PowerTarget,
+    pub power_end: PowerTarget,
+    pub cadence: Option<CadenceTarget>,
+    pub heart_rate: Option<HeartRateTarget>,
+    pub rpe: Option<Rpe>,
     start_time: StartTime, //Start time in seconds.
 }
 
+impl Segment {
+    fn new(duration: Duration, power_start: PowerTarget, power_end: PowerTarget) -> Self {
+        Self {
+            duration,
+            power_start,
+            power_end,
+            cadence: None,
+            heart_rate: None,
+            rpe: None,
+            start_time: 0.into(),
+        }
+    }
+}
+
+impl FromStr for Segment {
+    // TODO Implement proper errors
+    type Err = &'static str;
+
+    // Parses '<duration> @ <targets>' where targets are separated by whitespace
+    // and units can optionally be separated from their value.
+    // '5m @ 100'
+    // '10m @ 0.75 90rpm'
+    // '20m @ 140-150 bpm'
+    // '5m @ free 7rpe'
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '@').map(|x| x.trim());
+        let duration: Duration = parts.next().unwrap_or_default().parse()?;
+        let targets = parts
+            .next()
+            .ok_or("segment must be defined as <duration> @ <target(s)>")?;
+
+        // Join any units separated by whitespace back on to their value.
+        let mut tokens: Vec<String> = Vec::new();
+        for token in targets.split_whitespace() {
+            let is_unit = ["rpm", "bpm", "lthr", "rpe", "ftp", "map", "cp"]
+                .iter()
+                .any(|unit| token.eq_ignore_ascii_case(unit));
+            match tokens.last_mut() {
+                Some(last) if is_unit => last.push_str(token),
+                _ => tokens.push(token.to_string()),
+            }
+        }
+        if tokens.is_empty() {
+            return Err("segment requires at least one power, cadence, heart rate or RPE target");
+        }
+
+        let mut segment = Segment::new(duration, PowerTarget::Free, PowerTarget::Free);
+        let mut power = None;
+        for token in tokens {
+            let lower = token.to_ascii_lowercase();
+            if lower.ends_with("rpm") {
+                segment.cadence = Some(token.parse()?);
+            } else if lower.ends_with("bpm") || lower.ends_with("lthr") {
+                segment.heart_rate = Some(token.parse()?);
+            } else if lower.ends_with("rpe") {
+                segment.rpe = Some(token.parse()?);
+            } else if power.is_none() {
+                power = Some(token.parse::<PowerTarget>()?);
+            } else {
+                return Err("segment can only define one power target");
+            }
+        }
+        if let Some(power) = power {
+            segment.power_start = power;
+            segment.power_end = power;
+        }
+        Ok(segment)
+    }
+}
+
 impl<'de> serde::Deserialize<'de> for Segment {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -121,41 +205,30 @@ impl<'de> serde::Deserialize<'de> for Segment {
                 duration: Duration,
                 power_start: PowerTarget,
                 power_end: PowerTarget,
+                cadence: Option<CadenceTarget>,
+                heart_rate: Option<HeartRateTarget>,
+                rpe: Option<Rpe>,
             },
         }
 
-        let seg = SegType::deserialize(deserializer)?;
-        let (duration, power_start, power_end) = match seg {
-            SegType::A(value) => {
-                // Let's split our string and remove whitespaces.
-                let value_vec: Vec<&str> = value
-                    .split('@')
-                    .collect::<Vec<&str>>()
-                    .iter()
-                    .map(|x| x.trim())
-                    .collect();
-                // if value_vec.len() != 2 { return error }
-                // TODO: May need to look into support a partial segment
-                let duration: Duration = value_vec[0]
-                    .parse()
-                    .map_err(|e| Error::invalid_value(Unexpected::Str(value_vec[0]), &e))?;
-                let power_target: PowerTarget = value_vec[1]
-                    .parse()
-                    .map_err(|e| Error::invalid_value(Unexpected::Str(value_vec[1]), &e))?;
-                (duration, power_target, power_target)
-            }
+        match SegType::deserialize(deserializer)? {
+            SegType::A(value) => value
+                .parse()
+                .map_err(|e| Error::invalid_value(Unexpected::Str(&value), &e)),
             SegType::B {
                 duration,
                 power_start,
                 power_end,
-            } => (duration, power_start, power_end),
-        };
-        Ok(Segment {
-            duration,
-            power_start,
-            power_end,
-            start_time: 0.into(),
-        })
+                cadence,
+                heart_rate,
+                rpe,
+            } => Ok(Segment {
+                cadence,
+                heart_rate,
+                rpe,
+                ..Segment::new(duration, power_start, power_end)
+            }),
+        }
     }
 }
 
@@ -172,29 +245,258 @@ impl<'de> serde::Deserialize<'de> for Segment {
 // ]
 // lap_each_segment = false
 
+// Intervals can define named parameters with default values that are
+// referenced in segments as {name}. Workouts and other intervals can then
+// instantiate the interval with different values. A param without a default
+// has to be given by every workout using the interval.
+//
+// name = 'Over-Unders'
+// duration = '12m'
+// params = { under = 0.95, over = 1.05, time = '1m' }
+// segments = [
+//   '2m @ {under}',
+//   '{time} @ {over}'
+// ]
+// repeat = 4
+//
+// An interval can extend another interval by name, inheriting any field it
+// doesn't define. Params are merged with the extending interval's values taking
+// precedence.
+//
+// name = 'Hard Over-Unders'
+// extends = 'Over-Unders'
+// params = { over = 1.1 }
+
+// A segment as defined in an interval. Segments defined as a string are kept
+// so they can be rendered again with different params.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum SegmentDefinition {
+    Template(String),
+    Segment(Segment),
+}
+
+// Param values can be any TOML string, integer or float. Floats are kept in
+// their debug format so 1.0 stays a percentage rather than becoming 1 watt.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum ParamValue {
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl From<ParamValue> for String {
+    fn from(value: ParamValue) -> Self {
+        match value {
+            ParamValue::Integer(v) => v.to_string(),
+            ParamValue::Float(v) => format!("{:?}", v),
+            ParamValue::String(v) => v,
+        }
+    }
+}
+
+fn params_from(params: Option<BTreeMap<String, ParamValue>>) -> BTreeMap<String, String> {
+    params
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(k, v)| (k, v.into()))
+        .collect()
+}
+
+// Replaces each {name} in a segment with the value of the param.
+fn render_segment(
+    segment: &str,
+    params: &BTreeMap<String, String>,
+) -> Result<String, &'static str> {
+    let mut rendered = String::new();
+    let mut rest = segment;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .ok_or("segment param is missing a closing }")?
+            + open;
+        let value = params
+            .get(rest[open + 1..close].trim())
+            .ok_or("segment references a param that isn't defined")?;
+        rendered.push_str(&rest[..open]);
+        rendered.push_str(value);
+        rest = &rest[close + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+// Names of the params referenced in a segment.
+fn param_names(segment: &str) -> impl Iterator<Item = &str> {
+    segment
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.find('}').map(|close| part[..close].trim()))
+}
+
+fn render_segments(
+    definitions: &[SegmentDefinition],
+    params: &BTreeMap<String, String>,
+) -> Result<Vec<Segment>, &'static str> {
+    definitions
+        .iter()
+        .map(|definition| match definition {
+            SegmentDefinition::Template(value) => render_segment(value, params)?.parse(),
+            SegmentDefinition::Segment(segment) => Ok(segment.clone()),
+        })
+        .collect()
+}
+
+fn segments_duration(segments: &[Segment]) -> Duration {
+    segments
+        .iter()
+        .fold(Duration::from(0), |acc, x| acc + x.duration)
+}
+
+// Fields defined by an interval that extends another interval. These are
+// applied on top of the extended interval once it has been loaded.
+#[derive(Clone, Debug)]
+struct Extends {
+    name: String,
+    description: Option<String>,
+    duration: Option<Duration>,
+    segments: Option<Vec<SegmentDefinition>>,
+    lap_each_segment: Option<bool>,
+    repeat: Option<Quantity>,
+}
+
 // TODO Refactor what's being done with IntervalTemplate to simplify what's
 // going on here.
 #[derive(Clone, Debug)]
 pub struct IntervalTemplate {
     pub name: Option<String>,
-    description: Option<String>,
-    duration: Duration,
-    segments: Vec<Segment>,
-    lap_each_segment: bool,
-    repeat: Option<Quantity>,
+    pub description: Option<String>,
+    pub duration: Duration,
+    pub segments: Vec<Segment>,
+    pub lap_each_segment: bool,
+    pub repeat: Option<Quantity>,
+    // Whether a lap starts with the interval. None follows the workout's
+    // lap_each_interval. Set for the intervals of an included workout so they
+    // keep the laps of that workout.
+    pub lap_start: Option<bool>,
+    params: BTreeMap<String, String>,
+    segment_definitions: Vec<SegmentDefinition>,
+    extends: Option<Extends>,
+    pub source: Source,
+    // File the interval was read from, if any.
+    pub path: Option<PathBuf>,
 }
 
 impl IntervalTemplate {
+    // Creates an unnamed interval from a single segment defined in a workout.
+    fn from_segment(segment: Segment, repeat: Option<Quantity>, lap_each_segment: bool) -> Self {
+        Self {
+            name: None,
+            description: None,
+            duration: segment.duration * repeat.unwrap_or(Quantity(1)),
+            lap_each_segment,
+            segment_definitions: vec![SegmentDefinition::Segment(segment.clone())],
+            segments: vec![segment],
+            repeat,
+            lap_start: None,
+            params: BTreeMap::new(),
+            extends: None,
+            source: Source::User,
+            path: None,
+        }
+    }
+
     pub fn validate(&self) -> Result<(), &'static str> {
-        let seg_duration = self
-            .segments
-            .iter()
-            .fold(Duration::from(0), |acc, x| acc + x.duration);
-        if self.duration != seg_duration * self.repeat.unwrap_or(Quantity(1)) {
+        if self.extends.is_some() {
+            return Err("interval extends an interval that hasn't been loaded");
+        }
+        // Segments can't be checked until a workout gives the params.
+        if self.needs_params() {
+            return Ok(());
+        }
+        if self.duration != segments_duration(&self.segments) * self.repeat.unwrap_or(Quantity(1)) {
             return Err("duration does not match duration calculated from segments");
         }
         Ok(())
     }
+
+    // Name of the interval this interval extends if it hasn't been resolved yet.
+    fn extends(&self) -> Option<&str> {
+        self.extends.as_ref().map(|e| e.name.as_str())
+    }
+
+    // Returns true if a segment references a param the interval doesn't have
+    // a default for.
+    fn needs_params(&self) -> bool {
+        self.segment_definitions
+            .iter()
+            .any(|definition| match definition {
+                SegmentDefinition::Template(value) => {
+                    param_names(value).any(|name| !self.params.contains_key(name))
+                }
+                SegmentDefinition::Segment(_) => false,
+            })
+    }
+
+    // Renders the interval's segments with its own params. An interval with a
+    // param that doesn't have a default is left unrendered until a workout
+    // gives the param.
+    fn render(&mut self) -> Result<(), &'static str> {
+        if !self.needs_params() {
+            self.segments = render_segments(&self.segment_definitions, &self.params)?;
+        }
+        Ok(())
+    }
+
+    // Renders the interval's segments with params merged over the interval's
+    // default params. Duration is recalculated from the rendered segments.
+    fn with_params(&self, params: &BTreeMap<String, String>) -> Result<Self, &'static str> {
+        let mut template = self.clone();
+        template.params.extend(params.clone());
+        template.segments = render_segments(&template.segment_definitions, &template.params)?;
+        template.duration =
+            segments_duration(&template.segments) * template.repeat.unwrap_or(Quantity(1));
+        Ok(template)
+    }
+
+    // Builds this interval from the interval it extends.
+    fn resolve_extends(&mut self, library: &Library) -> Result<(), &'static str> {
+        let extends = match &self.extends {
+            Some(extends) => extends.clone(),
+            None => return Ok(()),
+        };
+        let base = library
+            .interval(&extends.name)
+            .ok_or("interval extends an interval that can't be found")?;
+        let mut template = base.clone();
+        template.name = self.name.clone();
+        template.path = self.path.clone();
+        if let Some(description) = extends.description {
+            template.description = Some(description);
+        }
+        if let Some(segments) = extends.segments {
+            template.segment_definitions = segments;
+        }
+        if let Some(lap_each_segment) = extends.lap_each_segment {
+            template.lap_each_segment = lap_each_segment;
+        }
+        if let Some(repeat) = extends.repeat {
+            template.repeat = Some(repeat);
+        }
+        template.params.extend(self.params.clone());
+        template.render()?;
+        if !template.needs_params() {
+            template.duration =
+                segments_duration(&template.segments) * template.repeat.unwrap_or(Quantity(1));
+        }
+        if let Some(duration) = extends.duration {
+            template.duration = duration;
+        }
+        template.validate()?;
+        *self = template;
+        Ok(())
+    }
 }
 
 impl<'de> serde::Deserialize<'de> for IntervalTemplate {
@@ -202,26 +504,70 @@ impl<'de> serde::Deserialize<'de> for IntervalTemplate {
     where
         D: serde::Deserializer<'de>,
     {
-        //        use serde::de::Error;
+        use serde::de::Error;
 
         #[derive(Deserialize)]
         struct ShadowIntervalTemplate {
             name: String,
             description: Option<String>,
-            duration: Duration,
-            segments: Vec<Segment>,
+            duration: Option<Duration>,
+            segments: Option<Vec<SegmentDefinition>>,
             lap_each_segment: Option<bool>,
             repeat: Option<Quantity>,
+            params: Option<BTreeMap<String, ParamValue>>,
+            extends: Option<String>,
         }
 
         let sil = ShadowIntervalTemplate::deserialize(deserializer)?;
+        let params = params_from(sil.params);
+        // Intervals extending another interval are rendered once the interval
+        // they extend is loaded.
+        if let Some(name) = sil.extends {
+            return Ok(Self {
+                name: Some(sil.name),
+                description: None,
+                duration: Duration(0),
+                segments: Vec::new(),
+                lap_each_segment: false,
+                repeat: None,
+                lap_start: None,
+                params,
+                segment_definitions: Vec::new(),
+                extends: Some(Extends {
+                    name,
+                    description: sil.description,
+                    duration: sil.duration,
+                    segments: sil.segments,
+                    lap_each_segment: sil.lap_each_segment,
+                    repeat: sil.repeat,
+                }),
+                source: Source::User,
+                path: None,
+            });
+        }
+        let duration = sil
+            .duration
+            .ok_or_else(|| Error::missing_field("duration"))?;
+        let segment_definitions = sil
+            .segments
+            .ok_or_else(|| Error::missing_field("segments"))?;
+        // Segments are rendered with the interval's default params when they
+        // can be. Errors rendering them are reported when the interval is
+        // loaded into the library so they only fail this interval.
+        let segments = render_segments(&segment_definitions, &params).unwrap_or_default();
         Ok(Self {
             name: Some(sil.name),
             description: sil.description,
-            duration: sil.duration,
-            segments: sil.segments,
+            duration,
+            segments,
             lap_each_segment: sil.lap_each_segment.unwrap_or(false),
             repeat: sil.repeat,
+            lap_start: None,
+            params,
+            segment_definitions,
+            extends: None,
+            source: Source::User,
+            path: None,
         })
     }
 }
@@ -233,6 +579,7 @@ struct ValidateIntervalTemplate {
     segments: Option<Vec<SegmentUpdate>>,
     lap_each_segment: Option<bool>,
     repeat: Option<Quantity>,
+    params: BTreeMap<String, String>,
 }
 
 #[derive(Clone, Debug)]
@@ -277,6 +624,7 @@ struct SegmentUpdate {
 // 'Warmup" # Just a string
 // '10m@.95' # A segment to be turned into an IntervalType
 // { name = '30on/30off', repeat = 30 } # a name interval with changed parameters
+// { workout = 'Openers' } # the intervals of another workout
 #[derive(Debug, Clone)]
 enum IntervalTemplateType {
     // A name of an Interval or a segment that can be parsed into a string.
@@ -284,6 +632,8 @@ enum IntervalTemplateType {
     // An IntervalTemplate that needs to be validated by name and has fields
     // to update in a found IntervalTemplate
     ValidateAndUpdate(ValidateIntervalTemplate),
+    // A name of a Workout whose intervals are embedded in this workout
+    ValidateWorkout(String),
     // A valid IntervalTemplate
     IntervalTemplate(IntervalTemplate),
 }
@@ -305,6 +655,9 @@ impl<'de> serde::Deserialize<'de> for IntervalTemplateType {
                 duration: Duration,
                 power_start: PowerTarget,
                 power_end: PowerTarget,
+                cadence: Option<CadenceTarget>,
+                heart_rate: Option<HeartRateTarget>,
+                rpe: Option<Rpe>,
                 repeat: Option<Quantity>,
                 lap_each_segment: Option<bool>,
             },
@@ -314,6 +667,11 @@ impl<'de> serde::Deserialize<'de> for IntervalTemplateType {
                 lap_each_segment: Option<bool>,
                 repeat: Option<Quantity>,
                 segments: Option<Vec<String>>,
+                params: Option<BTreeMap<String, ParamValue>>,
+            },
+            // { workout = 'Openers' }
+            D {
+                workout: String,
             },
         }
 
@@ -330,52 +688,36 @@ impl<'de> serde::Deserialize<'de> for IntervalTemplateType {
                     return Ok(Self::Validate(String::from(v[0])));
                 }
                 // Parse our segment and crate an interval type
-                let duration: Duration = v[0]
+                let segment: Segment = value
                     .parse()
-                    .map_err(|e| Error::invalid_value(Unexpected::Str(v[0]), &e))?;
-                let power_target: PowerTarget = v[1]
-                    .parse()
-                    .map_err(|e| Error::invalid_value(Unexpected::Str(v[1]), &e))?;
-                let segment = Segment {
-                    duration,
-                    power_start: power_target,
-                    power_end: power_target,
-                    start_time: 0.into(),
-                };
-                let interval_template = IntervalTemplate {
-                    name: Some(String::from("this should be optional")),
-                    description: Some(String::from("this should be optional")),
-                    duration,
-                    lap_each_segment: false,
-                    segments: vec![segment],
-                    repeat: None,
-                };
-                Ok(Self::IntervalTemplate(interval_template))
+                    .map_err(|e| Error::invalid_value(Unexpected::Str(&value), &e))?;
+                Ok(Self::IntervalTemplate(IntervalTemplate::from_segment(
+                    segment, None, false,
+                )))
             }
             IntervalType::B {
                 duration,
                 power_start,
                 power_end,
+                cadence,
+                heart_rate,
+                rpe,
                 repeat,
                 lap_each_segment,
             } => {
-                let interval_duration = duration * repeat.unwrap_or(Quantity(1));
                 let segment = Segment {
-                    duration,
-                    power_start,
-                    power_end,
-                    start_time: 0.into(),
+                    cadence,
+                    heart_rate,
+                    rpe,
+                    ..Segment::new(duration, power_start, power_end)
                 };
                 // Need to validate IntervalTemplates that are created from segments
                 // passed into the WorkoutTemplate
-                Ok(Self::IntervalTemplate(IntervalTemplate {
-                    name: Some(String::from("this should be optional")),
-                    description: Some(String::from("this should be optional")),
-                    duration: interval_duration,
-                    lap_each_segment: lap_each_segment.unwrap_or(false),
-                    segments: vec![segment],
+                Ok(Self::IntervalTemplate(IntervalTemplate::from_segment(
+                    segment,
                     repeat,
-                }))
+                    lap_each_segment.unwrap_or(false),
+                )))
             }
             IntervalType::C {
                 name,
@@ -383,6 +725,7 @@ impl<'de> serde::Deserialize<'de> for IntervalTemplateType {
                 repeat,
                 lap_each_segment,
                 segments,
+                params,
             } => {
                 // TODO catch all these unwrap(s)()
                 let mut merge_segments: Vec<SegmentUpdate> = Vec::new();
@@ -431,56 +774,91 @@ impl<'de> serde::Deserialize<'de> for IntervalTemplateType {
                     } else {
                         None
                     },
+                    params: params_from(params),
                 }))
             }
+            IntervalType::D { workout } => Ok(Self::ValidateWorkout(workout)),
         }
     }
 }
-#[derive(Deserialize)]
+#[derive(Clone, Debug)]
 pub struct WorkoutTemplate {
     pub name: String,
-    description: String,
-    duration: Duration,
-    lap_each_interval: bool,
-    intervals: Vec<IntervalTemplate>,
+    pub description: String,
+    pub duration: Duration,
+    pub lap_each_interval: bool,
+    pub intervals: Vec<IntervalTemplate>,
+    pub ftp_test: Option<FtpTest>,
+    pub source: Source,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct ShadowWorkoutTemplate {
-    name: String,
+    pub name: String,
     description: String,
     duration: Duration,
     lap_each_interval: bool,
     intervals: Vec<IntervalTemplateType>,
+    // Marks the workout as an FTP test with the protocol used to work out FTP.
+    #[serde(default)]
+    ftp_test: Option<FtpTest>,
+    // File the workout was read from, if any.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
 }
 impl ShadowWorkoutTemplate {
-    pub fn validate(
-        &mut self,
-        interval_templates: &BTreeMap<String, IntervalTemplate>,
-    ) -> Result<(), &'static str> {
+    pub fn validate(&mut self, library: &Library) -> Result<(), &'static str> {
         // IntervalTemplate gets validated for duration upon creation. This
         // validates the WorkoutTemplate duration and transforms any
         // IntervalTemplateType::Validate to IntervalTemplateType::IntervalTemplate or
-        // returns an error.
+        // returns an error. Embedded workouts are expanded into their intervals.
         // Keep track of our durations
         let mut duration = Duration(0);
-        for interval_type in self.intervals.iter_mut() {
+        let mut intervals = Vec::with_capacity(self.intervals.len());
+        // Where each included workout ends.
+        let mut included_ends = Vec::new();
+        for interval_type in self.intervals.iter() {
             match interval_type {
-                IntervalTemplateType::Validate(value) => {
-                    // Need to return an error here template can't be found
-                    if let Some(template) = interval_templates.get(value) {
+                IntervalTemplateType::Validate(value) => match library.interval(value) {
+                    Some(template) if template.needs_params() => {
+                        return Err("interval has params without a default that must be given")
+                    }
+                    Some(template) => {
                         duration += template.duration;
-                        *interval_type = IntervalTemplateType::IntervalTemplate(template.clone())
+                        intervals.push(IntervalTemplateType::IntervalTemplate(template.clone()))
                     }
+                    None => return Err("interval can't be found"),
+                },
+                IntervalTemplateType::IntervalTemplate(template) => {
+                    duration += template.duration;
+                    intervals.push(interval_type.clone())
+                }
+                IntervalTemplateType::ValidateWorkout(name) => {
+                    if *name == self.name {
+                        return Err("workout can't include itself");
+                    }
+                    let workout = library
+                        .workout(name)
+                        .ok_or("included workout can't be found")?;
+                    duration += workout.duration;
+                    // An included workout keeps its own laps, starting a lap
+                    // where it starts and with each of its intervals if it
+                    // laps each interval.
+                    intervals.extend(workout.intervals.iter().cloned().enumerate().map(
+                        |(i, mut interval)| {
+                            interval.lap_start = match i {
+                                0 => Some(true),
+                                _ => interval.lap_start.or(Some(workout.lap_each_interval)),
+                            };
+                            IntervalTemplateType::IntervalTemplate(interval)
+                        },
+                    ));
+                    included_ends.push(intervals.len());
                 }
-                IntervalTemplateType::IntervalTemplate(template) => duration += template.duration,
                 IntervalTemplateType::ValidateAndUpdate(validate_template) => {
-                    match interval_templates.get(&validate_template.name) {
+                    match library.interval(&validate_template.name) {
                         Some(template) => {
-                            let mut t = template.clone();
-                            if let Some(d) = validate_template.duration {
-                                t.duration = d;
-                            }
+                            let mut t = template.with_params(&validate_template.params)?;
                             if let Some(les) = validate_template.lap_each_segment {
                                 t.lap_each_segment = les;
                             }
@@ -489,6 +867,9 @@ impl ShadowWorkoutTemplate {
                             }
                             if let Some(segments) = &validate_template.segments {
                                 for seg in segments {
+                                    if seg.index >= t.segments.len() {
+                                        return Err("segment index out of range for interval");
+                                    }
                                     if let Some(d) = seg.duration {
                                         t.segments[seg.index].duration = d;
                                     }
@@ -500,8 +881,17 @@ impl ShadowWorkoutTemplate {
                                     }
                                 }
                             }
+                            // Duration follows any updated segments and repeats
+                            // unless the workout defines it.
+                            t.duration = match validate_template.duration {
+                                Some(d) => d,
+                                None => {
+                                    segments_duration(&t.segments) * t.repeat.unwrap_or(Quantity(1))
+                                }
+                            };
+                            t.validate()?;
                             duration += t.duration;
-                            *interval_type = IntervalTemplateType::IntervalTemplate(t.clone());
+                            intervals.push(IntervalTemplateType::IntervalTemplate(t));
                         }
                         None => return Err("Template not found"), // return error
                     }
@@ -511,9 +901,24 @@ impl ShadowWorkoutTemplate {
         if self.duration != duration {
             return Err("defined duration does not match sum of interval durations");
         }
+        // A lap also starts where an included workout ends.
+        for end in included_ends {
+            if let Some(IntervalTemplateType::IntervalTemplate(interval)) = intervals.get_mut(end) {
+                interval.lap_start = Some(true);
+            }
+        }
+        self.intervals = intervals;
         Ok(())
     }
 
+    // Names of the workouts this workout embeds.
+    fn workout_references(&self) -> impl Iterator<Item = &str> {
+        self.intervals.iter().filter_map(|x| match x {
+            IntervalTemplateType::ValidateWorkout(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
     pub fn build_workout_template(self) -> WorkoutTemplate {
         let intervals: Vec<IntervalTemplate> = self
             .intervals
@@ -529,6 +934,8 @@ impl ShadowWorkoutTemplate {
             duration: self.duration,
             lap_each_interval: self.lap_each_interval,
             intervals,
+            ftp_test: self.ftp_test,
+            source: Source::User,
         }
     }
 }
@@ -546,6 +953,108 @@ impl ShadowWorkoutTemplate {
 pub struct Library {
     pub intervals: BTreeMap<String, IntervalTemplate>,
     pub workouts: BTreeMap<String, WorkoutTemplate>,
+    // Templates with the same name as a template from another source.
+    pub shadowed: Vec<Shadowed>,
+    // Every loaded template by qualified name (team/Warmup) including templates
+    // shadowed by a template from a source with higher precedence.
+    qualified_intervals: BTreeMap<String, IntervalTemplate>,
+    qualified_workouts: BTreeMap<String, WorkoutTemplate>,
+}
+
+// Source of a template in the Library. When templates from different sources
+// have the same name, the template from the source with the highest precedence
+// is used: User > Team > BuiltIn. Templates from any source can still be
+// referenced by qualifying the name with the source, i.e. 'team/Warmup'.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    BuiltIn,
+    Team,
+    User,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match self {
+            Source::BuiltIn => "builtin",
+            Source::Team => "team",
+            Source::User => "user",
+        };
+        write!(f, "{}", source)
+    }
+}
+
+impl FromStr for Source {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "builtin" => Ok(Source::BuiltIn),
+            "team" => Ok(Source::Team),
+            "user" => Ok(Source::User),
+            _ => Err("source must be one of builtin, team or user"),
+        }
+    }
+}
+
+// Returns true if a template name is qualified with a source.
+fn is_qualified(name: &str) -> bool {
+    match name.find('/') {
+        Some(i) => name[..i].parse::<Source>().is_ok(),
+        None => false,
+    }
+}
+
+// An interval or workout that failed to load into the Library.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateError {
+    pub name: String,
+    pub source: Source,
+    pub error: &'static str,
+    // File the template was read from, if any.
+    pub path: Option<PathBuf>,
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}: {}", self.source, self.name, self.error)
+    }
+}
+
+// Kind of template in the Library.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TemplateKind {
+    Interval,
+    Workout,
+}
+
+impl std::fmt::Display for TemplateKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            TemplateKind::Interval => "interval",
+            TemplateKind::Workout => "workout",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+// A template in use with the name of a template from a source with lower
+// precedence.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shadowed {
+    pub kind: TemplateKind,
+    pub name: String,
+    pub source: Source,
+    pub shadowed: Source,
+}
+
+impl std::fmt::Display for Shadowed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}/{} shadows {}/{}",
+            self.kind, self.source, self.name, self.shadowed, self.name
+        )
+    }
 }
 
 impl Library {
@@ -553,8 +1062,233 @@ impl Library {
         Self {
             intervals: BTreeMap::new(),
             workouts: BTreeMap::new(),
+            shadowed: Vec::new(),
+            qualified_intervals: BTreeMap::new(),
+            qualified_workouts: BTreeMap::new(),
+        }
+    }
+
+    // Finds an interval by name. Names qualified with a source find the
+    // interval from that source even if it has been shadowed.
+    pub fn interval(&self, name: &str) -> Option<&IntervalTemplate> {
+        match is_qualified(name) {
+            true => self.qualified_intervals.get(name),
+            false => self.intervals.get(name),
+        }
+    }
+
+    // Finds a workout by name. Names qualified with a source find the
+    // workout from that source even if it has been shadowed.
+    pub fn workout(&self, name: &str) -> Option<&WorkoutTemplate> {
+        match is_qualified(name) {
+            true => self.qualified_workouts.get(name),
+            false => self.workouts.get(name),
+        }
+    }
+
+    // Validates and adds the intervals and workouts of a ShadowLibrary from a
+    // source to the Library. Intervals are loaded first so workouts can
+    // reference them, and intervals extending another interval are loaded once
+    // the interval they extend has been loaded. Any template that fails to
+    // validate or has a duplicate name within its source is skipped and
+    // returned as an error.
+    pub fn load(&mut self, shadow_library: ShadowLibrary, source: Source) -> Vec<TemplateError> {
+        let mut errors = Vec::new();
+        let mut pending = Vec::new();
+        for interval in shadow_library.intervals.unwrap_or_default() {
+            match interval.extends() {
+                Some(_) => pending.push(interval),
+                None => self.add_interval(interval, source, &mut errors),
+            }
+        }
+        // Keep resolving intervals until no more intervals can be resolved.
+        // Anything left over extends an unknown interval or is part of a cycle.
+        loop {
+            let count = pending.len();
+            let (ready, waiting): (Vec<IntervalTemplate>, Vec<IntervalTemplate>) =
+                pending.into_iter().partition(|interval| {
+                    matches!(interval.extends(), Some(name) if self.interval(name).is_some())
+                });
+            pending = waiting;
+            for mut interval in ready {
+                match interval.resolve_extends(self) {
+                    Ok(_) => self.add_interval(interval, source, &mut errors),
+                    Err(error) => errors.push(TemplateError {
+                        name: interval.name.unwrap_or_default(),
+                        source,
+                        error,
+                        path: interval.path,
+                    }),
+                }
+            }
+            if pending.len() == count {
+                break;
+            }
+        }
+        for interval in pending {
+            errors.push(TemplateError {
+                name: interval.name.unwrap_or_default(),
+                source,
+                error: "interval extends an interval that can't be found",
+                path: interval.path,
+            });
+        }
+
+        // Workouts embedding other workouts are loaded once the workouts they
+        // embed have been loaded, the same as intervals extending intervals.
+        let mut pending = shadow_library.workouts.unwrap_or_default();
+        loop {
+            let count = pending.len();
+            let (ready, waiting): (Vec<ShadowWorkoutTemplate>, Vec<ShadowWorkoutTemplate>) =
+                pending.into_iter().partition(|workout| {
+                    workout
+                        .workout_references()
+                        .all(|name| name == workout.name || self.workout(name).is_some())
+                });
+            pending = waiting;
+            for shadow_workout in ready {
+                self.add_workout(shadow_workout, source, &mut errors);
+            }
+            if pending.len() == count {
+                break;
+            }
+        }
+        for (i, workout) in pending.iter().enumerate() {
+            errors.push(TemplateError {
+                name: workout.name.clone(),
+                source,
+                error: match includes_cycle(i, &pending) {
+                    true => "included workouts form a cycle",
+                    false => "included workout can't be found",
+                },
+                path: workout.path.clone(),
+            });
+        }
+        errors
+    }
+
+    fn add_workout(
+        &mut self,
+        mut shadow_workout: ShadowWorkoutTemplate,
+        source: Source,
+        errors: &mut Vec<TemplateError>,
+    ) {
+        if let Err(error) = shadow_workout.validate(self) {
+            errors.push(TemplateError {
+                name: shadow_workout.name,
+                source,
+                error,
+                path: shadow_workout.path,
+            });
+            return;
+        }
+        let qualified = format!("{}/{}", source, shadow_workout.name);
+        if self.qualified_workouts.contains_key(&qualified) {
+            errors.push(TemplateError {
+                name: shadow_workout.name,
+                source,
+                error: "workout with the same name has already been loaded",
+                path: shadow_workout.path,
+            });
+            return;
+        }
+        let mut workout = shadow_workout.build_workout_template();
+        workout.source = source;
+        self.qualified_workouts.insert(qualified, workout.clone());
+        if let Some(shadowed) = self.shadow(TemplateKind::Workout, &workout.name, source, |l, n| {
+            l.workouts.get(n).map(|w| w.source)
+        }) {
+            if shadowed.shadowed != source {
+                self.workouts.insert(workout.name.clone(), workout);
+            }
+            self.shadowed.push(shadowed);
+            return;
+        }
+        self.workouts.insert(workout.name.clone(), workout);
+    }
+
+    fn add_interval(
+        &mut self,
+        mut interval: IntervalTemplate,
+        source: Source,
+        errors: &mut Vec<TemplateError>,
+    ) {
+        // As this is being deserialized through Serde where Serde
+        // requires a name to be present, unwrap can be called here
+        let name = interval.name.clone().unwrap();
+        if let Err(error) = interval.render().and_then(|_| interval.validate()) {
+            errors.push(TemplateError {
+                name,
+                source,
+                error,
+                path: interval.path,
+            });
+            return;
+        }
+        let qualified = format!("{}/{}", source, name);
+        if self.qualified_intervals.contains_key(&qualified) {
+            errors.push(TemplateError {
+                name,
+                source,
+                error: "interval with the same name has already been loaded",
+                path: interval.path,
+            });
+            return;
+        }
+        interval.source = source;
+        self.qualified_intervals.insert(qualified, interval.clone());
+        if let Some(shadowed) = self.shadow(TemplateKind::Interval, &name, source, |l, n| {
+            l.intervals.get(n).map(|i| i.source)
+        }) {
+            if shadowed.shadowed != source {
+                self.intervals.insert(name, interval);
+            }
+            self.shadowed.push(shadowed);
+            return;
+        }
+        self.intervals.insert(name, interval);
+    }
+
+    // Checks if a template being added has the same name as a template from
+    // another source, returning which source's template is shadowed.
+    fn shadow<F>(
+        &self,
+        kind: TemplateKind,
+        name: &str,
+        source: Source,
+        existing: F,
+    ) -> Option<Shadowed>
+    where
+        F: Fn(&Library, &str) -> Option<Source>,
+    {
+        existing(self, name).map(|existing| Shadowed {
+            kind,
+            name: name.to_string(),
+            source: std::cmp::max(existing, source),
+            shadowed: std::cmp::min(existing, source),
+        })
+    }
+}
+
+// Follows the workouts included by the workout at index start through the
+// workouts that couldn't be loaded to find if they lead back to it.
+fn includes_cycle(start: usize, workouts: &[ShadowWorkoutTemplate]) -> bool {
+    let mut visited = vec![false; workouts.len()];
+    let mut stack = vec![start];
+    while let Some(i) = stack.pop() {
+        for name in workouts[i].workout_references() {
+            if let Some(next) = workouts.iter().position(|w| w.name == name) {
+                if next == start {
+                    return true;
+                }
+                if !visited[next] {
+                    visited[next] = true;
+                    stack.push(next);
+                }
+            }
         }
     }
+    false
 }
 
 impl Default for Library {
@@ -563,31 +1297,9 @@ impl Default for Library {
         // Ok to unwrap here as this will be called with setting up applicaiton.
         // Application shouldn't start if our defaults are wrong.
         let sl: ShadowLibrary = toml::from_str(&default_library_templates()).unwrap();
-        if let Some(contents) = sl.intervals {
-            // Loop through default intervals, validate them, and add them to the library.
-            for interval in contents {
-                if let Err(_e) = interval.validate() {
-                    panic!("Invalid default intervals within application")
-                }
-                // As this is being deserialized through Serde where Serde
-                // requires a name to be present, unwrap can be called here
-                let name = interval.name.clone().unwrap();
-                match library.intervals.contains_key(&name) {
-                    true => panic!("Trying to load duplicate defaut intervals into library"), //Also log error for duplicate key
-                    false => {
-                        library.intervals.insert(name, interval);
-                    }
-                }
-            }
-        }
-        if let Some(contents) = sl.workouts {
-            for mut shadow_workout in contents {
-                if let Err(_e) = shadow_workout.validate(&library.intervals) {
-                    panic!("Invalid default workout within application");
-                }
-                let workout = shadow_workout.build_workout_template();
-                library.workouts.insert(workout.name.clone(), workout);
-            }
+        let errors = library.load(sl, Source::BuiltIn);
+        if !errors.is_empty() {
+            panic!("Invalid default templates within application: {:?}", errors);
         }
         library
     }
@@ -608,9 +1320,95 @@ impl Default for ShadowLibrary {
     }
 }
 
+impl ShadowLibrary {
+    // Reads intervals and workouts from a TOML file. Each template keeps the
+    // path of the file so errors can be reported against it.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut shadow_library: Self = toml::from_str(&contents).map_err(|e| e.to_string())?;
+        for interval in shadow_library.intervals.iter_mut().flatten() {
+            interval.path = Some(path.to_path_buf());
+        }
+        for workout in shadow_library.workouts.iter_mut().flatten() {
+            workout.path = Some(path.to_path_buf());
+        }
+        Ok(shadow_library)
+    }
+
+    // Reads and merges the intervals and workouts from every TOML file in a
+    // directory. Files that can't be read are skipped and returned with their
+    // error.
+    pub fn from_dir(path: &Path) -> (Self, Vec<(PathBuf, String)>) {
+        let mut shadow_library = Self::default();
+        let mut errors = Vec::new();
+        let files = match library_files(path) {
+            Ok(files) => files,
+            Err(e) => {
+                errors.push((path.to_path_buf(), e.to_string()));
+                return (shadow_library, errors);
+            }
+        };
+        for file in files {
+            match Self::from_file(&file) {
+                Ok(sl) => shadow_library.merge(sl),
+                Err(e) => errors.push((file, e)),
+            }
+        }
+        (shadow_library, errors)
+    }
+
+    pub fn merge(&mut self, other: ShadowLibrary) {
+        if let Some(intervals) = other.intervals {
+            self.intervals
+                .get_or_insert_with(Vec::new)
+                .extend(intervals);
+        }
+        if let Some(workouts) = other.workouts {
+            self.workouts.get_or_insert_with(Vec::new).extend(workouts);
+        }
+    }
+}
+
+// Library files in a directory, sorted so templates load in the same order
+// every time.
+pub fn library_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension() == Some(std::ffi::OsStr::new("toml")))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// Path, modified time and size of a library file.
+pub type FileStamp = (PathBuf, SystemTime, u64);
+
+// Stamp of every library file in the directories. Any change to the list
+// means a file was added, removed or saved since the stamps were taken. The
+// size is kept along with the modified time as a file saved twice within the
+// resolution of the file system's clock keeps the same modified time.
+pub fn file_stamps(dirs: &[PathBuf]) -> Vec<FileStamp> {
+    let mut stamps = Vec::new();
+    for dir in dirs {
+        for path in library_files(dir).unwrap_or_default() {
+            if let Ok(metadata) = fs::metadata(&path) {
+                if let Ok(modified) = metadata.modified() {
+                    stamps.push((path, modified, metadata.len()));
+                }
+            }
+        }
+    }
+    stamps
+}
+
 // Application default intervals and workout templates
 fn default_library_templates() -> String {
-    r#"
+    // The ramp test steps up 20W every minute from 100W until the rider fails.
+    let ramp: Vec<String> = (0..30)
+        .map(|step| format!("'1m @ {}'", 100 + step * 20))
+        .collect();
+    format!(
+        r#"
     [[ intervals ]]
     name = "Warmup"
     description = "Warming up the legs"
@@ -651,21 +1449,76 @@ fn default_library_templates() -> String {
       'Warmup',
       '2x20',
       'Cooldown',
-    ]"#
-    .to_string()
-}
+    ]
 
-#[cfg(test)]
-mod test {
+    [[ intervals ]]
+    name = "Ramp"
+    description = "1 minute steps up 20W at a time until failure"
+    duration = "30m"
+    lap_each_segment = false
+    segments = [
+      {ramp}
+    ]
+
+    [[ workouts ]]
+    name = "Ramp Test"
+    description = "Ride each step until you can't hold the target. FTP is 75% of your best minute"
+    duration = "40m"
+    lap_each_interval = true
+    ftp_test = "ramp"
+    intervals = [
+      'Warmup',
+      'Ramp',
+    ]
+
+    [[ workouts ]]
+    name = "20 Minute FTP Test"
+    description = "5 minutes hard to clear the legs then 20 minutes all out. FTP is 95% of the 20 minutes"
+    duration = "50m"
+    lap_each_interval = true
+    ftp_test = "twenty_minute"
+    intervals = [
+      'Warmup',
+      '5m @ free',
+      '10m @ 0.55',
+      '20m @ free',
+      'Cooldown',
+    ]
+
+    [[ workouts ]]
+    name = "8 Minute FTP Test"
+    description = "Two 8 minute efforts all out. FTP is 90% of the better effort"
+    duration = "41m"
+    lap_each_interval = true
+    ftp_test = "eight_minute"
+    intervals = [
+      'Warmup',
+      '8m @ free',
+      '10m @ 0.55',
+      '8m @ free',
+      'Cooldown',
+    ]"#,
+        ramp = ramp.join(",\n      ")
+    )
+}
+
+#[cfg(test)]
+mod test {
     use super::*;
+    use crate::application::types::Threshold;
+    use crate::application::TestDir;
 
     #[test]
     fn test_default_library() {
         let library = Library::default();
         assert!(!library.intervals.is_empty());
         assert!(!library.workouts.is_empty());
-        assert_eq!(library.intervals.len(), 3);
-        assert_eq!(library.workouts.len(), 1);
+        assert_eq!(library.intervals.len(), 4);
+        assert_eq!(library.workouts.len(), 4);
+        let ramp = &library.workouts["Ramp Test"];
+        assert_eq!(ramp.ftp_test, Some(FtpTest::Ramp));
+        assert_eq!(ramp.intervals[1].segments.len(), 30);
+        assert_eq!(library.workouts["Metcalfe"].ftp_test, None);
     }
 
     #[test]
@@ -716,10 +1569,7 @@ mod test {
           { duration = "5m", power_start = 0.85, power_end = 0.55 },
         ]"#;
 
-        let mut library = Library {
-            intervals: BTreeMap::new(),
-            workouts: BTreeMap::new(),
-        };
+        let mut library = Library::new();
         for file in vec![s1, s2].iter() {
             // This is a simplied version of reading in file contents then
             // parsing the contents into our data structure.
@@ -746,7 +1596,7 @@ mod test {
                 for mut shadow_workout in contents {
                     // Need a build and a validate method. Validate the duration
                     // and any interval templates passed in. Merge interval templates, etc.
-                    if let Err(_e) = shadow_workout.validate(&library.intervals) {
+                    if let Err(_e) = shadow_workout.validate(&library) {
                         // log error
                         continue;
                     }
@@ -821,10 +1671,7 @@ mod test {
           { duration = "5m", power_start = 0.85, power_end = 0.55 },
         ]"#;
 
-        let mut library = Library {
-            intervals: BTreeMap::new(),
-            workouts: BTreeMap::new(),
-        };
+        let mut library = Library::new();
         for file in vec![s1, s2].iter() {
             // This is a simplied version of reading in file contents then
             // parsing the contents into our data structure.
@@ -849,7 +1696,7 @@ mod test {
                 for mut shadow_workout in contents {
                     // Need a build and a validate method. Validate the duration
                     // and any interval templates passed in. Merge interval templates, etc.
-                    if let Err(_e) = shadow_workout.validate(&library.intervals) {
+                    if let Err(_e) = shadow_workout.validate(&library) {
                         // log error
                         continue;
                     }
@@ -885,10 +1732,7 @@ mod test {
           '10m@.55'
         ]"#;
 
-        let mut library = Library {
-            intervals: BTreeMap::new(),
-            workouts: BTreeMap::new(),
-        };
+        let mut library = Library::new();
 
         let sl: ShadowLibrary = toml::from_str(s1).unwrap();
         if let Some(contents) = sl.workouts {
@@ -911,6 +1755,455 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_interval_template_params() {
+        let s1 = r#"
+        [[ intervals ]]
+        name = "Over-Unders"
+        description = "Over-unders around FTP"
+        duration = "12m"
+        params = { under = 0.95, over = 1.05, time = "1m" }
+        segments = [
+          '2m @ {under}',
+          '{time} @ {over}',
+        ]
+        repeat = 4
+
+        [[ workouts ]]
+        name = "Short Over-Unders"
+        description = "Over-unders with 30s overs"
+        duration = "22m"
+        lap_each_interval = true
+        intervals = [
+          { name = "Over-Unders", params = { time = "30s", over = 1.1 } },
+          'Over-Unders',
+        ]"#;
+
+        let mut library = Library::new();
+        let sl: ShadowLibrary = toml::from_str(s1).unwrap();
+        assert!(library.load(sl, Source::User).is_empty());
+
+        let interval = library.intervals.get("Over-Unders").unwrap();
+        assert_eq!(interval.segments[1].duration, Duration(60));
+        assert_eq!(
+            interval.segments[1].power_start,
+            PowerTarget::Percentage(1.05)
+        );
+
+        let workout = library.workouts.get("Short Over-Unders").unwrap();
+        assert_eq!(
+            workout.intervals[0].duration,
+            Duration::from_str("10m").unwrap()
+        );
+        assert_eq!(workout.intervals[0].segments[1].duration, Duration(30));
+        assert_eq!(
+            workout.intervals[0].segments[1].power_start,
+            PowerTarget::Percentage(1.1)
+        );
+        assert_eq!(
+            workout.intervals[0].segments[0].power_start,
+            PowerTarget::Percentage(0.95)
+        );
+        assert_eq!(
+            workout.intervals[1].duration,
+            Duration::from_str("12m").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_interval_template_params_errors() {
+        // A param without a default is given by each workout, and an interval
+        // that fails to render only fails itself.
+        let s1 = r#"
+        [[ intervals ]]
+        name = "Efforts"
+        duration = "1m"
+        segments = ['1m @ {power}']
+
+        [[ intervals ]]
+        name = "Broken"
+        duration = "1m"
+        params = { power = "lots" }
+        segments = ['1m @ {power}']
+
+        [[ workouts ]]
+        name = "Threshold"
+        description = "Efforts at threshold"
+        duration = "1m"
+        lap_each_interval = false
+        intervals = [{ name = "Efforts", params = { power = 1.0 } }]
+
+        [[ workouts ]]
+        name = "VO2"
+        description = "Efforts at VO2"
+        duration = "1m"
+        lap_each_interval = false
+        intervals = [{ name = "Efforts", params = { power = 1.2 } }]
+
+        [[ workouts ]]
+        name = "No params"
+        description = "Uses the interval without its param"
+        duration = "1m"
+        lap_each_interval = false
+        intervals = ['Efforts']
+        "#;
+        let mut library = Library::new();
+        let errors = library.load(toml::from_str(s1).unwrap(), Source::User);
+        let failed: Vec<&str> = errors.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(failed, ["Broken", "No params"]);
+        assert!(library.intervals.contains_key("Efforts"));
+        assert_eq!(
+            library.workouts["VO2"].intervals[0].segments[0].power_start,
+            PowerTarget::Percentage(1.2)
+        );
+        assert_eq!(
+            library.workouts["Threshold"].intervals[0].segments[0].power_start,
+            PowerTarget::Percentage(1.0)
+        );
+
+        let s2 = r#"
+        [[ intervals ]]
+        name = "Repeats"
+        duration = "2m"
+        params = { on = "30s" }
+        segments = ['{on} @ 1.2', '30s @ 0.5']
+        repeat = 2
+
+        [[ workouts ]]
+        name = "Wrong duration"
+        description = "Param changes the interval duration"
+        duration = "2m"
+        lap_each_interval = false
+        intervals = [
+          { name = "Repeats", params = { on = "1m" } },
+        ]"#;
+        let mut library = Library::new();
+        let errors = library.load(toml::from_str(s2).unwrap(), Source::User);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].name, "Wrong duration");
+        assert!(library.workouts.is_empty());
+    }
+
+    #[test]
+    fn test_interval_template_extends() {
+        let s1 = r#"
+        [[ intervals ]]
+        name = "Hard Over-Unders"
+        extends = "Over-Unders"
+        params = { over = 1.1 }
+
+        [[ intervals ]]
+        name = "Long Over-Unders"
+        extends = "Over-Unders"
+        description = "Six sets"
+        repeat = 6
+
+        [[ intervals ]]
+        name = "Over-Unders"
+        description = "Over-unders around FTP"
+        duration = "12m"
+        params = { under = 0.95, over = 1.05 }
+        segments = [
+          '2m @ {under}',
+          '1m @ {over}',
+        ]
+        repeat = 4
+
+        [[ intervals ]]
+        name = "Nowhere"
+        extends = "Unknown"
+
+        [[ intervals ]]
+        name = "Cycle A"
+        extends = "Cycle B"
+
+        [[ intervals ]]
+        name = "Cycle B"
+        extends = "Cycle A"
+        "#;
+
+        let mut library = Library::new();
+        let errors = library.load(toml::from_str(s1).unwrap(), Source::User);
+        let mut names: Vec<&str> = errors.iter().map(|e| e.name.as_str()).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["Cycle A", "Cycle B", "Nowhere"]);
+
+        let hard = library.intervals.get("Hard Over-Unders").unwrap();
+        assert_eq!(hard.name, Some(String::from("Hard Over-Unders")));
+        assert_eq!(
+            hard.description,
+            Some(String::from("Over-unders around FTP"))
+        );
+        assert_eq!(hard.duration, Duration::from_str("12m").unwrap());
+        assert_eq!(hard.segments[0].power_start, PowerTarget::Percentage(0.95));
+        assert_eq!(hard.segments[1].power_start, PowerTarget::Percentage(1.1));
+
+        let long = library.intervals.get("Long Over-Unders").unwrap();
+        assert_eq!(long.description, Some(String::from("Six sets")));
+        assert_eq!(long.duration, Duration::from_str("18m").unwrap());
+        assert_eq!(long.segments[1].power_start, PowerTarget::Percentage(1.05));
+    }
+
+    #[test]
+    fn test_workout_includes_workout() {
+        let s1 = r#"
+        [[ intervals ]]
+        name = "Openers"
+        duration = "3m"
+        segments = ['1m @ 0.6', '30s @ 1.2', '1m30s @ 0.6']
+
+        [[ workouts ]]
+        name = "Threshold"
+        description = "Warmup, openers, then threshold"
+        duration = "33m"
+        lap_each_interval = true
+        intervals = [
+          { workout = "Warmup and Openers" },
+          '20m @ 0.95',
+        ]
+
+        [[ workouts ]]
+        name = "Warmup and Openers"
+        description = "Standard start to a session"
+        duration = "13m"
+        lap_each_interval = true
+        intervals = [
+          '10m @ 0.55..0.65',
+          'Openers',
+        ]
+
+        [[ workouts ]]
+        name = "Wrong duration"
+        description = "Doesn't account for the included workout"
+        duration = "20m"
+        lap_each_interval = true
+        intervals = [
+          { workout = "Warmup and Openers" },
+          '20m @ 0.95',
+        ]
+
+        [[ workouts ]]
+        name = "Missing"
+        description = "Includes a workout that doesn't exist"
+        duration = "10m"
+        lap_each_interval = true
+        intervals = [{ workout = "Nothing" }]
+
+        [[ workouts ]]
+        name = "Cycle A"
+        description = "Includes B"
+        duration = "10m"
+        lap_each_interval = true
+        intervals = [{ workout = "Cycle B" }]
+
+        [[ workouts ]]
+        name = "Cycle B"
+        description = "Includes A"
+        duration = "10m"
+        lap_each_interval = true
+        intervals = [{ workout = "Cycle A" }]
+
+        [[ workouts ]]
+        name = "Self"
+        description = "Includes itself"
+        duration = "10m"
+        lap_each_interval = true
+        intervals = [{ workout = "Self" }]
+        "#;
+
+        let mut library = Library::new();
+        let errors = library.load(toml::from_str(s1).unwrap(), Source::User);
+        let find = |name: &str| errors.iter().find(|e| e.name == name).unwrap().error;
+        assert_eq!(errors.len(), 5);
+        assert_eq!(
+            find("Wrong duration"),
+            "defined duration does not match sum of interval durations"
+        );
+        assert_eq!(find("Missing"), "included workout can't be found");
+        assert_eq!(find("Cycle A"), "included workouts form a cycle");
+        assert_eq!(find("Cycle B"), "included workouts form a cycle");
+        assert_eq!(find("Self"), "workout can't include itself");
+
+        assert_eq!(library.workouts.len(), 2);
+        let workout = library.workouts.get("Threshold").unwrap();
+        assert_eq!(workout.intervals.len(), 3);
+        assert_eq!(workout.intervals[1].segments.len(), 3);
+        assert_eq!(
+            workout.intervals[2].segments[0].power_start,
+            PowerTarget::Percentage(0.95)
+        );
+    }
+
+    #[test]
+    fn test_workout_interval_not_found() {
+        let s1 = r#"
+        [[ workouts ]]
+        name = "Unknown interval"
+        description = "References an interval that doesn't exist"
+        duration = "10m"
+        lap_each_interval = true
+        intervals = ['Nothing']
+        "#;
+
+        let mut library = Library::new();
+        let errors = library.load(toml::from_str(s1).unwrap(), Source::User);
+        assert_eq!(errors[0].error, "interval can't be found");
+    }
+
+    #[test]
+    fn test_library_sources() {
+        let team = r#"
+        [[ intervals ]]
+        name = "Warmup"
+        description = "Team warmup"
+        duration = "15m"
+        segments = ['15m @ 0.6']
+
+        [[ intervals ]]
+        name = "Openers"
+        duration = "1m"
+        segments = ['1m @ 1.1']
+
+        [[ intervals ]]
+        name = "Openers"
+        duration = "2m"
+        segments = ['2m @ 1.1']
+        "#;
+
+        let user = r#"
+        [[ intervals ]]
+        name = "Warmup"
+        description = "My warmup"
+        duration = "5m"
+        segments = ['5m @ 0.5']
+
+        [[ workouts ]]
+        name = "Warmups"
+        description = "Every warmup"
+        duration = "31m"
+        lap_each_interval = true
+        intervals = [
+          'Warmup',
+          'team/Warmup',
+          'builtin/Warmup',
+          'Openers',
+        ]
+
+        [[ workouts ]]
+        name = "Metcalfe"
+        description = "My Metcalfe"
+        duration = "5m"
+        lap_each_interval = true
+        intervals = ['Warmup']
+        "#;
+
+        let mut library = Library::default();
+        // Warmups can't load until the team library has been loaded.
+        let errors = library.load(toml::from_str(user).unwrap(), Source::User);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].name, "Warmups");
+        let errors = library.load(toml::from_str(team).unwrap(), Source::Team);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].name, "Openers");
+        assert_eq!(errors[0].source, Source::Team);
+
+        // User templates take precedence regardless of load order.
+        let warmup = library.interval("Warmup").unwrap();
+        assert_eq!(warmup.source, Source::User);
+        assert_eq!(warmup.duration, Duration::from_str("5m").unwrap());
+        assert_eq!(
+            library.interval("team/Warmup").unwrap().source,
+            Source::Team
+        );
+        assert_eq!(
+            library.interval("builtin/Warmup").unwrap().source,
+            Source::BuiltIn
+        );
+        assert!(library.interval("user/Openers").is_none());
+        assert_eq!(library.workout("Metcalfe").unwrap().source, Source::User);
+        assert_eq!(
+            library.workout("builtin/Metcalfe").unwrap().source,
+            Source::BuiltIn
+        );
+
+        let mut shadowed: Vec<String> = library.shadowed.iter().map(|s| s.to_string()).collect();
+        shadowed.sort();
+        assert_eq!(
+            shadowed,
+            vec![
+                "interval user/Warmup shadows builtin/Warmup",
+                "interval user/Warmup shadows team/Warmup",
+                "workout user/Metcalfe shadows builtin/Metcalfe",
+            ]
+        );
+
+        // Loading the user library again reports Warmup as a duplicate but
+        // Warmups loads now that Openers is in the library.
+        assert!(library.workout("Warmups").is_none());
+        let errors = library.load(
+            toml::from_str(&user.replace("Metcalfe", "Other")).unwrap(),
+            Source::User,
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].name, "Warmup");
+        let workout = library.workout("Warmups").unwrap();
+        assert_eq!(workout.intervals.len(), 4);
+        assert_eq!(
+            workout.intervals[1].description,
+            Some(String::from("Team warmup"))
+        );
+    }
+
+    #[test]
+    fn test_shadow_library_from_dir() {
+        let dir = TestDir::new("library");
+        fs::write(
+            dir.join("a.toml"),
+            "[[ intervals ]]\nname = 'A'\nduration = '1m'\nsegments = ['1m @ 100']",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("b.toml"),
+            "[[ intervals ]]\nname = 'B'\nduration = '1m'\nsegments = ['1m @ 100']",
+        )
+        .unwrap();
+        fs::write(dir.join("c.toml"), "[[ intervals ]]\nname = ").unwrap();
+        fs::write(dir.join("notes.txt"), "not a library file").unwrap();
+
+        let (shadow_library, errors) = ShadowLibrary::from_dir(&dir);
+        assert_eq!(shadow_library.intervals.unwrap().len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, dir.join("c.toml"));
+    }
+
+    #[test]
+    fn test_file_stamps() {
+        let dir = TestDir::new("stamps");
+        let interval = "[[ intervals ]]\nname = 'A'\nduration = '1m'\nsegments = ['1m @ 100']";
+        fs::write(dir.join("a.toml"), interval).unwrap();
+        fs::write(dir.join("notes.txt"), "not a library file").unwrap();
+        let dirs = vec![dir.to_path_buf()];
+        let stamps = file_stamps(&dirs);
+        assert_eq!(stamps.len(), 1);
+        assert_eq!(file_stamps(&dirs), stamps);
+
+        // A file saved again is picked up even within the same second.
+        fs::write(dir.join("a.toml"), format!("{}\n", interval)).unwrap();
+        let saved = file_stamps(&dirs);
+        assert_ne!(saved, stamps);
+
+        // Added and removed files are picked up, other files aren't.
+        fs::write(dir.join("b.toml"), interval).unwrap();
+        let added = file_stamps(&dirs);
+        assert_eq!(added.len(), 2);
+        fs::write(dir.join("notes.txt"), "still not a library file").unwrap();
+        assert_eq!(file_stamps(&dirs), added);
+        fs::remove_file(dir.join("a.toml")).unwrap();
+        let removed = file_stamps(&dirs);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].0, dir.join("b.toml"));
+    }
+
     #[test]
     fn test_interval_template() {
         let it_str = r#"
@@ -1010,4 +2303,135 @@ mod test {
         assert_eq!(foo.segments[5].power_start, PowerTarget::Watts(200));
         assert_eq!(foo.segments[6].power_end, PowerTarget::Percentage(0.85));
     }
+
+    #[test]
+    fn test_segment_cadence_heart_rate_rpe() {
+        let seg_str = r#"
+        segments = [
+          '10m @ 0.75 90rpm',
+          '20m @ 140-150bpm',
+          '20m @ 0.85-0.9 LTHR',
+          '5m @ free 7 RPE',
+          '1m @ 300 100-110 rpm 165bpm',
+          { duration = '5m', power_start = 'free', power_end = 'free', cadence = '85-95rpm' },
+        ]"#;
+
+        #[derive(Deserialize)]
+        struct Foo {
+            segments: Vec<Segment>,
+        }
+
+        let foo: Foo = toml::from_str(seg_str).unwrap();
+
+        assert_eq!(foo.segments.len(), 6);
+        assert_eq!(foo.segments[0].power_start, PowerTarget::Percentage(0.75));
+        assert_eq!(foo.segments[0].cadence, Some(CadenceTarget::Rpm(90)));
+        assert_eq!(foo.segments[0].heart_rate, None);
+        assert_eq!(foo.segments[1].power_start, PowerTarget::Free);
+        assert_eq!(
+            foo.segments[1].heart_rate,
+            Some(HeartRateTarget::BpmRange(140, 150))
+        );
+        assert_eq!(
+            foo.segments[2].heart_rate,
+            Some(HeartRateTarget::LthrRange(0.85, 0.9))
+        );
+        assert_eq!(foo.segments[3].power_end, PowerTarget::Free);
+        assert_eq!(foo.segments[3].rpe, Some(Rpe(7)));
+        assert_eq!(foo.segments[4].power_start, PowerTarget::Watts(300));
+        assert_eq!(
+            foo.segments[4].cadence,
+            Some(CadenceTarget::Range(100, 110))
+        );
+        assert_eq!(foo.segments[4].heart_rate, Some(HeartRateTarget::Bpm(165)));
+        assert_eq!(foo.segments[5].power_start, PowerTarget::Free);
+        assert_eq!(foo.segments[5].cadence, Some(CadenceTarget::Range(85, 95)));
+    }
+
+    #[test]
+    fn test_segment_power_range() {
+        let seg_str = r#"
+        segments = [
+          '20m @ 0.88..0.94',
+          '10m @ 200..220 90rpm',
+          { duration = '5m', power_start = '0.5..0.6', power_end = '0.7..0.8' },
+        ]"#;
+
+        #[derive(Deserialize)]
+        struct Foo {
+            segments: Vec<Segment>,
+        }
+
+        let foo: Foo = toml::from_str(seg_str).unwrap();
+        assert_eq!(
+            foo.segments[0].power_start,
+            PowerTarget::PercentageRange(0.88, 0.94)
+        );
+        assert_eq!(foo.segments[1].power_end, PowerTarget::WattsRange(200, 220));
+        assert_eq!(foo.segments[1].cadence, Some(CadenceTarget::Rpm(90)));
+        assert_eq!(
+            foo.segments[2].power_end,
+            PowerTarget::PercentageRange(0.7, 0.8)
+        );
+    }
+
+    #[test]
+    fn test_segment_threshold() {
+        let segment: Segment = "3m @ 1.05 MAP 100rpm".parse().unwrap();
+        assert_eq!(
+            segment.power_start,
+            PowerTarget::Relative(1.05, Threshold::Map)
+        );
+        assert_eq!(segment.cadence, Some(CadenceTarget::Rpm(100)));
+        let segment: Segment = "1m @ 1.2..1.3cp".parse().unwrap();
+        assert_eq!(
+            segment.power_end,
+            PowerTarget::RelativeRange(1.2, 1.3, Threshold::Cp)
+        );
+    }
+
+    #[test]
+    fn test_segment_errors() {
+        assert!("10m".parse::<Segment>().is_err());
+        assert!("10m @".parse::<Segment>().is_err());
+        assert!("10m @ 200 250".parse::<Segment>().is_err());
+        assert!("10m @ 90rpx".parse::<Segment>().is_err());
+    }
+
+    #[test]
+    fn test_workout_segment_with_targets() {
+        let s1 = r#"
+        [[ workouts ]]
+        name = "Cadence drills"
+        description = "Z2 with high cadence"
+        duration = "30m"
+        lap_each_interval = true
+        intervals = [
+          '10m @ 140-150bpm',
+          '10m @ 0.65 100rpm',
+          { duration = "10m", power_start = 0.65, power_end = 0.55, cadence = "85-95rpm" },
+        ]"#;
+
+        let sl: ShadowLibrary = toml::from_str(s1).unwrap();
+        let mut workouts = sl.workouts.unwrap();
+        let mut shadow_workout = workouts.remove(0);
+        assert!(shadow_workout.validate(&Library::new()).is_ok());
+        let workout = shadow_workout.build_workout_template();
+        assert_eq!(
+            workout.intervals[0].segments[0].heart_rate,
+            Some(HeartRateTarget::BpmRange(140, 150))
+        );
+        assert_eq!(
+            workout.intervals[0].segments[0].power_start,
+            PowerTarget::Free
+        );
+        assert_eq!(
+            workout.intervals[1].segments[0].cadence,
+            Some(CadenceTarget::Rpm(100))
+        );
+        assert_eq!(
+            workout.intervals[2].segments[0].cadence,
+            Some(CadenceTarget::Range(85, 95))
+        );
+    }
 }
@@ -0,0 +1,167 @@
+// A PowerCurve is the best average power held for each of a set of durations
+// from 1 second to an hour, for a ride or the best of every ride in a
+// profile's history.
+//
+// Each duration is worked out with a sliding window that adds the newest
+// second and drops the oldest, so a curve takes one pass over the ride for
+// each duration rather than averaging every window from scratch. A 2 hour
+// ride is 14 passes of 7200 seconds.
+use serde_derive::{Deserialize, Serialize};
+
+use crate::application::session::Record;
+
+// Durations in seconds the curve is worked out for.
+pub const DURATIONS: [u32; 14] = [
+    1, 5, 10, 15, 30, 60, 120, 180, 300, 600, 1200, 1800, 2700, 3600,
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PowerCurve {
+    // Seconds and the best average watts held for that long, shortest first.
+    // Durations longer than the ride aren't included.
+    best: Vec<(u32, u16)>,
+}
+
+impl PowerCurve {
+    pub fn new(records: &[Record]) -> Self {
+        let power: Vec<Option<u16>> = records.iter().map(|r| r.sample.power).collect();
+        let best = DURATIONS
+            .iter()
+            .filter_map(|seconds| {
+                best_average(&power, *seconds as usize)
+                    .map(|watts| (*seconds, watts.round() as u16))
+            })
+            .collect();
+        Self { best }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.best.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u16)> + '_ {
+        self.best.iter().copied()
+    }
+
+    pub fn best(&self, seconds: u32) -> Option<u16> {
+        self.iter()
+            .find(|(s, _)| *s == seconds)
+            .map(|(_, watts)| watts)
+    }
+
+    // Keeps the best of this curve and another for each duration.
+    pub fn merge(&mut self, other: &PowerCurve) {
+        for (seconds, watts) in other.iter() {
+            match self.best.binary_search_by_key(&seconds, |(s, _)| *s) {
+                Ok(i) => self.best[i].1 = self.best[i].1.max(watts),
+                Err(i) => self.best.insert(i, (seconds, watts)),
+            }
+        }
+    }
+
+    // Durations where this curve beats the previous best. A duration that
+    // has never been ridden before isn't a record so a first ride isn't
+    // nothing but records.
+    pub fn records(&self, previous: &PowerCurve) -> Vec<(u32, u16)> {
+        self.iter()
+            .filter(
+                |(seconds, watts)| matches!(previous.best(*seconds), Some(best) if *watts > best),
+            )
+            .collect()
+    }
+}
+
+// 5s, 1m, 1h, 1m30s
+pub fn duration_label(seconds: u32) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let mut label = String::new();
+    for (value, unit) in [(hours, "h"), (minutes, "m"), (seconds, "s")].iter() {
+        if *value > 0 {
+            label.push_str(&format!("{}{}", value, unit));
+        }
+    }
+    label
+}
+
+// Best average power over a number of seconds. Dropouts count as no power.
+pub fn best_average(power: &[Option<u16>], seconds: usize) -> Option<f32> {
+    if seconds == 0 || power.len() < seconds {
+        return None;
+    }
+    let watts = |i: usize| u32::from(power[i].unwrap_or(0));
+    let mut sum: u32 = (0..seconds).map(watts).sum();
+    let mut best = sum;
+    for i in seconds..power.len() {
+        sum = sum + watts(i) - watts(i - seconds);
+        best = best.max(sum);
+    }
+    Some(best as f32 / seconds as f32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::session::RecordBuilder;
+
+    #[test]
+    fn test_best_average() {
+        let power = vec![Some(100), None, Some(300), Some(200), Some(100)];
+        assert_eq!(best_average(&power, 2), Some(250.0));
+        assert_eq!(best_average(&power, 5), Some(140.0));
+        assert_eq!(best_average(&power, 6), None);
+    }
+
+    #[test]
+    fn test_power_curve() {
+        let ride = RecordBuilder::default()
+            .power(600, None, Some(150))
+            .power(5, None, Some(900))
+            .power(295, None, Some(300))
+            .power(600, None, Some(150))
+            .build();
+        let curve = PowerCurve::new(&ride);
+        assert_eq!(curve.best(1), Some(900));
+        assert_eq!(curve.best(5), Some(900));
+        // 5 seconds at 900W and 295 seconds at 300W.
+        assert_eq!(curve.best(300), Some(310));
+        assert_eq!(curve.best(1200), Some(190));
+        // Longer than the ride.
+        assert_eq!(curve.best(1800), None);
+        assert_eq!(curve.iter().count(), 11);
+
+        // A 2 hour ride is quick to work out.
+        let long = RecordBuilder::default()
+            .power(7200, None, Some(200))
+            .build();
+        assert_eq!(PowerCurve::new(&long).best(3600), Some(200));
+    }
+
+    #[test]
+    fn test_power_curve_merge_and_records() {
+        let mut best =
+            PowerCurve::new(&RecordBuilder::default().power(300, None, Some(300)).build());
+        let ride = PowerCurve::new(
+            &RecordBuilder::default()
+                .power(10, None, Some(600))
+                .power(590, None, Some(250))
+                .build(),
+        );
+        // The ride has the best minute but not the best 5 minutes, and 10
+        // minutes hasn't been ridden before.
+        let seconds: Vec<u32> = ride.records(&best).iter().map(|(s, _)| *s).collect();
+        assert_eq!(seconds, vec![1, 5, 10, 15, 30, 60]);
+        best.merge(&ride);
+        assert_eq!(best.best(10), Some(600));
+        assert_eq!(best.best(300), Some(300));
+        assert_eq!(best.best(600), Some(256));
+        assert!(ride.records(&best).is_empty());
+        assert!(ride.records(&PowerCurve::default()).is_empty());
+    }
+
+    #[test]
+    fn test_duration_label() {
+        assert_eq!(duration_label(5), "5s");
+        assert_eq!(duration_label(90), "1m30s");
+        assert_eq!(duration_label(3600), "1h");
+    }
+}
@@ -0,0 +1,485 @@
+// ProfileSettings are a rider's preferences for how things are shown and how
+// the trainer is controlled during a ride. Settings are saved with the
+// profile.
+//
+// [profiles.settings]
+// units = "imperial"
+// erg_mode = false
+// power_smoothing = 10
+// ride_metrics = ["power", "target", "heart_rate", "remaining"]
+//
+// [profiles.settings.ride_keys]
+// pause = "p"
+// skip_segment = "tab"
+use std::convert::TryFrom;
+use std::fmt;
+
+use iced_native::keyboard::KeyCode;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::application::types::WeightUnit;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    Metric,
+    Imperial,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self::Metric
+    }
+}
+
+impl Units {
+    pub fn weight_unit(self) -> WeightUnit {
+        match self {
+            Self::Metric => WeightUnit::Kg,
+            Self::Imperial => WeightUnit::Lb,
+        }
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Metric => write!(f, "Metric"),
+            Self::Imperial => write!(f, "Imperial"),
+        }
+    }
+}
+
+// Values that can be shown while riding.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RideMetric {
+    Elapsed,
+    Remaining,
+    Lap,
+    LapPower,
+    Target,
+    Power,
+    WattsPerKg,
+    HeartRate,
+    Cadence,
+    WPrimeBalance,
+}
+
+impl RideMetric {
+    pub const ALL: [RideMetric; 10] = [
+        Self::Elapsed,
+        Self::Remaining,
+        Self::Lap,
+        Self::LapPower,
+        Self::Target,
+        Self::Power,
+        Self::WattsPerKg,
+        Self::HeartRate,
+        Self::Cadence,
+        Self::WPrimeBalance,
+    ];
+}
+
+impl fmt::Display for RideMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Elapsed => write!(f, "Elapsed"),
+            Self::Remaining => write!(f, "Remaining"),
+            Self::Lap => write!(f, "Lap"),
+            Self::LapPower => write!(f, "Lap power"),
+            Self::Target => write!(f, "Target"),
+            Self::Power => write!(f, "Power"),
+            Self::WattsPerKg => write!(f, "W/kg"),
+            Self::HeartRate => write!(f, "Heart rate"),
+            Self::Cadence => write!(f, "Cadence"),
+            Self::WPrimeBalance => write!(f, "W' balance"),
+        }
+    }
+}
+
+// Seconds of power averaged for the power shown while riding. 1 shows power
+// as it is read.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub struct PowerSmoothing(u8);
+
+impl PowerSmoothing {
+    pub const CHOICES: [u8; 4] = [1, 3, 5, 10];
+
+    pub fn seconds(self) -> u8 {
+        self.0
+    }
+}
+
+impl Default for PowerSmoothing {
+    fn default() -> Self {
+        Self(3)
+    }
+}
+
+impl TryFrom<u8> for PowerSmoothing {
+    type Error = &'static str;
+
+    fn try_from(seconds: u8) -> Result<Self, Self::Error> {
+        match Self::CHOICES.contains(&seconds) {
+            true => Ok(Self(seconds)),
+            false => Err("power smoothing must be 1, 3, 5 or 10 seconds"),
+        }
+    }
+}
+
+impl From<PowerSmoothing> for u8 {
+    fn from(smoothing: PowerSmoothing) -> Self {
+        smoothing.0
+    }
+}
+
+// Things the rider can do from the keyboard while riding.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RideAction {
+    Pause,
+    TargetUp,
+    TargetDown,
+    IntensityUp,
+    IntensityDown,
+    Lap,
+    SkipSegment,
+    Erg,
+    Help,
+}
+
+impl RideAction {
+    pub const ALL: [RideAction; 9] = [
+        Self::Pause,
+        Self::TargetUp,
+        Self::TargetDown,
+        Self::IntensityUp,
+        Self::IntensityDown,
+        Self::Lap,
+        Self::SkipSegment,
+        Self::Erg,
+        Self::Help,
+    ];
+}
+
+impl fmt::Display for RideAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pause => write!(f, "Pause or resume"),
+            Self::TargetUp => write!(f, "Raise target 5W"),
+            Self::TargetDown => write!(f, "Lower target 5W"),
+            Self::IntensityUp => write!(f, "Raise intensity 1%"),
+            Self::IntensityDown => write!(f, "Lower intensity 1%"),
+            Self::Lap => write!(f, "Start a lap"),
+            Self::SkipSegment => write!(f, "Skip to the next segment"),
+            Self::Erg => write!(f, "ERG mode on or off"),
+            Self::Help => write!(f, "Show or hide keys"),
+        }
+    }
+}
+
+// Names of the keys that can control a ride.
+const KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::Key1, "key1"),
+    (KeyCode::Key2, "key2"),
+    (KeyCode::Key3, "key3"),
+    (KeyCode::Key4, "key4"),
+    (KeyCode::Key5, "key5"),
+    (KeyCode::Key6, "key6"),
+    (KeyCode::Key7, "key7"),
+    (KeyCode::Key8, "key8"),
+    (KeyCode::Key9, "key9"),
+    (KeyCode::Key0, "key0"),
+    (KeyCode::A, "a"),
+    (KeyCode::B, "b"),
+    (KeyCode::C, "c"),
+    (KeyCode::D, "d"),
+    (KeyCode::E, "e"),
+    (KeyCode::F, "f"),
+    (KeyCode::G, "g"),
+    (KeyCode::H, "h"),
+    (KeyCode::I, "i"),
+    (KeyCode::J, "j"),
+    (KeyCode::K, "k"),
+    (KeyCode::L, "l"),
+    (KeyCode::M, "m"),
+    (KeyCode::N, "n"),
+    (KeyCode::O, "o"),
+    (KeyCode::P, "p"),
+    (KeyCode::Q, "q"),
+    (KeyCode::R, "r"),
+    (KeyCode::S, "s"),
+    (KeyCode::T, "t"),
+    (KeyCode::U, "u"),
+    (KeyCode::V, "v"),
+    (KeyCode::W, "w"),
+    (KeyCode::X, "x"),
+    (KeyCode::Y, "y"),
+    (KeyCode::Z, "z"),
+    (KeyCode::F1, "f1"),
+    (KeyCode::F2, "f2"),
+    (KeyCode::F3, "f3"),
+    (KeyCode::F4, "f4"),
+    (KeyCode::F5, "f5"),
+    (KeyCode::F6, "f6"),
+    (KeyCode::F7, "f7"),
+    (KeyCode::F8, "f8"),
+    (KeyCode::F9, "f9"),
+    (KeyCode::F10, "f10"),
+    (KeyCode::F11, "f11"),
+    (KeyCode::F12, "f12"),
+    (KeyCode::Escape, "escape"),
+    (KeyCode::Space, "space"),
+    (KeyCode::Enter, "enter"),
+    (KeyCode::Tab, "tab"),
+    (KeyCode::Backspace, "backspace"),
+    (KeyCode::Insert, "insert"),
+    (KeyCode::Delete, "delete"),
+    (KeyCode::Home, "home"),
+    (KeyCode::End, "end"),
+    (KeyCode::PageUp, "pageup"),
+    (KeyCode::PageDown, "pagedown"),
+    (KeyCode::Up, "up"),
+    (KeyCode::Down, "down"),
+    (KeyCode::Left, "left"),
+    (KeyCode::Right, "right"),
+    (KeyCode::Numpad0, "numpad0"),
+    (KeyCode::Numpad1, "numpad1"),
+    (KeyCode::Numpad2, "numpad2"),
+    (KeyCode::Numpad3, "numpad3"),
+    (KeyCode::Numpad4, "numpad4"),
+    (KeyCode::Numpad5, "numpad5"),
+    (KeyCode::Numpad6, "numpad6"),
+    (KeyCode::Numpad7, "numpad7"),
+    (KeyCode::Numpad8, "numpad8"),
+    (KeyCode::Numpad9, "numpad9"),
+    (KeyCode::NumpadAdd, "numpadadd"),
+    (KeyCode::NumpadSubtract, "numpadsubtract"),
+    (KeyCode::NumpadMultiply, "numpadmultiply"),
+    (KeyCode::NumpadDivide, "numpaddivide"),
+    (KeyCode::NumpadEnter, "numpadenter"),
+    (KeyCode::Minus, "minus"),
+    (KeyCode::Equals, "equals"),
+    (KeyCode::Comma, "comma"),
+    (KeyCode::Period, "period"),
+    (KeyCode::Slash, "slash"),
+    (KeyCode::Backslash, "backslash"),
+    (KeyCode::Semicolon, "semicolon"),
+    (KeyCode::Apostrophe, "apostrophe"),
+    (KeyCode::Grave, "grave"),
+    (KeyCode::LBracket, "lbracket"),
+    (KeyCode::RBracket, "rbracket"),
+];
+
+// Name of a key that can control a ride.
+pub fn key_name(key_code: KeyCode) -> Option<&'static str> {
+    KEYS.iter()
+        .find(|(code, _)| *code == key_code)
+        .map(|(_, name)| *name)
+}
+
+// Key for each ride action. Keys are named in lowercase, i.e. "space", "up",
+// "pageup", "l", "key1" or "f1". A key bound to more than one action does the
+// first action in RideAction::ALL.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RideKeys {
+    pub pause: String,
+    pub target_up: String,
+    pub target_down: String,
+    pub intensity_up: String,
+    pub intensity_down: String,
+    pub lap: String,
+    pub skip_segment: String,
+    pub erg: String,
+    pub help: String,
+}
+
+impl Default for RideKeys {
+    fn default() -> Self {
+        Self {
+            pause: String::from("space"),
+            target_up: String::from("up"),
+            target_down: String::from("down"),
+            intensity_up: String::from("right"),
+            intensity_down: String::from("left"),
+            lap: String::from("l"),
+            skip_segment: String::from("n"),
+            erg: String::from("e"),
+            help: String::from("h"),
+        }
+    }
+}
+
+impl RideKeys {
+    pub fn key(&self, action: RideAction) -> &str {
+        match action {
+            RideAction::Pause => &self.pause,
+            RideAction::TargetUp => &self.target_up,
+            RideAction::TargetDown => &self.target_down,
+            RideAction::IntensityUp => &self.intensity_up,
+            RideAction::IntensityDown => &self.intensity_down,
+            RideAction::Lap => &self.lap,
+            RideAction::SkipSegment => &self.skip_segment,
+            RideAction::Erg => &self.erg,
+            RideAction::Help => &self.help,
+        }
+    }
+
+    // Action bound to a key, ignoring case.
+    pub fn action(&self, key: &str) -> Option<RideAction> {
+        RideAction::ALL
+            .iter()
+            .copied()
+            .find(|action| self.key(*action).eq_ignore_ascii_case(key))
+    }
+
+    // Actions bound to a key that isn't named in KEYS, which can't be pressed.
+    pub fn unknown(&self) -> Vec<RideAction> {
+        RideAction::ALL
+            .iter()
+            .copied()
+            .filter(|action| {
+                let key = self.key(*action);
+                !KEYS.iter().any(|(_, name)| name.eq_ignore_ascii_case(key))
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProfileSettings {
+    pub units: Units,
+    // Whether rides start with the trainer holding the target (ERG mode) or
+    // free so the rider controls resistance.
+    pub erg_mode: bool,
+    pub power_smoothing: PowerSmoothing,
+    // Metrics shown while riding in the order they are shown.
+    pub ride_metrics: Vec<RideMetric>,
+    pub ride_keys: RideKeys,
+}
+
+impl Default for ProfileSettings {
+    fn default() -> Self {
+        Self {
+            units: Units::default(),
+            erg_mode: true,
+            power_smoothing: PowerSmoothing::default(),
+            ride_metrics: vec![
+                RideMetric::Elapsed,
+                RideMetric::Remaining,
+                RideMetric::Lap,
+                RideMetric::Target,
+                RideMetric::Power,
+                RideMetric::HeartRate,
+                RideMetric::Cadence,
+            ],
+            ride_keys: RideKeys::default(),
+        }
+    }
+}
+
+impl ProfileSettings {
+    // Shows or hides a ride metric. Shown metrics keep the order of
+    // RideMetric::ALL.
+    pub fn show_metric(&mut self, metric: RideMetric, show: bool) {
+        self.ride_metrics.retain(|m| *m != metric);
+        if show {
+            let position = |m: &RideMetric| RideMetric::ALL.iter().position(|a| a == m);
+            let i = self
+                .ride_metrics
+                .iter()
+                .position(|m| position(m) > position(&metric))
+                .unwrap_or(self.ride_metrics.len());
+            self.ride_metrics.insert(i, metric);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_profile_settings_deserialize() {
+        let settings: ProfileSettings = toml::from_str(
+            r#"
+            units = "imperial"
+            power_smoothing = 10
+            ride_metrics = ["power", "watts_per_kg"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings.units.weight_unit(), WeightUnit::Lb);
+        assert!(settings.erg_mode);
+        assert_eq!(settings.power_smoothing.seconds(), 10);
+        assert_eq!(
+            settings.ride_metrics,
+            vec![RideMetric::Power, RideMetric::WattsPerKg]
+        );
+        assert!(toml::from_str::<ProfileSettings>("power_smoothing = 7").is_err());
+        assert_eq!(
+            toml::from_str::<ProfileSettings>("").unwrap(),
+            ProfileSettings::default()
+        );
+    }
+
+    #[test]
+    fn test_profile_settings_show_metric() {
+        let mut settings = ProfileSettings {
+            ride_metrics: vec![RideMetric::Lap, RideMetric::HeartRate],
+            ..Default::default()
+        };
+        settings.show_metric(RideMetric::Power, true);
+        settings.show_metric(RideMetric::Elapsed, true);
+        settings.show_metric(RideMetric::Cadence, true);
+        settings.show_metric(RideMetric::Power, true);
+        settings.show_metric(RideMetric::Lap, false);
+        assert_eq!(
+            settings.ride_metrics,
+            vec![
+                RideMetric::Elapsed,
+                RideMetric::Power,
+                RideMetric::HeartRate,
+                RideMetric::Cadence
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ride_keys() {
+        let settings: ProfileSettings = toml::from_str(
+            r#"
+            [ride_keys]
+            pause = "P"
+            lap = "space"
+            "#,
+        )
+        .unwrap();
+        let keys = &settings.ride_keys;
+        assert_eq!(keys.action("p"), Some(RideAction::Pause));
+        assert_eq!(keys.action("up"), Some(RideAction::TargetUp));
+        assert_eq!(keys.action("space"), Some(RideAction::Lap));
+        assert_eq!(keys.action("x"), None);
+        // The first action wins when a key is bound twice.
+        let keys = RideKeys {
+            erg: String::from("l"),
+            ..Default::default()
+        };
+        assert_eq!(keys.action("L"), Some(RideAction::Lap));
+        assert_eq!(keys.key(RideAction::Erg), "l");
+        assert!(keys.unknown().is_empty());
+
+        assert_eq!(key_name(KeyCode::PageUp), Some("pageup"));
+        assert_eq!(key_name(KeyCode::Key1), Some("key1"));
+        assert_eq!(key_name(KeyCode::Mute), None);
+        let keys = RideKeys {
+            pause: String::from("spacebar"),
+            help: String::from("F1"),
+            ..Default::default()
+        };
+        assert_eq!(keys.unknown(), vec![RideAction::Pause]);
+    }
+}
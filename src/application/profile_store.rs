@@ -0,0 +1,348 @@
+// ProfileStore owns the user profiles and which one is active. Profiles are
+// looked up by a ProfileId handed out when a profile is added to the store so
+// adding or deleting a profile never changes which profile an ID refers to.
+// IDs are saved with the profile so they also last from one run to the next,
+// and rides are kept by the ID of the profile that rode them. New IDs are
+// taken from the clock so a deleted profile's ID isn't given to a new profile.
+//
+// The store keeps these true no matter what is done to it:
+// - Every profile has a name and no two profiles share a name.
+// - There is an active profile whenever there are profiles, and only the
+//   active profile has its active flag set.
+use std::path::Path;
+
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::application::user_profile::{load_profiles, save_profiles, UserProfile};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct ProfileId(u64);
+
+#[derive(Debug, Clone, Default)]
+pub struct ProfileStore {
+    // Profiles in the order they were added.
+    profiles: Vec<(ProfileId, UserProfile)>,
+    active: Option<ProfileId>,
+    next_id: u64,
+}
+
+impl ProfileStore {
+    // Builds a store from saved profiles. The first profile marked active is
+    // made the active profile, or the first profile if none are marked.
+    // Profiles without a name or with a name already taken are renamed so no
+    // saved profile is lost. Profiles keep their saved ID, and profiles
+    // without one or sharing one are given a new ID.
+    pub fn from_profiles(profiles: Vec<UserProfile>) -> Self {
+        let mut store = Self::default();
+        let mut active = None;
+        for mut profile in profiles {
+            let name = match profile.name.as_str() {
+                "" => String::from("Unnamed"),
+                name => name.to_string(),
+            };
+            profile.name = store.unique_name(&name);
+            if profile.name != name {
+                warn!(
+                    "Renamed duplicate user profile {} to {}",
+                    name, profile.name
+                );
+            }
+            // Keys that can't be pressed are left bound so the profile saves
+            // the way it was written.
+            for action in profile.settings.ride_keys.unknown() {
+                warn!(
+                    "Unknown key {} for {} in user profile {}",
+                    profile.settings.ride_keys.key(action),
+                    action,
+                    profile.name
+                );
+            }
+            let is_active = profile.active;
+            let id = store.insert(profile);
+            match (is_active, active) {
+                (true, None) => active = Some(id),
+                (true, Some(_)) => {
+                    warn!(
+                        "Multiple user profiles set as active. Leaving first profile set as active"
+                    )
+                }
+                _ => {}
+            }
+        }
+        let first = store.profiles.first().map(|(id, _)| *id);
+        store.set_active(active.or(first));
+        store
+    }
+
+    // Loads the store from a profiles file. Profiles given an ID as they're
+    // loaded are saved straight away so they keep it.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let profiles = load_profiles(path)?;
+        let new_ids = profiles.iter().any(|p| p.id.is_none());
+        let store = Self::from_profiles(profiles);
+        if new_ids {
+            save_profiles(path, &store.to_profiles())?;
+        }
+        Ok(store)
+    }
+
+    // Profiles to save with the active profile marked.
+    pub fn to_profiles(&self) -> Vec<UserProfile> {
+        self.profiles.iter().map(|(_, p)| p.clone()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ProfileId, &UserProfile)> {
+        self.profiles.iter().map(|(id, p)| (*id, p))
+    }
+
+    pub fn get(&self, id: ProfileId) -> Option<&UserProfile> {
+        self.position(id).map(|i| &self.profiles[i].1)
+    }
+
+    pub fn find(&self, name: &str) -> Option<ProfileId> {
+        self.iter().find(|(_, p)| p.name == name).map(|(id, _)| id)
+    }
+
+    pub fn active_id(&self) -> Option<ProfileId> {
+        self.active
+    }
+
+    pub fn active(&self) -> Option<&UserProfile> {
+        self.active.and_then(|id| self.get(id))
+    }
+
+    // Adds a profile and makes it the active profile.
+    pub fn add(&mut self, profile: UserProfile) -> Result<ProfileId, &'static str> {
+        self.check_name(&profile.name, None)?;
+        let id = self.insert(profile);
+        self.set_active(Some(id));
+        Ok(id)
+    }
+
+    // Replaces a profile. Whether the profile is active is kept.
+    pub fn update(&mut self, id: ProfileId, profile: UserProfile) -> Result<(), &'static str> {
+        let i = self.position(id).ok_or("profile can't be found")?;
+        self.check_name(&profile.name, Some(id))?;
+        self.profiles[i].1 = UserProfile {
+            id: Some(id),
+            active: self.active == Some(id),
+            ..profile
+        };
+        Ok(())
+    }
+
+    // Deletes a profile. Deleting the active profile makes the profile after
+    // it active, or the one before it if it was the last profile.
+    pub fn delete(&mut self, id: ProfileId) -> Result<UserProfile, &'static str> {
+        let i = self.position(id).ok_or("profile can't be found")?;
+        let (_, profile) = self.profiles.remove(i);
+        if self.active == Some(id) {
+            let next = self
+                .profiles
+                .get(i)
+                .or_else(|| self.profiles.last())
+                .map(|(id, _)| *id);
+            self.set_active(next);
+        }
+        Ok(profile)
+    }
+
+    pub fn activate(&mut self, id: ProfileId) -> Result<(), &'static str> {
+        self.position(id).ok_or("profile can't be found")?;
+        self.set_active(Some(id));
+        Ok(())
+    }
+
+    // Adds a profile, keeping its ID unless another profile already has it.
+    fn insert(&mut self, mut profile: UserProfile) -> ProfileId {
+        let id = match profile.id {
+            Some(id) if self.position(id).is_none() => id,
+            _ => {
+                let now = chrono::Utc::now().timestamp_millis() as u64;
+                ProfileId(self.next_id.max(now))
+            }
+        };
+        self.next_id = self.next_id.max(id.0 + 1);
+        profile.id = Some(id);
+        self.profiles.push((id, profile));
+        id
+    }
+
+    fn position(&self, id: ProfileId) -> Option<usize> {
+        self.profiles.iter().position(|(i, _)| *i == id)
+    }
+
+    fn set_active(&mut self, active: Option<ProfileId>) {
+        self.active = active;
+        for (id, profile) in self.profiles.iter_mut() {
+            profile.active = Some(*id) == active;
+        }
+    }
+
+    // Checks a name can be used by a profile, ignoring the profile being
+    // updated.
+    fn check_name(&self, name: &str, id: Option<ProfileId>) -> Result<(), &'static str> {
+        if name.is_empty() {
+            return Err("Name is required");
+        }
+        match self.find(name) {
+            Some(other) if Some(other) != id => Err("A profile with that name already exists"),
+            _ => Ok(()),
+        }
+    }
+
+    fn unique_name(&self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 1;
+        while self.find(&unique).is_some() {
+            n += 1;
+            unique = format!("{} ({})", name, n);
+        }
+        unique
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn profile(name: &str, active: bool) -> UserProfile {
+        UserProfile {
+            name: name.to_string(),
+            ftp: 250,
+            ..UserProfile::new(active)
+        }
+    }
+
+    // Checks the invariants the store promises.
+    fn check(store: &ProfileStore) {
+        let names: Vec<&str> = store.iter().map(|(_, p)| p.name.as_str()).collect();
+        for (i, name) in names.iter().enumerate() {
+            assert!(!name.is_empty());
+            assert!(!names[i + 1..].contains(name), "{} is duplicated", name);
+        }
+        assert_eq!(store.is_empty(), store.active_id().is_none());
+        for (id, p) in store.iter() {
+            assert_eq!(p.active, Some(id) == store.active_id());
+            assert_eq!(p.id, Some(id));
+        }
+    }
+
+    #[test]
+    fn test_profile_store_from_profiles() {
+        let store = ProfileStore::from_profiles(vec![
+            profile("Sam", false),
+            profile("Justina", true),
+            profile("Alex", true),
+        ]);
+        check(&store);
+        assert_eq!(store.active().unwrap().name, "Justina");
+
+        // No profile marked active makes the first profile active.
+        let store = ProfileStore::from_profiles(vec![profile("Sam", false), profile("", false)]);
+        check(&store);
+        assert_eq!(store.active().unwrap().name, "Sam");
+
+        let store = ProfileStore::from_profiles(vec![profile("Sam", false), profile("Sam", true)]);
+        check(&store);
+        assert_eq!(store.active().unwrap().name, "Sam (2)");
+
+        let store = ProfileStore::from_profiles(Vec::new());
+        check(&store);
+        assert!(store.active().is_none());
+    }
+
+    #[test]
+    fn test_profile_store_saved_ids() {
+        let mut store = ProfileStore::default();
+        let sam = store.add(profile("Sam", false)).unwrap();
+        let justina = store.add(profile("Justina", false)).unwrap();
+
+        // Saved IDs are kept when the profiles are loaded again.
+        let loaded = ProfileStore::from_profiles(store.to_profiles());
+        check(&loaded);
+        assert_eq!(loaded.find("Sam"), Some(sam));
+        assert_eq!(loaded.find("Justina"), Some(justina));
+
+        // A profile sharing an ID is given a new one.
+        let mut profiles = store.to_profiles();
+        profiles.push(UserProfile {
+            id: Some(sam),
+            ..profile("Alex", false)
+        });
+        let loaded = ProfileStore::from_profiles(profiles);
+        check(&loaded);
+        assert_eq!(loaded.find("Sam"), Some(sam));
+        assert!(![sam, justina].contains(&loaded.find("Alex").unwrap()));
+    }
+
+    #[test]
+    fn test_profile_store_add_update() {
+        let mut store = ProfileStore::default();
+        let sam = store.add(profile("Sam", false)).unwrap();
+        let justina = store.add(profile("Justina", false)).unwrap();
+        check(&store);
+        assert_ne!(sam, justina);
+        assert_eq!(store.active_id(), Some(justina));
+        assert_eq!(
+            store.add(profile("Sam", false)),
+            Err("A profile with that name already exists")
+        );
+        assert_eq!(store.add(profile("", false)), Err("Name is required"));
+
+        // Updating keeps which profile is active.
+        store
+            .update(
+                sam,
+                UserProfile {
+                    ftp: 260,
+                    ..profile("Sam", true)
+                },
+            )
+            .unwrap();
+        check(&store);
+        assert_eq!(store.get(sam).unwrap().ftp, 260);
+        assert_eq!(store.active_id(), Some(justina));
+        assert_eq!(
+            store.update(sam, profile("Justina", false)),
+            Err("A profile with that name already exists")
+        );
+        assert!(store.update(sam, profile("Samantha", false)).is_ok());
+        assert_eq!(store.find("Samantha"), Some(sam));
+    }
+
+    #[test]
+    fn test_profile_store_delete_activate() {
+        let mut store = ProfileStore::default();
+        let a = store.add(profile("A", false)).unwrap();
+        let b = store.add(profile("B", false)).unwrap();
+        let c = store.add(profile("C", false)).unwrap();
+        store.activate(b).unwrap();
+        check(&store);
+
+        // Deleting the active profile activates the next profile.
+        assert_eq!(store.delete(b).unwrap().name, "B");
+        check(&store);
+        assert_eq!(store.active_id(), Some(c));
+        assert!(store.get(b).is_none());
+        assert_eq!(store.delete(b), Err("profile can't be found"));
+        assert_eq!(store.activate(b), Err("profile can't be found"));
+
+        // Deleting the last profile activates the one before it.
+        store.delete(c).unwrap();
+        assert_eq!(store.active_id(), Some(a));
+        store.delete(a).unwrap();
+        check(&store);
+        assert!(store.active().is_none());
+
+        // IDs aren't reused.
+        let d = store.add(profile("D", false)).unwrap();
+        assert!(![a, b, c].contains(&d));
+    }
+}
@@ -0,0 +1,278 @@
+// Sensors provide the samples recorded in a session and control the trainer.
+// Sensors are read once a second by whatever is running the session.
+use std::sync::mpsc::{Receiver, Sender};
+
+use log::warn;
+
+use crate::application::session::Sample;
+
+pub trait Sensors {
+    // Reads the latest values from the sensors.
+    fn read(&mut self) -> Sample;
+
+    // Sets the watts the trainer should hold in ERG mode. None releases the
+    // trainer so the rider can free ride.
+    fn set_target(&mut self, watts: Option<u16>);
+}
+
+// Simulated sensors for trying out workouts without a trainer. Power follows
+// the trainer target with a little wobble, heart rate drifts toward a level
+// set by power, and cadence sits around 90rpm. Values are deterministic so a
+// simulated ride is the same each time.
+pub struct Simulated {
+    second: u32,
+    target: Option<u16>,
+    heart_rate: f32,
+    // Power ridden when the trainer isn't holding a target.
+    free_ride: u16,
+}
+
+impl Simulated {
+    pub fn new(free_ride: u16) -> Self {
+        Self {
+            second: 0,
+            target: None,
+            heart_rate: 90.0,
+            free_ride,
+        }
+    }
+}
+
+impl Sensors for Simulated {
+    fn read(&mut self) -> Sample {
+        self.second += 1;
+        let wobble = (self.second * 7 % 11) as i32 - 5;
+        let power = (i32::from(self.target.unwrap_or(self.free_ride)) + wobble).max(0) as u16;
+        self.heart_rate += (80.0 + f32::from(power) / 3.0 - self.heart_rate) / 30.0;
+        Sample {
+            power: Some(power),
+            heart_rate: Some(self.heart_rate.round() as u8),
+            cadence: Some((90 + wobble / 2) as u8),
+        }
+    }
+
+    fn set_target(&mut self, watts: Option<u16>) {
+        self.target = watts;
+    }
+}
+
+// ANT+ device profiles read during a ride. Pages are tagged with the device
+// they came from because page numbers overlap between profiles.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Device {
+    HeartRate,
+    Power,
+    // Speed and cadence sensor profile, cadence only.
+    Cadence,
+    // FE-C smart trainer.
+    Trainer,
+}
+
+// An 8 byte ANT+ data page from a device.
+pub type Page = (Device, [u8; 8]);
+
+// Seconds a reading is kept without a new page before the sensor is treated
+// as dropped out.
+const STALE_AFTER: u32 = 3;
+
+// The latest reading from a sensor and how many reads ago it arrived.
+#[derive(Copy, Clone, Debug, Default)]
+struct Latest<T> {
+    value: Option<T>,
+    age: u32,
+}
+
+impl<T: Copy> Latest<T> {
+    fn set(&mut self, value: Option<T>) {
+        if value.is_some() {
+            self.value = value;
+            self.age = 0;
+        }
+    }
+
+    fn read(&mut self) -> Option<T> {
+        self.age += 1;
+        if self.age > STALE_AFTER {
+            self.value = None;
+        }
+        self.value
+    }
+}
+
+// ANT+ sensors. Broadcast data pages from paired devices arrive on pages and
+// pages for the FE-C trainer are sent on trainer. Power from a power meter is
+// preferred over the trainer's. Opening and pairing the ANT+ channels that
+// carry the pages is left to libant.
+// TODO: Ride with these from the Ride screen and `2by20 ride` once libant
+// hands data pages to the application, nothing opens the channels yet.
+#[allow(dead_code)]
+pub struct Ant {
+    pages: Receiver<Page>,
+    trainer: Sender<[u8; 8]>,
+    power: Latest<u16>,
+    trainer_power: Latest<u16>,
+    heart_rate: Latest<u8>,
+    cadence: Latest<u8>,
+    // Last cadence sensor event time in 1/1024s and revolution count.
+    cadence_event: Option<(u16, u16)>,
+    // Last target sent to the trainer, which is only sent when it changes.
+    target: Option<Option<u16>>,
+}
+
+#[allow(dead_code)]
+impl Ant {
+    pub fn new(pages: Receiver<Page>, trainer: Sender<[u8; 8]>) -> Self {
+        Self {
+            pages,
+            trainer,
+            power: Latest::default(),
+            trainer_power: Latest::default(),
+            heart_rate: Latest::default(),
+            cadence: Latest::default(),
+            cadence_event: None,
+            target: None,
+        }
+    }
+
+    fn decode(&mut self, (device, data): Page) {
+        match device {
+            // Every heart rate page carries the computed heart rate last.
+            Device::HeartRate => self.heart_rate.set(Some(data[7]).filter(|&hr| hr != 0)),
+            // Standard power-only page.
+            Device::Power if data[0] == 0x10 => {
+                self.power.set(Some(u16::from_le_bytes([data[6], data[7]])));
+                self.cadence.set(Some(data[3]).filter(|&rpm| rpm != 0xFF));
+            }
+            Device::Power => {}
+            Device::Cadence => {
+                let time = u16::from_le_bytes([data[4], data[5]]);
+                let revolutions = u16::from_le_bytes([data[6], data[7]]);
+                if let Some((last_time, last_revolutions)) = self.cadence_event {
+                    let elapsed = time.wrapping_sub(last_time);
+                    // The event time only moves when a revolution completes.
+                    if elapsed > 0 {
+                        let turned = u32::from(revolutions.wrapping_sub(last_revolutions));
+                        let rpm = turned * 60 * 1024 / u32::from(elapsed);
+                        self.cadence.set(Some(rpm.min(254) as u8));
+                    }
+                }
+                self.cadence_event = Some((time, revolutions));
+            }
+            // FE-C specific trainer data page.
+            Device::Trainer if data[0] == 0x19 => {
+                let watts = u16::from(data[5]) | u16::from(data[6] & 0x0F) << 8;
+                self.trainer_power.set(Some(watts).filter(|&w| w != 0xFFF));
+                self.cadence.set(Some(data[2]).filter(|&rpm| rpm != 0xFF));
+            }
+            Device::Trainer => {}
+        }
+    }
+}
+
+impl Sensors for Ant {
+    fn read(&mut self) -> Sample {
+        while let Ok(page) = self.pages.try_recv() {
+            self.decode(page);
+        }
+        let trainer_power = self.trainer_power.read();
+        Sample {
+            power: self.power.read().or(trainer_power),
+            heart_rate: self.heart_rate.read(),
+            cadence: self.cadence.read(),
+        }
+    }
+
+    fn set_target(&mut self, watts: Option<u16>) {
+        if self.target == Some(watts) {
+            return;
+        }
+        let page = match watts {
+            // FE-C target power page, in 0.25W.
+            Some(watts) => {
+                let [low, high] = watts.saturating_mul(4).to_le_bytes();
+                [0x31, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, low, high]
+            }
+            // No resistance from the basic resistance page.
+            None => [0x30, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0],
+        };
+        match self.trainer.send(page) {
+            Ok(()) => self.target = Some(watts),
+            Err(_) => warn!("Couldn't send the target to the trainer"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn ant() -> (Ant, Sender<Page>, Receiver<[u8; 8]>) {
+        let (page_tx, page_rx) = mpsc::channel();
+        let (trainer_tx, trainer_rx) = mpsc::channel();
+        (Ant::new(page_rx, trainer_tx), page_tx, trainer_rx)
+    }
+
+    #[test]
+    fn test_ant_pages() {
+        let (mut ant, pages, _) = ant();
+        assert_eq!(ant.read(), Sample::default());
+
+        // 250W and 85rpm from the trainer, 142bpm.
+        pages
+            .send((Device::Trainer, [0x19, 1, 85, 0, 0, 250, 0x30, 0]))
+            .unwrap();
+        pages
+            .send((Device::HeartRate, [4, 0, 0, 0, 0, 0, 1, 142]))
+            .unwrap();
+        let sample = ant.read();
+        assert_eq!(sample.power, Some(250));
+        assert_eq!(sample.heart_rate, Some(142));
+        assert_eq!(sample.cadence, Some(85));
+
+        // 0x12C is 300W from the power meter, preferred over the trainer.
+        pages
+            .send((Device::Power, [0x10, 1, 0xFF, 92, 0, 0, 0x2C, 0x01]))
+            .unwrap();
+        let sample = ant.read();
+        assert_eq!(sample.power, Some(300));
+        assert_eq!(sample.cadence, Some(92));
+
+        // Two revolutions in a second is 120rpm.
+        pages
+            .send((Device::Cadence, [0, 0, 0, 0, 0, 0, 10, 0]))
+            .unwrap();
+        pages
+            .send((Device::Cadence, [0, 0, 0, 0, 0, 4, 12, 0]))
+            .unwrap();
+        assert_eq!(ant.read().cadence, Some(120));
+
+        // Readings drop out once no pages arrive for a while.
+        pages
+            .send((Device::HeartRate, [4, 0, 0, 0, 0, 0, 1, 142]))
+            .unwrap();
+        for _ in 0..STALE_AFTER {
+            assert_eq!(ant.read().heart_rate, Some(142));
+        }
+        assert_eq!(ant.read(), Sample::default());
+    }
+
+    #[test]
+    fn test_ant_set_target() {
+        let (mut ant, _, trainer) = ant();
+        ant.set_target(Some(250));
+        assert_eq!(
+            trainer.try_recv(),
+            Ok([0x31, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xE8, 0x03])
+        );
+        // The same target isn't sent again.
+        ant.set_target(Some(250));
+        assert!(trainer.try_recv().is_err());
+        ant.set_target(None);
+        assert_eq!(
+            trainer.try_recv(),
+            Ok([0x30, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0])
+        );
+    }
+}
@@ -0,0 +1,528 @@
+// A Session is a Workout being ridden. The session keeps track of where the
+// rider is in the workout, what the trainer should be targeting, and records a
+// sample from the sensors every second. The session doesn't know where samples
+// come from or how it is displayed so the same session drives the GUI and
+// the headless ride from the command line.
+use serde_derive::{Deserialize, Serialize};
+
+use crate::application::activity::Activity;
+use crate::application::compliance::{SegmentCompliance, WorkoutCompliance};
+use crate::application::sensors::Sensors;
+use crate::application::stats::{self, LapSummary};
+use crate::application::user_profile::UserProfile;
+use crate::application::workout::Workout;
+
+// Values read from sensors for one second of a ride. Any sensor can drop out
+// or not be connected, so every value is optional.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Sample {
+    pub power: Option<u16>,
+    pub heart_rate: Option<u8>,
+    pub cadence: Option<u8>,
+}
+
+// A sample recorded at a second of the ride along with what was being
+// targeted at the time. The target is the workout's target, and any move the
+// rider made to it is kept apart so the ride is scored against the workout.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Record {
+    pub elapsed: u32,
+    pub target: Option<u16>,
+    // Low and high watts of a range target. None for single targets, which
+    // are scored within a tolerance of the target instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub band: Option<(u16, u16)>,
+    // Watts the rider moved the target by. None when it wasn't moved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adjustment: Option<i32>,
+    pub lap: usize,
+    #[serde(flatten)]
+    pub sample: Sample,
+}
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub workout: Workout,
+    started: chrono::DateTime<chrono::Local>,
+    elapsed: u32,
+    paused: bool,
+    // Whether the trainer holds the target. Out of ERG mode the rider
+    // controls resistance and the target is only shown.
+    erg: bool,
+    // Set when the rider fails an FTP test that ends on failure.
+    failed: bool,
+    // Start of each lap in seconds, the laps of the workout along with any
+    // laps started by the rider.
+    laps: Vec<u32>,
+    // Watts and percent the rider has moved every target of the workout by.
+    target_offset: i16,
+    intensity_offset: i16,
+    records: Vec<Record>,
+}
+
+impl Session {
+    pub fn new(workout: Workout) -> Self {
+        Self {
+            laps: workout.laps.clone(),
+            workout,
+            started: chrono::Local::now(),
+            elapsed: 0,
+            paused: false,
+            erg: true,
+            failed: false,
+            target_offset: 0,
+            intensity_offset: 0,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn elapsed(&self) -> u32 {
+        self.elapsed
+    }
+
+    pub fn remaining(&self) -> u32 {
+        self.workout.duration.saturating_sub(self.elapsed)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.failed || self.elapsed >= self.workout.duration
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn is_erg(&self) -> bool {
+        self.erg
+    }
+
+    pub fn set_erg(&mut self, erg: bool) {
+        self.erg = erg;
+    }
+
+    // Watts the trainer should be targeting, with the rider's adjustments.
+    // None when the rider is free riding, the session is paused, or the
+    // workout is over.
+    pub fn target(&self) -> Option<u16> {
+        self.workout_target().map(|watts| {
+            let percent = 100 + i32::from(self.intensity_offset);
+            let adjusted = i32::from(watts) * percent / 100 + i32::from(self.target_offset);
+            adjusted.clamp(0, i32::from(u16::MAX)) as u16
+        })
+    }
+
+    // Watts the workout targets without the rider's adjustments.
+    fn workout_target(&self) -> Option<u16> {
+        match self.paused {
+            true => None,
+            false => self.workout.watts_at(self.elapsed),
+        }
+    }
+
+    // Moves every target from here on by a number of watts.
+    pub fn adjust_target(&mut self, watts: i16) {
+        self.target_offset = self.target_offset.saturating_add(watts);
+    }
+
+    // Moves every target from here on by a percentage of the target. Targets
+    // can't go below 0%.
+    pub fn adjust_intensity(&mut self, percent: i16) {
+        self.intensity_offset = self.intensity_offset.saturating_add(percent).max(-100);
+    }
+
+    // Watts and percent the targets have been moved by.
+    pub fn adjustment(&self) -> (i16, i16) {
+        (self.target_offset, self.intensity_offset)
+    }
+
+    pub fn lap(&self) -> usize {
+        self.laps
+            .iter()
+            .filter(|start| **start <= self.elapsed)
+            .count()
+            .saturating_sub(1)
+    }
+
+    // Index of the workout segment being ridden. None once the workout is
+    // over.
+    pub fn segment(&self) -> Option<usize> {
+        self.workout
+            .segments
+            .iter()
+            .position(|s| s.start <= self.elapsed && self.elapsed < s.end())
+    }
+
+    // Skips the rest of the segment being ridden. The seconds skipped aren't
+    // recorded so the segment shows as cut short.
+    pub fn skip_segment(&mut self) {
+        if let Some(i) = self.segment() {
+            self.elapsed = self.workout.segments[i].end();
+        }
+    }
+
+    // How closely a segment has been ridden so far.
+    pub fn segment_compliance(&self, segment: usize) -> SegmentCompliance {
+        SegmentCompliance::new(&self.workout, segment, &self.records)
+    }
+
+    // Starts a lap at the current second. Laps the workout already starts
+    // here aren't doubled up.
+    pub fn new_lap(&mut self) {
+        if let Err(i) = self.laps.binary_search(&self.elapsed) {
+            self.laps.insert(i, self.elapsed);
+        }
+    }
+
+    // Summary of a lap ridden so far. The current lap is summarised up to the
+    // last second recorded.
+    pub fn lap_summary(&self, lap: usize) -> Option<LapSummary> {
+        let start = self.records.iter().position(|r| r.lap == lap)?;
+        let end = self.records[start..]
+            .iter()
+            .position(|r| r.lap != lap)
+            .map_or(self.records.len(), |i| start + i);
+        Some(LapSummary::new(&self.records[start..end], 0))
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    // Records one second of the ride and moves the session on a second.
+    // Nothing is recorded while paused or once the workout is over. Returns
+    // true if the sample was recorded.
+    pub fn record(&mut self, sample: Sample) -> bool {
+        if self.paused || self.is_finished() {
+            return false;
+        }
+        let (target, adjusted) = (self.workout_target(), self.target());
+        self.records.push(Record {
+            elapsed: self.elapsed,
+            target,
+            band: target
+                .and_then(|_| self.workout.band_at(self.elapsed))
+                .filter(|(low, high)| low != high),
+            adjustment: match (target, adjusted) {
+                (Some(target), Some(adjusted)) if target != adjusted => {
+                    Some(i32::from(adjusted) - i32::from(target))
+                }
+                _ => None,
+            },
+            lap: self.lap(),
+            sample,
+        });
+        self.elapsed += 1;
+        if let Some(test) = self.workout.ftp_test {
+            self.failed = test.is_failed(&self.records);
+        }
+        true
+    }
+
+    // Average power over the last number of seconds recorded. Seconds without
+    // a power reading are left out.
+    pub fn smoothed_power(&self, seconds: u8) -> Option<u16> {
+        let start = self.records.len().saturating_sub(usize::from(seconds));
+        stats::average_power(&self.records[start..])
+    }
+
+    // Joules of W' left. Without CP and W' there's no balance to work out.
+    pub fn w_prime_balance(&self, cp: Option<u16>, w_prime: Option<u32>) -> Option<f32> {
+        Some(stats::w_prime_balance(&self.records, cp?, w_prime?))
+    }
+
+    // Runs one second of the session. In ERG mode the trainer is set to the
+    // target for the current second, then the sensors are read and recorded.
+    pub fn step(&mut self, sensors: &mut dyn Sensors) -> Sample {
+        sensors.set_target(if self.erg { self.target() } else { None });
+        let sample = sensors.read();
+        self.record(sample);
+        sample
+    }
+
+    // Ends the session, keeping whatever has been recorded so far. The ride
+    // lasted as many seconds as were recorded, so skipped seconds don't count.
+    pub fn finish(self, profile: &UserProfile) -> Activity {
+        let elapsed = self.elapsed;
+        let compliance = WorkoutCompliance::new(&self.workout, &self.records);
+        Activity {
+            workout: self.workout.name,
+            profile: profile.name.clone(),
+            profile_id: profile.id,
+            weight: profile.weight,
+            started: self.started.to_rfc3339(),
+            duration: self.records.len() as u32,
+            laps: self
+                .laps
+                .into_iter()
+                .filter(|start| *start < elapsed)
+                .collect(),
+            compliance: Some(compliance),
+            records: self.records,
+        }
+    }
+}
+
+// Builds the records of a ride for tests. Each lap is ridden for a number of
+// seconds at a target with the same sample every second.
+#[cfg(test)]
+#[derive(Default)]
+pub struct RecordBuilder {
+    records: Vec<Record>,
+}
+
+#[cfg(test)]
+impl RecordBuilder {
+    pub fn lap(mut self, seconds: u32, target: Option<u16>, sample: Sample) -> Self {
+        let lap = self.records.last().map_or(0, |r| r.lap + 1);
+        for _ in 0..seconds {
+            self.records.push(Record {
+                elapsed: self.records.len() as u32,
+                target,
+                band: None,
+                adjustment: None,
+                lap,
+                sample,
+            });
+        }
+        self
+    }
+
+    // A lap with only power recorded.
+    pub fn power(self, seconds: u32, target: Option<u16>, power: Option<u16>) -> Self {
+        self.lap(
+            seconds,
+            target,
+            Sample {
+                power,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn build(self) -> Vec<Record> {
+        self.records
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::library::Library;
+    use crate::application::types::Thresholds;
+
+    fn session() -> Session {
+        let library = Library::default();
+        let thresholds = Thresholds {
+            ftp: 250,
+            ..Default::default()
+        };
+        Session::new(Workout::new(&library.workouts["Metcalfe"], &thresholds))
+    }
+
+    #[test]
+    fn test_session_record() {
+        let mut session = session();
+        let sample = Sample {
+            power: Some(100),
+            heart_rate: Some(120),
+            cadence: None,
+        };
+        assert_eq!(session.target(), Some(100));
+        assert!(session.record(sample));
+        assert_eq!(session.elapsed(), 1);
+        assert_eq!(session.remaining(), 3599);
+        assert_eq!(session.records()[0].target, Some(100));
+        assert_eq!(session.records()[0].sample, sample);
+
+        session.toggle_pause();
+        assert_eq!(session.target(), None);
+        assert!(!session.record(sample));
+        assert_eq!(session.elapsed(), 1);
+        session.toggle_pause();
+
+        for _ in 1..600 {
+            session.record(sample);
+        }
+        assert_eq!(session.lap(), 1);
+        assert_eq!(session.target(), Some(213));
+    }
+
+    #[test]
+    fn test_session_step() {
+        let mut session = session();
+        let mut sensors = crate::application::sensors::Simulated::new(150);
+        for _ in 0..600 {
+            session.step(&mut sensors);
+        }
+        // The simulated trainer follows the target within a few watts.
+        let sample = session.step(&mut sensors);
+        assert!((208..=218).contains(&sample.power.unwrap()));
+        assert_eq!(session.records()[600].target, Some(213));
+    }
+
+    #[test]
+    fn test_session_erg() {
+        let mut session = session();
+        let mut sensors = crate::application::sensors::Simulated::new(150);
+        session.set_erg(false);
+        for _ in 0..10 {
+            session.step(&mut sensors);
+        }
+        // Out of ERG mode the target is still recorded but the trainer is
+        // left for the rider to ride at their own power.
+        assert_eq!(session.records()[9].target, Some(100));
+        assert!((145..=155).contains(&session.records()[9].sample.power.unwrap()));
+    }
+
+    #[test]
+    fn test_session_smoothed_power() {
+        let mut session = session();
+        for power in [Some(100), None, Some(200), Some(300)].iter() {
+            session.record(Sample {
+                power: *power,
+                ..Default::default()
+            });
+        }
+        assert_eq!(session.smoothed_power(1), Some(300));
+        assert_eq!(session.smoothed_power(3), Some(250));
+        assert_eq!(session.smoothed_power(10), Some(200));
+    }
+
+    #[test]
+    fn test_session_finish() {
+        let mut session = session();
+        assert_eq!(session.segment(), Some(0));
+        while session.record(Sample::default()) {}
+        assert!(session.is_finished());
+        assert_eq!(session.lap(), 4);
+        assert_eq!(session.segment(), None);
+        let profile = UserProfile {
+            name: String::from("Justina"),
+            weight: crate::application::types::Weight::new(72.5, Default::default()).ok(),
+            ..UserProfile::new(true)
+        };
+        let activity = session.finish(&profile);
+        assert_eq!(activity.profile, "Justina");
+        assert_eq!(activity.weight, profile.weight);
+        assert_eq!(activity.duration, 3600);
+        assert_eq!(activity.records.len(), 3600);
+        assert_eq!(activity.laps, vec![0, 600, 1800, 2100, 3300]);
+        assert_eq!(activity.lap_summaries(250).len(), 5);
+        assert_eq!(activity.lap_summaries(250)[1].start, 600);
+        // Every segment was ridden but without power there's nothing to score.
+        let compliance = activity.compliance.unwrap();
+        assert_eq!(compliance.incomplete().count(), 0);
+        assert_eq!(compliance.overall, None);
+    }
+
+    #[test]
+    fn test_session_manual_lap() {
+        let mut session = session();
+        let sample = Sample {
+            power: Some(100),
+            ..Default::default()
+        };
+        for _ in 0..120 {
+            session.record(sample);
+        }
+        session.new_lap();
+        session.new_lap();
+        assert_eq!(session.lap(), 1);
+        for _ in 120..700 {
+            session.record(sample);
+        }
+        // The workout's own laps follow on from the rider's lap.
+        assert_eq!(session.lap(), 2);
+        let previous = session.lap_summary(1).unwrap();
+        assert_eq!((previous.start, previous.stats.duration), (120, 480));
+        assert_eq!(previous.stats.average_power, Some(100));
+        assert_eq!(session.lap_summary(2).unwrap().stats.duration, 100);
+        assert!(session.lap_summary(3).is_none());
+
+        // A lap the workout already starts isn't started twice.
+        let mut session = session_at(600);
+        session.new_lap();
+        assert_eq!(session.lap(), 1);
+        session.record(Sample::default());
+        let activity = session.finish(&UserProfile::new(true));
+        assert_eq!(activity.laps, vec![0, 600]);
+    }
+
+    fn session_at(elapsed: u32) -> Session {
+        let mut session = session();
+        for _ in 0..elapsed {
+            session.record(Sample::default());
+        }
+        session
+    }
+
+    #[test]
+    fn test_session_adjust_target() {
+        let mut session = session();
+        // Metcalfe starts at 40% of 250W.
+        assert_eq!(session.target(), Some(100));
+        session.adjust_target(5);
+        session.adjust_target(5);
+        assert_eq!(session.target(), Some(110));
+        session.adjust_intensity(-2);
+        assert_eq!(session.target(), Some(108));
+        assert_eq!(session.adjustment(), (10, -2));
+        session.record(Sample::default());
+        // The workout's target is recorded apart from the rider's move.
+        assert_eq!(session.records()[0].target, Some(100));
+        assert_eq!(session.records()[0].adjustment, Some(8));
+        session.adjust_intensity(-200);
+        assert_eq!(session.target(), Some(10));
+        session.adjust_target(-20);
+        assert_eq!(session.target(), Some(0));
+    }
+
+    #[test]
+    fn test_session_adjusted_compliance() {
+        let mut session = session();
+        session.adjust_target(50);
+        for _ in 0..10 {
+            let power = session.target();
+            session.record(Sample {
+                power,
+                ..Default::default()
+            });
+        }
+        // Riding the moved target isn't riding the workout's target.
+        let compliance = session.segment_compliance(0).compliance.unwrap();
+        assert_eq!(compliance.in_band, 0.0);
+        assert_eq!(compliance.average_deviation, 50.0);
+        let lap = session.lap_summary(0).unwrap();
+        assert_eq!(lap.average_target, Some(100));
+    }
+
+    #[test]
+    fn test_session_skip_segment() {
+        let mut session = session();
+        session.record(Sample::default());
+        session.skip_segment();
+        let second = session.workout.segments[1].start;
+        assert_eq!(session.elapsed(), second);
+        assert_eq!(session.segment(), Some(1));
+        session.record(Sample::default());
+        assert_eq!(session.records()[1].elapsed, second);
+        assert_eq!(
+            session.segment_compliance(0).status,
+            crate::application::compliance::SegmentStatus::CutShort
+        );
+        // Skipping the last segment ends the workout.
+        while session.segment() != Some(session.workout.segments.len() - 1) {
+            session.skip_segment();
+        }
+        session.skip_segment();
+        assert!(session.is_finished());
+        assert_eq!(session.segment(), None);
+        // Only the seconds ridden count towards the ride's duration.
+        let activity = session.finish(&UserProfile::new(true));
+        assert_eq!(activity.duration, 2);
+    }
+}
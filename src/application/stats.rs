@@ -0,0 +1,384 @@
+// Stats worked out from the records of a ride, for the whole ride or a lap.
+// Records are one second apart so averages are over seconds ridden.
+//
+// Sensors drop out, which is recorded as no reading, and riders coast, which
+// is recorded as 0W. Seconds without a power reading are left out of power
+// stats so a dropout doesn't drag power down, but coasting counts as 0W the
+// same as it would on the road. A heart rate of 0 is a strap that has lost
+// contact and a cadence of 0 is coasting, so neither counts toward averages.
+use serde_derive::{Deserialize, Serialize};
+
+use crate::application::compliance::Compliance;
+use crate::application::session::Record;
+
+// Seconds of power averaged before normalized power is worked out.
+const NORMALIZED_POWER_SECONDS: usize = 30;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Stats {
+    // Seconds recorded, including seconds without sensor readings.
+    pub duration: u32,
+    pub average_power: Option<u16>,
+    pub max_power: Option<u16>,
+    pub normalized_power: Option<u16>,
+    pub intensity_factor: Option<f32>,
+    pub tss: Option<f32>,
+    // Kilojoules of work done in the seconds with a power reading.
+    pub work: Option<f32>,
+    pub average_heart_rate: Option<u8>,
+    pub max_heart_rate: Option<u8>,
+    pub average_cadence: Option<u8>,
+    pub max_cadence: Option<u8>,
+    pub variability_index: Option<f32>,
+}
+
+impl Stats {
+    // Stats for records against the FTP in effect on the day of the ride.
+    // Intensity factor and TSS aren't worked out without an FTP.
+    pub fn new(records: &[Record], ftp: u16) -> Self {
+        let power: Vec<u16> = records.iter().filter_map(|r| r.sample.power).collect();
+        let heart_rate: Vec<u8> = records
+            .iter()
+            .filter_map(|r| r.sample.heart_rate)
+            .filter(|hr| *hr > 0)
+            .collect();
+        let cadence: Vec<u8> = records
+            .iter()
+            .filter_map(|r| r.sample.cadence)
+            .filter(|rpm| *rpm > 0)
+            .collect();
+
+        let average_power = average(&power).map(|watts| watts as u16);
+        let normalized_power = normalized_power(&power);
+        let intensity_factor = match ftp {
+            0 => None,
+            ftp => normalized_power.map(|np| f32::from(np) / f32::from(ftp)),
+        };
+        let work = match power.is_empty() {
+            true => None,
+            false => Some(power.iter().map(|watts| u32::from(*watts)).sum::<u32>() as f32 / 1000.0),
+        };
+        let variability_index = match (normalized_power, average_power) {
+            (Some(np), Some(average)) if average > 0 => Some(f32::from(np) / f32::from(average)),
+            _ => None,
+        };
+        Self {
+            duration: records.len() as u32,
+            average_power,
+            max_power: power.iter().max().copied(),
+            normalized_power,
+            intensity_factor,
+            // An hour at FTP is 100.
+            tss: intensity_factor.map(|i| records.len() as f32 * i * i / 3600.0 * 100.0),
+            work,
+            average_heart_rate: average(&heart_rate).map(|bpm| bpm as u8),
+            max_heart_rate: heart_rate.iter().max().copied(),
+            average_cadence: average(&cadence).map(|rpm| rpm as u8),
+            max_cadence: cadence.iter().max().copied(),
+            variability_index,
+        }
+    }
+}
+
+// Summary of a lap kept with the ride and shown while riding. The stats are
+// saved alongside the other fields so rides saved before the summary held
+// every stat still load.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct LapSummary {
+    // Index of the lap in the ride. Skipping ahead can leave a lap without
+    // any records, so laps can't be numbered by their position.
+    #[serde(default)]
+    pub lap: usize,
+    // Seconds into the ride the lap started.
+    pub start: u32,
+    #[serde(flatten)]
+    pub stats: Stats,
+    // Average target of the seconds with a target. Free ride laps don't have
+    // a target.
+    pub average_target: Option<u16>,
+    // Average power of the seconds with a target.
+    pub average_target_power: Option<u16>,
+    // How closely the target was held. Free ride laps and laps summarised
+    // before compliance was scored don't have one.
+    #[serde(default)]
+    pub compliance: Option<Compliance>,
+}
+
+impl LapSummary {
+    // Summary of the records of a lap against the FTP in effect on the day of
+    // the ride.
+    pub fn new(records: &[Record], ftp: u16) -> Self {
+        let targeted: Vec<&Record> = records.iter().filter(|r| r.target.is_some()).collect();
+        let target: Vec<u16> = targeted.iter().filter_map(|r| r.target).collect();
+        let target_power: Vec<u16> = targeted.iter().filter_map(|r| r.sample.power).collect();
+        Self {
+            lap: records.first().map_or(0, |r| r.lap),
+            start: records.first().map_or(0, |r| r.elapsed),
+            stats: Stats::new(records, ftp),
+            average_target: average(&target).map(|watts| watts as u16),
+            average_target_power: average(&target_power).map(|watts| watts as u16),
+            compliance: Compliance::new(records),
+        }
+    }
+
+    // Summary of each lap in the order they were ridden.
+    pub fn laps(records: &[Record], ftp: u16) -> Vec<Self> {
+        lap_ranges(records)
+            .into_iter()
+            .map(|(start, end)| Self::new(&records[start..end], ftp))
+            .collect()
+    }
+
+    // Power ridden against the target as a percentage of the target, i.e.
+    // 98% when 245W was ridden against a 250W target.
+    pub fn target_percentage(&self) -> Option<f32> {
+        match (self.average_target_power, self.average_target) {
+            (Some(power), Some(target)) if target > 0 => {
+                Some(f32::from(power) / f32::from(target) * 100.0)
+            }
+            _ => None,
+        }
+    }
+}
+
+// Start and end of each lap in the records.
+fn lap_ranges(records: &[Record]) -> Vec<(usize, usize)> {
+    let mut laps = Vec::new();
+    let mut start = 0;
+    for i in 1..=records.len() {
+        if i == records.len() || records[i].lap != records[start].lap {
+            laps.push((start, i));
+            start = i;
+        }
+    }
+    laps
+}
+
+// Average power of the seconds with a power reading.
+pub fn average_power(records: &[Record]) -> Option<u16> {
+    let power: Vec<u16> = records.iter().filter_map(|r| r.sample.power).collect();
+    average(&power).map(|watts| watts as u16)
+}
+
+// W' balance is the joules of W' left at the end of the records, starting
+// from a full W'. Power over CP spends W' a joule a watt every second, and
+// power under CP recharges it faster the more of it has been spent (the
+// differential model from Skiba and Clarke). Dropouts neither spend nor
+// recharge W'.
+pub fn w_prime_balance(records: &[Record], cp: u16, w_prime: u32) -> f32 {
+    let (cp, w_prime) = (f32::from(cp), w_prime as f32);
+    records
+        .iter()
+        .filter_map(|r| r.sample.power)
+        .fold(w_prime, |balance, watts| match f32::from(watts) - cp {
+            over if over > 0.0 => balance - over,
+            under => balance - under * (w_prime - balance) / w_prime,
+        })
+}
+
+fn average<T: Copy + Into<u32>>(values: &[T]) -> Option<u32> {
+    match values.len() {
+        0 => None,
+        n => {
+            let sum: u32 = values.iter().map(|v| (*v).into()).sum();
+            Some((sum as f32 / n as f32).round() as u32)
+        }
+    }
+}
+
+// Normalized power is the fourth root of the average of the 30 second rolling
+// average power raised to the fourth power. Fewer than 30 seconds of power
+// don't have a normalized power.
+fn normalized_power(power: &[u16]) -> Option<u16> {
+    if power.len() < NORMALIZED_POWER_SECONDS {
+        return None;
+    }
+    let power: Vec<f64> = power.iter().map(|watts| f64::from(*watts)).collect();
+    let mut sum: f64 = power[..NORMALIZED_POWER_SECONDS].iter().sum();
+    let mut total = (sum / NORMALIZED_POWER_SECONDS as f64).powi(4);
+    for i in NORMALIZED_POWER_SECONDS..power.len() {
+        sum += power[i] - power[i - NORMALIZED_POWER_SECONDS];
+        total += (sum / NORMALIZED_POWER_SECONDS as f64).powi(4);
+    }
+    let windows = (power.len() - NORMALIZED_POWER_SECONDS + 1) as f64;
+    Some((total / windows).powf(0.25).round() as u16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::session::{RecordBuilder, Sample};
+
+    fn sample(power: Option<u16>, heart_rate: Option<u8>, cadence: Option<u8>) -> Sample {
+        Sample {
+            power,
+            heart_rate,
+            cadence,
+        }
+    }
+
+    #[test]
+    fn test_stats_steady() {
+        let steady = RecordBuilder::default()
+            .lap(3600, None, sample(Some(250), Some(150), Some(90)))
+            .build();
+        let stats = Stats::new(&steady, 250);
+        assert_eq!(stats.duration, 3600);
+        assert_eq!(stats.average_power, Some(250));
+        assert_eq!(stats.max_power, Some(250));
+        assert_eq!(stats.normalized_power, Some(250));
+        assert_eq!(stats.intensity_factor, Some(1.0));
+        assert_eq!(stats.tss, Some(100.0));
+        assert_eq!(stats.work, Some(900.0));
+        assert_eq!(stats.variability_index, Some(1.0));
+        assert_eq!(stats.average_heart_rate, Some(150));
+        assert_eq!(stats.average_cadence, Some(90));
+
+        // Without an FTP there's no intensity to work out.
+        let stats = Stats::new(&steady, 0);
+        assert_eq!((stats.intensity_factor, stats.tss), (None, None));
+        assert_eq!(stats.normalized_power, Some(250));
+    }
+
+    #[test]
+    fn test_stats_dropouts() {
+        // A minute without readings in the middle of a steady ride.
+        let ride = RecordBuilder::default()
+            .lap(1800, None, sample(Some(250), Some(150), Some(90)))
+            .lap(60, None, sample(None, Some(0), None))
+            .lap(1740, None, sample(Some(250), Some(150), Some(90)))
+            .build();
+        let stats = Stats::new(&ride, 250);
+        assert_eq!(stats.duration, 3600);
+        assert_eq!(stats.average_power, Some(250));
+        assert_eq!(stats.normalized_power, Some(250));
+        assert_eq!(stats.tss, Some(100.0));
+        assert_eq!(stats.work, Some(885.0));
+        assert_eq!(stats.average_heart_rate, Some(150));
+
+        let none = Stats::new(
+            &RecordBuilder::default()
+                .lap(60, None, Sample::default())
+                .build(),
+            250,
+        );
+        assert_eq!(none.average_power, None);
+        assert_eq!(none.work, None);
+        assert_eq!(none.variability_index, None);
+        assert_eq!(none.max_heart_rate, None);
+    }
+
+    #[test]
+    fn test_stats_coasting() {
+        // Half an hour hard and half an hour coasting.
+        let ride = RecordBuilder::default()
+            .lap(1800, None, sample(Some(300), Some(170), Some(95)))
+            .lap(1800, None, sample(Some(0), Some(110), Some(0)))
+            .build();
+        let stats = Stats::new(&ride, 250);
+        assert_eq!(stats.average_power, Some(150));
+        // Coasting counts as 0W so normalized power is close to the fourth
+        // root of half of 300W to the fourth.
+        assert!((250..=254).contains(&stats.normalized_power.unwrap()));
+        assert!(stats.variability_index.unwrap() > 1.6);
+        assert_eq!(stats.work, Some(540.0));
+        assert_eq!(stats.average_heart_rate, Some(140));
+        assert_eq!(stats.max_heart_rate, Some(170));
+        // Coasting doesn't lower the average cadence.
+        assert_eq!(stats.average_cadence, Some(95));
+
+        assert_eq!(
+            Stats::new(
+                &RecordBuilder::default()
+                    .lap(29, None, sample(Some(200), None, None))
+                    .build(),
+                250
+            )
+            .normalized_power,
+            None
+        );
+    }
+
+    #[test]
+    fn test_w_prime_balance() {
+        // A minute 100W over CP spends 6kJ of a 20kJ W'.
+        let ride = RecordBuilder::default()
+            .power(60, None, Some(350))
+            .power(10, None, None)
+            .build();
+        assert_eq!(w_prime_balance(&ride, 250, 20000), 14000.0);
+        // Recovering under CP recharges W' without going over W'.
+        let ride = RecordBuilder::default()
+            .power(60, None, Some(350))
+            .power(600, None, Some(150))
+            .build();
+        let balance = w_prime_balance(&ride, 250, 20000);
+        assert!(balance > 19000.0 && balance < 20000.0);
+        assert_eq!(w_prime_balance(&[], 250, 20000), 20000.0);
+    }
+
+    #[test]
+    fn test_stats_laps() {
+        let ride = RecordBuilder::default()
+            .lap(600, None, sample(Some(150), Some(120), Some(85)))
+            .lap(300, None, sample(Some(350), Some(175), Some(100)))
+            .lap(1, None, sample(Some(100), None, None))
+            .build();
+        let laps: Vec<Stats> = LapSummary::laps(&ride, 250)
+            .iter()
+            .map(|lap| lap.stats)
+            .collect();
+        assert_eq!(laps.len(), 3);
+        assert_eq!(laps[0].duration, 600);
+        assert_eq!(laps[1].average_power, Some(350));
+        assert_eq!(laps[1].max_heart_rate, Some(175));
+        assert_eq!(laps[2].normalized_power, None);
+        assert!(LapSummary::laps(&[], 250).is_empty());
+    }
+
+    #[test]
+    fn test_lap_summary() {
+        let mut ride = RecordBuilder::default()
+            .lap(300, None, sample(Some(150), Some(120), Some(85)))
+            .lap(60, None, sample(Some(245), Some(160), Some(95)))
+            .lap(30, None, sample(None, Some(150), None))
+            .build();
+        for r in ride.iter_mut().filter(|r| r.lap == 1) {
+            r.target = Some(250);
+        }
+        let laps = LapSummary::laps(&ride, 250);
+        assert_eq!(laps.len(), 3);
+        assert_eq!((laps[1].start, laps[1].stats.duration), (300, 60));
+        assert_eq!(laps[1].average_target, Some(250));
+        assert_eq!(laps[1].target_percentage(), Some(98.0));
+        assert_eq!(laps[1].stats.normalized_power, Some(245));
+        assert_eq!(laps[1].stats.intensity_factor, Some(0.98));
+        // Free ride laps don't have a target to compare against.
+        assert_eq!(laps[0].average_target, None);
+        assert_eq!(laps[0].target_percentage(), None);
+        assert_eq!(laps[2].stats.average_power, None);
+        assert_eq!(laps[2].stats.average_heart_rate, Some(150));
+
+        // A lap without records, i.e. skipped over, leaves a gap in the lap
+        // numbers rather than renumbering the laps after it.
+        for r in ride.iter_mut().filter(|r| r.lap == 2) {
+            r.lap = 3;
+        }
+        let laps = LapSummary::laps(&ride, 250);
+        assert_eq!(laps.iter().map(|l| l.lap).collect::<Vec<_>>(), [0, 1, 3]);
+    }
+
+    #[test]
+    fn test_lap_summary_deserialize() {
+        // Laps saved before the summary held every stat.
+        let s = r#"{"start":300,"duration":60,"average_power":245,"max_power":250,
+            "normalized_power":245,"average_heart_rate":160,"average_cadence":95,
+            "average_target":250,"average_target_power":245}"#;
+        let lap: LapSummary = serde_json::from_str(s).unwrap();
+        assert_eq!(lap.stats.duration, 60);
+        assert_eq!(lap.stats.normalized_power, Some(245));
+        assert_eq!(lap.stats.tss, None);
+        assert_eq!(lap.target_percentage(), Some(98.0));
+    }
+}
@@ -0,0 +1,176 @@
+// Training load tracks fitness and fatigue from the TSS of each day's rides.
+// Chronic training load (CTL, fitness) is an exponentially weighted average of
+// daily TSS over 42 days and acute training load (ATL, fatigue) the same over
+// 7 days. Training stress balance (TSB, form) is the fitness less the fatigue
+// carried into a day, so a hard ride shows up in form the day after.
+//
+// Days without a ride count as 0 TSS, so load decays on rest days. Planned
+// workouts are passed in the same way as rides to project load forward.
+use chrono::NaiveDate;
+
+use crate::application::library::Library;
+use crate::application::user_profile::{PlannedWorkout, UserProfile};
+use crate::application::workout::Workout;
+
+const CTL_DAYS: f32 = 42.0;
+const ATL_DAYS: f32 = 7.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DailyLoad {
+    pub date: NaiveDate,
+    // TSS of every ride on the day.
+    pub tss: f32,
+    pub ctl: f32,
+    pub atl: f32,
+    pub tsb: f32,
+}
+
+// TSS of the workouts a profile has planned after today, each ridden on
+// target. Workouts that are no longer in the library are left out.
+pub fn planned_tss(
+    profile: &UserProfile,
+    library: &Library,
+    today: NaiveDate,
+) -> Vec<(NaiveDate, f32)> {
+    profile
+        .planned
+        .iter()
+        .filter(|planned| planned.date > today)
+        .filter_map(|planned| Some((planned.date, workout_tss(planned, profile, library)?)))
+        .collect()
+}
+
+// TSS of a planned workout ridden on target by the profile. None when the
+// workout isn't in the library.
+pub fn workout_tss(
+    planned: &PlannedWorkout,
+    profile: &UserProfile,
+    library: &Library,
+) -> Option<f32> {
+    let template = library.workout(&planned.workout)?;
+    let workout = Workout::new(template, &profile.thresholds());
+    Some(workout.tss(profile.ftp).unwrap_or(0.0))
+}
+
+// Load for each day from start to end. Load is built up from the first day in
+// tss even when that's before start, so the first day shown carries the load
+// of the rides before it. tss doesn't need to be in order and can have more
+// than one ride a day.
+pub fn daily_load(tss: &[(NaiveDate, f32)], start: NaiveDate, end: NaiveDate) -> Vec<DailyLoad> {
+    let first = tss.iter().map(|(date, _)| *date).min().unwrap_or(start);
+    let (ctl_decay, atl_decay) = ((-1.0 / CTL_DAYS).exp(), (-1.0 / ATL_DAYS).exp());
+    let (mut ctl, mut atl) = (0.0, 0.0);
+    let mut days = Vec::new();
+    let mut date = first.min(start);
+    while date <= end {
+        let day_tss = tss
+            .iter()
+            .filter(|(d, _)| *d == date)
+            .fold(0.0, |sum, (_, tss)| sum + tss);
+        let tsb = ctl - atl;
+        ctl = ctl * ctl_decay + day_tss * (1.0 - ctl_decay);
+        atl = atl * atl_decay + day_tss * (1.0 - atl_decay);
+        if date >= start {
+            days.push(DailyLoad {
+                date,
+                tss: day_tss,
+                ctl,
+                atl,
+                tsb,
+            });
+        }
+        date = match date.succ_opt() {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    days
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.05
+    }
+
+    #[test]
+    fn test_daily_load_one_ride() {
+        let days = daily_load(
+            &[(date("2021-10-01"), 100.0)],
+            date("2021-10-01"),
+            date("2021-10-03"),
+        );
+        assert_eq!(days.len(), 3);
+        assert_eq!(days[0].tss, 100.0);
+        assert!(close(days[0].ctl, 2.35));
+        assert!(close(days[0].atl, 13.31));
+        // Form doesn't change until the day after the ride.
+        assert_eq!(days[0].tsb, 0.0);
+        assert!(close(days[1].tsb, 2.35 - 13.31));
+        // Fatigue falls faster than fitness on rest days.
+        assert!(days[2].atl < days[1].atl && days[2].ctl < days[1].ctl);
+        assert!(days[2].tsb > days[1].tsb);
+    }
+
+    #[test]
+    fn test_daily_load_steady_training() {
+        // 100 TSS every day for a year settles both loads at 100.
+        let start = date("2021-01-01");
+        let tss: Vec<(NaiveDate, f32)> = (0..365)
+            .map(|day| (start + chrono::Duration::days(day), 100.0))
+            .collect();
+        let end = date("2021-12-31");
+        let days = daily_load(&tss, end, end);
+        assert_eq!(days.len(), 1);
+        assert!(close(days[0].ctl, 100.0));
+        assert!(close(days[0].atl, 100.0));
+        assert!(close(days[0].tsb, 0.0));
+    }
+
+    #[test]
+    fn test_daily_load_rides_and_range() {
+        // Two rides on a day count together and rides can be in any order.
+        let tss = vec![
+            (date("2021-10-03"), 50.0),
+            (date("2021-10-01"), 60.0),
+            (date("2021-10-01"), 40.0),
+        ];
+        let all = daily_load(&tss, date("2021-10-01"), date("2021-10-05"));
+        assert_eq!(all[0].tss, 100.0);
+        assert_eq!(all[2].tss, 50.0);
+
+        // Load from before the start is carried into the first day.
+        let later = daily_load(&tss, date("2021-10-04"), date("2021-10-05"));
+        assert_eq!(later.len(), 2);
+        assert_eq!(later[0], all[3]);
+
+        // No rides is no load.
+        let none = daily_load(&[], date("2021-10-01"), date("2021-10-02"));
+        assert_eq!(none.len(), 2);
+        assert_eq!((none[1].ctl, none[1].atl, none[1].tsb), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_planned_tss() {
+        let mut profile = UserProfile {
+            ftp: 250,
+            ..UserProfile::new(true)
+        };
+        let today = date("2021-10-18");
+        profile.plan(date("2021-10-20"), "Metcalfe", today);
+        profile.plan(date("2021-10-21"), "Not in the library", today);
+        let library = Library::default();
+        let planned = planned_tss(&profile, &library, today);
+        assert_eq!(planned.len(), 1);
+        assert_eq!(planned[0].0, date("2021-10-20"));
+        assert!(planned[0].1 > 0.0);
+        // Days that have passed aren't projected.
+        assert!(planned_tss(&profile, &library, date("2021-10-20")).is_empty());
+    }
+}
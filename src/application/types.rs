@@ -1,18 +1,113 @@
 // A collection of types used throughout the application. Allows cleaning
 // up code and keeping all types creating in one module along with tests.
 use derive_more::Add;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 use std::convert::TryFrom;
+use std::fmt;
 use std::ops::{Add, AddAssign, Mul};
 use std::str::FromStr;
 
+// Threshold: Power thresholds a power target can be defined as a percentage of.
+// FTP is the default when no threshold is given.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Threshold {
+    Ftp,
+    Map,
+    Cp,
+}
+
+// Thresholds: A user's thresholds used to resolve power targets to watts and
+// heart rate targets to beats per minute. MAP, CP and LTHR are optional as not
+// every user will have tested for them.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Thresholds {
+    pub ftp: u16,
+    pub map: Option<u16>,
+    pub cp: Option<u16>,
+    pub lthr: Option<u8>,
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let threshold = match self {
+            Threshold::Ftp => "FTP",
+            Threshold::Map => "MAP",
+            Threshold::Cp => "CP",
+        };
+        write!(f, "{}", threshold)
+    }
+}
+
+impl Thresholds {
+    // An FTP of 0 hasn't been set, the same as a missing MAP or CP.
+    pub fn get(&self, threshold: Threshold) -> Option<u16> {
+        match threshold {
+            Threshold::Ftp if self.ftp == 0 => None,
+            Threshold::Ftp => Some(self.ftp),
+            Threshold::Map => self.map,
+            Threshold::Cp => self.cp,
+        }
+    }
+}
+
 // PowerTarget: Enum representing power for a segment either by an integer (u16)
-// for watts or a float (f32) for a percentage of the user's FTP
-#[derive(Copy, Deserialize, Clone, Debug, PartialEq)]
-#[serde(untagged)]
+// for watts, a float (f32) for a percentage of the user's FTP, a float for a
+// percentage of another threshold (1.05 MAP), a range of any of these
+// (200..220 or 0.88..0.94), or Free for a segment ridden without a power
+// target (free ride, RPE or heart rate driven).
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum PowerTarget {
     Watts(u16),
     Percentage(f32),
+    Relative(f32, Threshold),
+    WattsRange(u16, u16),
+    PercentageRange(f32, f32),
+    RelativeRange(f32, f32, Threshold),
+    Free,
+}
+
+impl PowerTarget {
+    // Resolves the power target to the watts used for ERG mode. Ranges resolve
+    // to their midpoint. Free, or a target relative to a threshold the user
+    // hasn't set, has no power target.
+    pub fn watts(&self, thresholds: &Thresholds) -> Option<u16> {
+        match *self {
+            Self::PercentageRange(low, high) => {
+                Self::Percentage((low + high) / 2.0).watts(thresholds)
+            }
+            Self::RelativeRange(low, high, threshold) => {
+                Self::Relative((low + high) / 2.0, threshold).watts(thresholds)
+            }
+            _ => self
+                .band(thresholds)
+                .map(|(low, high)| ((u32::from(low) + u32::from(high)) / 2) as u16),
+        }
+    }
+
+    // Resolves the power target to the low and high watts of the band used
+    // for compliance scoring and chart shading. Single values resolve to a
+    // band of zero width. All resolution of power targets to watts happens here.
+    pub fn band(&self, thresholds: &Thresholds) -> Option<(u16, u16)> {
+        let percentage = |p: f32, threshold: Threshold| {
+            thresholds
+                .get(threshold)
+                .map(|t| (f32::from(t) * p).round() as u16)
+        };
+        match *self {
+            Self::Watts(w) => Some((w, w)),
+            Self::Percentage(p) => percentage(p, Threshold::Ftp).map(|w| (w, w)),
+            Self::Relative(p, threshold) => percentage(p, threshold).map(|w| (w, w)),
+            Self::WattsRange(low, high) => Some((low, high)),
+            Self::PercentageRange(low, high) => Some((
+                percentage(low, Threshold::Ftp)?,
+                percentage(high, Threshold::Ftp)?,
+            )),
+            Self::RelativeRange(low, high, threshold) => {
+                Some((percentage(low, threshold)?, percentage(high, threshold)?))
+            }
+            Self::Free => None,
+        }
+    }
 }
 
 impl FromStr for PowerTarget {
@@ -20,6 +115,46 @@ impl FromStr for PowerTarget {
     type Err = &'static str;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.eq_ignore_ascii_case("free") {
+            return Ok(Self::Free);
+        }
+        // Percentages of a threshold other than FTP are suffixed with the
+        // threshold (1.05 MAP, 0.9..1.0 CP). FTP can be given explicitly.
+        for (unit, threshold) in [
+            ("ftp", Threshold::Ftp),
+            ("map", Threshold::Map),
+            ("cp", Threshold::Cp),
+        ]
+        .iter()
+        {
+            if let Some(v) = strip_unit(value, unit) {
+                return match (v.parse::<PowerTarget>()?, threshold) {
+                    (target, Threshold::Ftp) => match target {
+                        Self::Percentage(_) | Self::PercentageRange(_, _) => Ok(target),
+                        _ => Err("threshold requires a decimal number (1.05 MAP) or range (0.9..1.0 CP) as a percentage"),
+                    },
+                    (Self::Percentage(p), _) => Ok(Self::Relative(p, *threshold)),
+                    (Self::PercentageRange(l, h), _) => Ok(Self::RelativeRange(l, h, *threshold)),
+                    _ => Err("threshold requires a decimal number (1.05 MAP) or range (0.9..1.0 CP) as a percentage"),
+                };
+            }
+        }
+        // Ranges are defined as <low>..<high> with both ends either in watts
+        // or as a percentage of FTP.
+        if let Some(i) = value.find("..") {
+            let low = value[..i].trim().parse::<PowerTarget>()?;
+            let high = value[i + 2..].trim().parse::<PowerTarget>()?;
+            return match (low, high) {
+                (Self::Watts(l), Self::Watts(h)) if l <= h => Ok(Self::WattsRange(l, h)),
+                (Self::Percentage(l), Self::Percentage(h)) if l <= h => {
+                    Ok(Self::PercentageRange(l, h))
+                }
+                (Self::Watts(_), Self::Watts(_)) | (Self::Percentage(_), Self::Percentage(_)) => {
+                    Err("low end of a power range must not be greater than the high end")
+                }
+                _ => Err("power range must be defined in either Watts (200..220) or Percentage of FTP (0.88..0.94)"),
+            };
+        }
         // First check to see if the &str is a u16 as a u16 can be
         // converted to a f32 but not vice versa.
         match value.parse::<u16>() {
@@ -37,6 +172,64 @@ impl FromStr for PowerTarget {
     }
 }
 
+// Power targets in a table can be given as a number (200 or 0.85) or as a
+// string ("free" or "0.88..0.94").
+impl<'de> serde::Deserialize<'de> for PowerTarget {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::{Error, Unexpected};
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Target {
+            Watts(u16),
+            Percentage(f32),
+            Str(String),
+        }
+
+        match Target::deserialize(deserializer)? {
+            Target::Watts(v) => Ok(v.into()),
+            Target::Percentage(v) => {
+                if v.is_sign_negative() {
+                    return Err(Error::invalid_value(
+                        Unexpected::Float(v.into()),
+                        &"a positive decimal number for Percentage of FTP",
+                    ));
+                }
+                Ok(v.into())
+            }
+            Target::Str(v) => v
+                .parse()
+                .map_err(|e| Error::invalid_value(Unexpected::Str(&v), &e)),
+        }
+    }
+}
+
+// 250W, 75%, 105% MAP, 200-220W, 88-94% or free
+impl fmt::Display for PowerTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Watts(w) => write!(f, "{}W", w),
+            Self::Percentage(p) => write!(f, "{}%", percent(p)),
+            Self::Relative(p, t) => write!(f, "{}% {}", percent(p), t),
+            Self::WattsRange(l, h) => write!(f, "{}-{}W", l, h),
+            Self::PercentageRange(l, h) => write!(f, "{}-{}%", percent(l), percent(h)),
+            Self::RelativeRange(l, h, t) => write!(f, "{}-{}% {}", percent(l), percent(h), t),
+            Self::Free => write!(f, "free"),
+        }
+    }
+}
+
+// Targets are serialized as they are displayed for the JSON output of
+// workouts.
+impl serde::Serialize for PowerTarget {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
 // This allows calling into() on a u16 and getting a PowerTarget::Watts
 impl From<u16> for PowerTarget {
     fn from(value: u16) -> Self {
@@ -51,9 +244,207 @@ impl From<f32> for PowerTarget {
     }
 }
 
+// Converts a decimal percentage (0.85) into a whole percentage (85).
+fn percent(value: f32) -> u16 {
+    (value * 100.0).round() as u16
+}
+
+// Splits a "<low>-<high>" string into a pair of values. A single value is
+// returned as both the low and high end of the range.
+fn parse_range<T>(value: &str) -> Result<(T, T), &'static str>
+where
+    T: FromStr + PartialOrd + Copy,
+{
+    let mut parts = value.splitn(2, '-').map(|x| x.trim());
+    let low = parts
+        .next()
+        .unwrap_or_default()
+        .parse::<T>()
+        .map_err(|_| "requires a positive number or range of numbers (i.e. 85-95)")?;
+    let high = match parts.next() {
+        Some(v) => v
+            .parse::<T>()
+            .map_err(|_| "requires a positive number or range of numbers (i.e. 85-95)")?,
+        None => low,
+    };
+    if low > high {
+        return Err("low end of a range must not be greater than the high end");
+    }
+    Ok((low, high))
+}
+
+// Strips a case insensitive unit suffix (rpm, bpm, lthr, etc) from a value.
+fn strip_unit<'a>(value: &'a str, unit: &str) -> Option<&'a str> {
+    let split = value.len().checked_sub(unit.len())?;
+    if value.is_char_boundary(split) && value[split..].eq_ignore_ascii_case(unit) {
+        Some(value[..split].trim())
+    } else {
+        None
+    }
+}
+
+// CadenceTarget: Cadence for a segment in revolutions per minute either as a
+// single value (90rpm) or as a range (85-95rpm).
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum CadenceTarget {
+    Rpm(u8),
+    Range(u8, u8),
+}
+
+impl FromStr for CadenceTarget {
+    type Err = &'static str;
+
+    // 90rpm
+    // 85-95rpm
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = strip_unit(s.trim(), "rpm").ok_or("cadence must end with rpm (i.e. 90rpm)")?;
+        match parse_range::<u8>(value)? {
+            (low, high) if low == high => Ok(Self::Rpm(low)),
+            (low, high) => Ok(Self::Range(low, high)),
+        }
+    }
+}
+
+impl fmt::Display for CadenceTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rpm(rpm) => write!(f, "{}rpm", rpm),
+            Self::Range(low, high) => write!(f, "{}-{}rpm", low, high),
+        }
+    }
+}
+
+impl serde::Serialize for CadenceTarget {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl TryFrom<String> for CadenceTarget {
+    type Error = &'static str;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+// HeartRateTarget: Heart rate for a segment either in beats per minute
+// (145bpm or 140-150bpm) or as a percentage of the user's lactate threshold
+// heart rate (0.85lthr or 0.85-0.90lthr).
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum HeartRateTarget {
+    Bpm(u8),
+    BpmRange(u8, u8),
+    Lthr(f32),
+    LthrRange(f32, f32),
+}
+
+impl FromStr for HeartRateTarget {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(value) = strip_unit(s, "bpm") {
+            return match parse_range::<u8>(value)? {
+                (low, high) if low == high => Ok(Self::Bpm(low)),
+                (low, high) => Ok(Self::BpmRange(low, high)),
+            };
+        }
+        if let Some(value) = strip_unit(s, "lthr") {
+            let (low, high) = parse_range::<f32>(value)?;
+            if low.is_sign_negative() {
+                return Err("percentage of LTHR must be a positive decimal number");
+            }
+            return match value.contains('-') {
+                true => Ok(Self::LthrRange(low, high)),
+                false => Ok(Self::Lthr(low)),
+            };
+        }
+        Err("heart rate must end with bpm (i.e. 140-150bpm) or lthr (i.e. 0.85lthr)")
+    }
+}
+
+impl HeartRateTarget {
+    // Resolves the heart rate target to a low and high bpm. Targets relative
+    // to LTHR can't be resolved without an LTHR.
+    pub fn bpm(&self, lthr: Option<u8>) -> Option<(u8, u8)> {
+        let percentage = |p: f32| lthr.map(|lthr| (f32::from(lthr) * p).round().min(255.0) as u8);
+        match *self {
+            Self::Bpm(bpm) => Some((bpm, bpm)),
+            Self::BpmRange(low, high) => Some((low, high)),
+            Self::Lthr(p) => percentage(p).map(|bpm| (bpm, bpm)),
+            Self::LthrRange(low, high) => Some((percentage(low)?, percentage(high)?)),
+        }
+    }
+}
+
+impl fmt::Display for HeartRateTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Bpm(bpm) => write!(f, "{}bpm", bpm),
+            Self::BpmRange(low, high) => write!(f, "{}-{}bpm", low, high),
+            Self::Lthr(p) => write!(f, "{}% LTHR", percent(p)),
+            Self::LthrRange(low, high) => write!(f, "{}-{}% LTHR", percent(low), percent(high)),
+        }
+    }
+}
+
+impl serde::Serialize for HeartRateTarget {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl TryFrom<String> for HeartRateTarget {
+    type Error = &'static str;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+// Rpe: Rating of perceived exertion on a scale of 1 to 10 for segments ridden
+// by feel (7rpe).
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "u8")]
+pub struct Rpe(pub u8);
+
+impl FromStr for Rpe {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = strip_unit(s.trim(), "rpe").ok_or("RPE must end with rpe (i.e. 7rpe)")?;
+        let rpe = value
+            .parse::<u8>()
+            .map_err(|_| "RPE requires an integer between 1 and 10")?;
+        Self::try_from(rpe)
+    }
+}
+
+impl Rpe {
+    // Rough intensity as a fraction of FTP a rating is ridden at, from 45% for
+    // an RPE of 1 to 120% for an RPE of 10.
+    pub fn intensity(self) -> f32 {
+        const INTENSITY: [f32; 10] = [0.45, 0.5, 0.55, 0.65, 0.75, 0.85, 0.9, 1.0, 1.1, 1.2];
+        INTENSITY[usize::from(self.0 - 1)]
+    }
+}
+
+impl TryFrom<u8> for Rpe {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1..=10 => Ok(Self(value)),
+            _ => Err("RPE requires an integer between 1 and 10"),
+        }
+    }
+}
+
 // Duration is a u32 holding the number of seconds for the duration of a Workout,
 // Interval, or Segment.
-// TODO Have a display feature that will break down the number of seconds into HH::MM::SS
 #[derive(Copy, Clone, Debug, Add, PartialEq, Deserialize)]
 #[serde(try_from = "String", into = "String")]
 pub struct Duration(pub u32);
@@ -95,6 +486,17 @@ impl FromStr for Duration {
     }
 }
 
+// Displays as H:MM:SS, or M:SS for durations under an hour.
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (hours, minutes, seconds) = (self.0 / 3600, self.0 / 60 % 60, self.0 % 60);
+        match hours {
+            0 => write!(f, "{}:{:02}", minutes, seconds),
+            _ => write!(f, "{}:{:02}:{:02}", hours, minutes, seconds),
+        }
+    }
+}
+
 impl TryFrom<String> for Duration {
     type Error = &'static str;
 
@@ -194,6 +596,82 @@ impl AddAssign<StartTime> for StartTime {
 #[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
 pub struct Quantity(pub u32);
 
+// WeightUnit: Unit a user prefers weights to be entered and shown in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WeightUnit {
+    Kg,
+    Lb,
+}
+
+impl Default for WeightUnit {
+    fn default() -> Self {
+        Self::Kg
+    }
+}
+
+impl fmt::Display for WeightUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Kg => write!(f, "kg"),
+            Self::Lb => write!(f, "lb"),
+        }
+    }
+}
+
+const POUNDS_PER_KG: f32 = 2.204_622_6;
+
+// Weight: A weight held in grams so profiles holding weights can still be
+// compared, but read and written as a number of kilograms (72.5).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "f32", into = "f32")]
+pub struct Weight(u32);
+
+impl Weight {
+    pub fn new(value: f32, unit: WeightUnit) -> Result<Self, &'static str> {
+        let kg = match unit {
+            WeightUnit::Kg => value,
+            WeightUnit::Lb => value / POUNDS_PER_KG,
+        };
+        Self::try_from(kg)
+    }
+
+    pub fn kg(self) -> f32 {
+        self.0 as f32 / 1000.0
+    }
+
+    // Weight in a unit to one decimal place.
+    pub fn value(self, unit: WeightUnit) -> f32 {
+        let value = match unit {
+            WeightUnit::Kg => self.kg(),
+            WeightUnit::Lb => self.kg() * POUNDS_PER_KG,
+        };
+        (value * 10.0).round() / 10.0
+    }
+
+    // 72.5kg or 159.8lb
+    pub fn display(self, unit: WeightUnit) -> String {
+        format!("{}{}", self.value(unit), unit)
+    }
+}
+
+impl TryFrom<f32> for Weight {
+    type Error = &'static str;
+
+    fn try_from(kg: f32) -> Result<Self, Self::Error> {
+        if !kg.is_finite() || kg <= 0.0 || kg > 1000.0 {
+            return Err("weight must be a positive number of kg");
+        }
+        Ok(Self((kg * 1000.0).round() as u32))
+    }
+}
+
+impl From<Weight> for f32 {
+    fn from(weight: Weight) -> Self {
+        weight.kg()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -227,6 +705,185 @@ mod test {
         assert!("-200".parse::<PowerTarget>().is_err());
     }
 
+    #[test]
+    fn test_powertarget_free() {
+        assert_eq!("free".parse::<PowerTarget>().unwrap(), PowerTarget::Free);
+        assert_eq!("Free".parse::<PowerTarget>().unwrap(), PowerTarget::Free);
+    }
+
+    #[test]
+    fn test_powertarget_range_from_str() {
+        assert_eq!(
+            "0.88..0.94".parse::<PowerTarget>(),
+            Ok(PowerTarget::PercentageRange(0.88, 0.94))
+        );
+        assert_eq!(
+            "200 .. 220".parse::<PowerTarget>(),
+            Ok(PowerTarget::WattsRange(200, 220))
+        );
+        assert!("0.94..0.88".parse::<PowerTarget>().is_err());
+        assert!("200..0.9".parse::<PowerTarget>().is_err());
+        assert!("free..200".parse::<PowerTarget>().is_err());
+        assert!("200..".parse::<PowerTarget>().is_err());
+    }
+
+    #[test]
+    fn test_powertarget_threshold_from_str() {
+        assert_eq!(
+            "1.05 MAP".parse::<PowerTarget>(),
+            Ok(PowerTarget::Relative(1.05, Threshold::Map))
+        );
+        assert_eq!(
+            "0.9..1.0cp".parse::<PowerTarget>(),
+            Ok(PowerTarget::RelativeRange(0.9, 1.0, Threshold::Cp))
+        );
+        assert_eq!(
+            "0.85 FTP".parse::<PowerTarget>(),
+            Ok(PowerTarget::Percentage(0.85))
+        );
+        assert!("300 MAP".parse::<PowerTarget>().is_err());
+        assert!("free cp".parse::<PowerTarget>().is_err());
+    }
+
+    fn thresholds() -> Thresholds {
+        Thresholds {
+            ftp: 250,
+            map: Some(340),
+            cp: None,
+            lthr: None,
+        }
+    }
+
+    #[test]
+    fn test_powertarget_watts() {
+        let t = thresholds();
+        assert_eq!(PowerTarget::Watts(200).watts(&t), Some(200));
+        assert_eq!(PowerTarget::Percentage(0.9).watts(&t), Some(225));
+        assert_eq!(PowerTarget::WattsRange(200, 220).watts(&t), Some(210));
+        assert_eq!(
+            PowerTarget::PercentageRange(0.88, 0.94).watts(&Thresholds { ftp: 300, ..t }),
+            Some(273)
+        );
+        assert_eq!(
+            PowerTarget::Relative(1.05, Threshold::Map).watts(&t),
+            Some(357)
+        );
+        assert_eq!(
+            PowerTarget::RelativeRange(1.0, 1.1, Threshold::Map).watts(&t),
+            Some(357)
+        );
+        assert_eq!(PowerTarget::Relative(1.05, Threshold::Cp).watts(&t), None);
+        assert_eq!(PowerTarget::Free.watts(&t), None);
+    }
+
+    #[test]
+    fn test_powertarget_band() {
+        let t = thresholds();
+        assert_eq!(PowerTarget::Watts(200).band(&t), Some((200, 200)));
+        assert_eq!(
+            PowerTarget::PercentageRange(0.88, 0.94).band(&t),
+            Some((220, 235))
+        );
+        assert_eq!(
+            PowerTarget::RelativeRange(1.0, 1.1, Threshold::Map).band(&t),
+            Some((340, 374))
+        );
+        assert_eq!(
+            PowerTarget::RelativeRange(1.0, 1.1, Threshold::Cp).band(&t),
+            None
+        );
+        assert_eq!(PowerTarget::Free.band(&t), None);
+    }
+
+    #[test]
+    fn test_powertarget_deserialize() {
+        #[derive(Deserialize)]
+        struct Foo {
+            watts: PowerTarget,
+            percentage: PowerTarget,
+            free: PowerTarget,
+            range: PowerTarget,
+        }
+
+        let foo: Foo =
+            toml::from_str("watts = 200\npercentage = 0.85\nfree = 'free'\nrange = '0.88..0.94'")
+                .unwrap();
+        assert_eq!(foo.watts, PowerTarget::Watts(200));
+        assert_eq!(foo.range, PowerTarget::PercentageRange(0.88, 0.94));
+        assert_eq!(foo.percentage, PowerTarget::Percentage(0.85));
+        assert_eq!(foo.free, PowerTarget::Free);
+    }
+
+    #[test]
+    fn test_cadence_target() {
+        assert_eq!("90rpm".parse::<CadenceTarget>(), Ok(CadenceTarget::Rpm(90)));
+        assert_eq!(
+            "90 RPM".parse::<CadenceTarget>(),
+            Ok(CadenceTarget::Rpm(90))
+        );
+        assert_eq!(
+            "85-95rpm".parse::<CadenceTarget>(),
+            Ok(CadenceTarget::Range(85, 95))
+        );
+        assert!("95-85rpm".parse::<CadenceTarget>().is_err());
+        assert!("90".parse::<CadenceTarget>().is_err());
+        assert!("300rpm".parse::<CadenceTarget>().is_err());
+    }
+
+    #[test]
+    fn test_heart_rate_target() {
+        assert_eq!(
+            "145bpm".parse::<HeartRateTarget>(),
+            Ok(HeartRateTarget::Bpm(145))
+        );
+        assert_eq!(
+            "140-150bpm".parse::<HeartRateTarget>(),
+            Ok(HeartRateTarget::BpmRange(140, 150))
+        );
+        assert_eq!(
+            "0.85 LTHR".parse::<HeartRateTarget>(),
+            Ok(HeartRateTarget::Lthr(0.85))
+        );
+        assert_eq!(
+            "0.85-0.9lthr".parse::<HeartRateTarget>(),
+            Ok(HeartRateTarget::LthrRange(0.85, 0.9))
+        );
+        assert!("145".parse::<HeartRateTarget>().is_err());
+        assert!("150-140bpm".parse::<HeartRateTarget>().is_err());
+    }
+
+    #[test]
+    fn test_heart_rate_target_bpm() {
+        assert_eq!(HeartRateTarget::Bpm(145).bpm(None), Some((145, 145)));
+        assert_eq!(HeartRateTarget::Lthr(0.85).bpm(None), None);
+        assert_eq!(HeartRateTarget::Lthr(0.85).bpm(Some(170)), Some((145, 145)));
+        assert_eq!(
+            HeartRateTarget::LthrRange(0.85, 0.9).bpm(Some(170)),
+            Some((145, 153))
+        );
+    }
+
+    #[test]
+    fn test_weight() {
+        let weight = Weight::new(72.5, WeightUnit::Kg).unwrap();
+        assert_eq!(weight.kg(), 72.5);
+        assert_eq!(weight.display(WeightUnit::Kg), "72.5kg");
+        assert_eq!(weight.display(WeightUnit::Lb), "159.8lb");
+        let weight = Weight::new(160.0, WeightUnit::Lb).unwrap();
+        assert_eq!(weight.value(WeightUnit::Lb), 160.0);
+        assert_eq!(weight.value(WeightUnit::Kg), 72.6);
+        assert!(Weight::new(0.0, WeightUnit::Kg).is_err());
+        assert!(Weight::new(f32::NAN, WeightUnit::Lb).is_err());
+    }
+
+    #[test]
+    fn test_rpe() {
+        assert_eq!("7rpe".parse::<Rpe>(), Ok(Rpe(7)));
+        assert_eq!("7 RPE".parse::<Rpe>(), Ok(Rpe(7)));
+        assert!("0rpe".parse::<Rpe>().is_err());
+        assert!("11rpe".parse::<Rpe>().is_err());
+    }
+
     #[test]
     fn test_duration_seconds() {
         let d: Duration = "30s".parse().unwrap();
@@ -334,4 +991,37 @@ mod test {
     fn test_duration_times_quantity() {
         assert_eq!(Duration(30) * Quantity(3), Duration(30 * 3));
     }
+
+    #[test]
+    fn test_target_display() {
+        assert_eq!(PowerTarget::Watts(250).to_string(), "250W");
+        assert_eq!(PowerTarget::Percentage(0.875).to_string(), "88%");
+        assert_eq!(
+            PowerTarget::Relative(1.05, Threshold::Map).to_string(),
+            "105% MAP"
+        );
+        assert_eq!(PowerTarget::WattsRange(200, 220).to_string(), "200-220W");
+        assert_eq!(
+            PowerTarget::PercentageRange(0.88, 0.94).to_string(),
+            "88-94%"
+        );
+        assert_eq!(PowerTarget::Free.to_string(), "free");
+        assert_eq!(CadenceTarget::Range(85, 95).to_string(), "85-95rpm");
+        assert_eq!(HeartRateTarget::Bpm(145).to_string(), "145bpm");
+        assert_eq!(HeartRateTarget::Lthr(0.85).to_string(), "85% LTHR");
+    }
+
+    #[test]
+    fn test_threshold_ftp_not_set() {
+        let t = Thresholds::default();
+        assert_eq!(PowerTarget::Percentage(0.75).watts(&t), None);
+        assert_eq!(PowerTarget::Watts(200).watts(&t), Some(200));
+    }
+
+    #[test]
+    fn test_duration_display() {
+        assert_eq!(Duration(45).to_string(), "0:45");
+        assert_eq!(Duration(630).to_string(), "10:30");
+        assert_eq!(Duration(3600 + 65).to_string(), "1:01:05");
+    }
 }
@@ -2,5 +2,8 @@
 // Each module contains the separate UI elements for the entire
 // application.
 
+pub mod history;
+pub mod library;
 pub mod menubar;
+pub mod ride;
 pub mod user_profile;
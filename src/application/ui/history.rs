@@ -0,0 +1,516 @@
+// The History screen lists the rides of the active profile, newest first, with
+// the date, workout, duration, average and normalized power and TSS of each,
+// under a chart of the profile's best power for each duration and a chart of
+// fitness, fatigue and form over the last 90 days, projected forward over the
+// workouts the profile has planned, which are listed with the rides. Selecting a
+// ride reads the ride file and shows the ride in detail with a chart of the
+// power ridden, any new bests set on the ride, and stats for the ride and
+// each lap.
+use crate::application::activity::Activity;
+use crate::application::compliance::{Compliance, SegmentCompliance, SegmentStatus};
+use crate::application::history::{History, HistoryEntry};
+use crate::application::library::Library;
+use crate::application::power_curve::{duration_label, PowerCurve};
+use crate::application::stats::{self, LapSummary, Stats};
+use crate::application::training_load::{self, DailyLoad};
+use crate::application::types::Duration;
+use crate::application::ui::library::{zone_color, ZoneStyle};
+use crate::application::user_profile::UserProfile;
+use iced::{
+    button, scrollable, Button, Color, Column, Container, Element, Length, Row, Scrollable, Space,
+    Text,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    // The ride being shown and the ride read from its file.
+    selected: Option<(HistoryEntry, Result<Activity, String>)>,
+    ride_buttons: Vec<button::State>,
+    unplan_buttons: Vec<button::State>,
+    rides_scroll: scrollable::State,
+    ride_scroll: scrollable::State,
+    back_button: button::State,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    RideSelected(String),
+    Back,
+    // Removing a planned workout is handled by the application as it saves
+    // the profile.
+    Unplan(usize),
+}
+
+const CHART_HEIGHT: u16 = 200;
+// Days of training load shown, and days after today projected with rest.
+const LOAD_DAYS: i64 = 90;
+const PROJECTED_DAYS: i64 = 14;
+const LOAD_HEIGHT: u16 = 120;
+const FORM_HEIGHT: u16 = 80;
+// Rides are drawn with at most this many bars, each the average power of
+// an equal part of the ride.
+const CHART_BARS: usize = 120;
+
+impl State {
+    pub fn update(&mut self, message: Message, history: &History) {
+        match message {
+            Message::RideSelected(file) => {
+                self.selected = history
+                    .entry(&file)
+                    .map(|entry| (entry.clone(), history.load(entry)))
+            }
+            Message::Back => self.selected = None,
+            Message::Unplan(_) => {}
+        }
+    }
+
+    pub fn view<'a>(
+        &'a mut self,
+        history: &History,
+        profile: &UserProfile,
+        library: &Library,
+    ) -> Element<'a, Message> {
+        let content = match &self.selected {
+            Some((entry, activity)) => {
+                let back = Button::new(&mut self.back_button, Text::new("Back").size(16))
+                    .padding(8)
+                    .on_press(Message::Back);
+                let records = history.personal_records(entry);
+                ride(
+                    entry,
+                    activity,
+                    profile,
+                    &records,
+                    back,
+                    &mut self.ride_scroll,
+                )
+            }
+            None => {
+                let entries: Vec<&HistoryEntry> = history.entries(profile).collect();
+                self.ride_buttons
+                    .resize_with(entries.len(), Default::default);
+                let mut rides = Scrollable::new(&mut self.rides_scroll)
+                    .spacing(5)
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .push(Text::new("History").size(30));
+                let curve = history.power_curve(profile);
+                if !curve.is_empty() {
+                    rides = rides
+                        .push(Text::new("Best power").size(20))
+                        .push(power_curve_chart(&curve));
+                }
+                let today = chrono::Local::now().naive_local().date();
+                let planned = training_load::planned_tss(profile, library, today);
+                let mut tss = history.daily_tss(profile);
+                tss.extend(planned.iter());
+                let end = planned
+                    .iter()
+                    .map(|(date, _)| *date)
+                    .chain(std::iter::once(
+                        today + chrono::Duration::days(PROJECTED_DAYS),
+                    ))
+                    .max()
+                    .unwrap_or(today);
+                let load = training_load::daily_load(
+                    &tss,
+                    today - chrono::Duration::days(LOAD_DAYS - 1),
+                    end,
+                );
+                if load.iter().any(|day| day.ctl >= 1.0) {
+                    rides = rides
+                        .push(Text::new("Training load").size(20))
+                        .push(training_load_chart(&load, today));
+                }
+                // Workouts are planned from the Library screen.
+                let upcoming: Vec<_> = profile
+                    .planned
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, planned)| planned.date > today)
+                    .collect();
+                self.unplan_buttons
+                    .resize_with(upcoming.len(), Default::default);
+                if !upcoming.is_empty() {
+                    rides = rides.push(Text::new("Planned").size(20));
+                }
+                for ((i, planned), state) in
+                    upcoming.into_iter().zip(self.unplan_buttons.iter_mut())
+                {
+                    let tss = training_load::workout_tss(planned, profile, library)
+                        .map_or_else(|| String::from("-"), |tss| format!("{:.0}", tss));
+                    rides = rides.push(
+                        Row::new()
+                            .spacing(10)
+                            .push(
+                                Text::new(planned.date.format("%Y-%m-%d").to_string())
+                                    .size(16)
+                                    .width(Length::Units(150)),
+                            )
+                            .push(
+                                Text::new(planned.workout.as_str())
+                                    .size(16)
+                                    .width(Length::Units(250)),
+                            )
+                            .push(
+                                Text::new(format!("TSS {}", tss))
+                                    .size(16)
+                                    .width(Length::Units(90)),
+                            )
+                            .push(
+                                Button::new(state, Text::new("Remove").size(14))
+                                    .padding(4)
+                                    .on_press(Message::Unplan(i)),
+                            ),
+                    );
+                }
+                rides = rides.push(entry_row(
+                    ["Date", "Workout", "Duration", "Avg", "NP", "TSS", "In band"]
+                        .iter()
+                        .map(|h| h.to_string())
+                        .collect(),
+                ));
+                if entries.is_empty() {
+                    rides = rides.push(Text::new("No rides yet").size(16));
+                }
+                for (entry, state) in entries.into_iter().zip(self.ride_buttons.iter_mut()) {
+                    rides = rides.push(
+                        Button::new(state, entry_row(summary(entry)))
+                            .padding(8)
+                            .width(Length::Fill)
+                            .on_press(Message::RideSelected(entry.file.clone())),
+                    );
+                }
+                rides.into()
+            }
+        };
+        Container::new(content)
+            .padding(20)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .into()
+    }
+}
+
+fn ride<'a>(
+    entry: &HistoryEntry,
+    activity: &Result<Activity, String>,
+    profile: &UserProfile,
+    records: &[(u32, u16)],
+    back: Button<'a, Message>,
+    scroll: &'a mut scrollable::State,
+) -> Element<'a, Message> {
+    let mut detail = Scrollable::new(scroll)
+        .spacing(10)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .push(back)
+        .push(Text::new(entry.workout.as_str()).size(30))
+        .push(Text::new(entry.started_at()).size(16));
+    match activity {
+        Ok(activity) => {
+            if let Some(weight) = activity.weight {
+                let unit = profile.settings.units.weight_unit();
+                detail =
+                    detail.push(Text::new(format!("Weight: {}", weight.display(unit))).size(16));
+            }
+            // Rides by a profile that no longer exists are coloured by the
+            // current profile's FTP and don't have an intensity.
+            detail = detail.push(chart(activity, profile, entry.ftp.unwrap_or(profile.ftp)));
+            if !records.is_empty() {
+                let records: Vec<String> = records
+                    .iter()
+                    .map(|(seconds, watts)| format!("{} {}W", duration_label(*seconds), watts))
+                    .collect();
+                detail = detail.push(
+                    Text::new(format!("New bests: {}", records.join(", ")))
+                        .size(16)
+                        .color(Color::from_rgb(0.2, 0.6, 0.3)),
+                );
+            }
+            let ftp = entry.ftp.unwrap_or(0);
+            detail = detail
+                .push(stats_row(
+                    [
+                        "",
+                        "Time",
+                        "Avg",
+                        "Max",
+                        "NP",
+                        "IF",
+                        "TSS",
+                        "kJ",
+                        "VI",
+                        "HR",
+                        "Max HR",
+                        "Cadence",
+                        "Target",
+                        "Of target",
+                        "In band",
+                        "Off by",
+                    ]
+                    .iter()
+                    .map(|h| h.to_string())
+                    .collect(),
+                ))
+                .push(stats_row(stats_values(
+                    String::from("Ride"),
+                    &Stats::new(&activity.records, ftp),
+                    None,
+                    activity
+                        .compliance
+                        .as_ref()
+                        .and_then(|c| c.overall.as_ref()),
+                )));
+            for lap in activity.lap_summaries(ftp) {
+                detail = detail.push(stats_row(stats_values(
+                    format!("Lap {}", lap.lap + 1),
+                    &lap.stats,
+                    Some(&lap),
+                    lap.compliance.as_ref(),
+                )));
+            }
+            if let Some(compliance) = &activity.compliance {
+                for segment in compliance.incomplete() {
+                    detail = detail.push(Text::new(incomplete_segment(segment)).size(16));
+                }
+            }
+        }
+        Err(e) => {
+            detail = detail.push(
+                Text::new(format!("Unable to read ride: {}", e))
+                    .size(16)
+                    .color(Color::from_rgb(0.8, 0.2, 0.2)),
+            )
+        }
+    }
+    detail.into()
+}
+
+// Power ridden as a row of bars coloured by power zone.
+fn chart<'a>(activity: &Activity, profile: &UserProfile, ftp: u16) -> Element<'a, Message> {
+    let size = activity.records.len() / CHART_BARS + 1;
+    let bars: Vec<Option<u16>> = activity
+        .records
+        .chunks(size)
+        .map(stats::average_power)
+        .collect();
+    let max = bars.iter().flatten().max().copied().unwrap_or(1).max(1);
+    let mut chart = Row::new()
+        .align_items(iced::Align::End)
+        .width(Length::Fill)
+        .height(Length::Units(CHART_HEIGHT));
+    for bar in bars {
+        let watts = bar.unwrap_or(0);
+        let height = (u32::from(watts) * u32::from(CHART_HEIGHT) / u32::from(max)) as u16;
+        chart = chart.push(
+            Container::new(Space::new(Length::Fill, Length::Fill))
+                .width(Length::Fill)
+                .height(Length::Units(height.max(1)))
+                .style(ZoneStyle(zone_color(profile.power_zones.zone(watts, ftp)))),
+        );
+    }
+    chart.into()
+}
+
+// Segment 4 cut short, 0:30 of 4:00 ridden
+fn incomplete_segment(segment: &SegmentCompliance) -> String {
+    match segment.status {
+        SegmentStatus::Skipped => format!("Segment {} skipped", segment.segment + 1),
+        _ => format!(
+            "Segment {} cut short, {} of {} ridden",
+            segment.segment + 1,
+            Duration(segment.ridden),
+            Duration(segment.duration)
+        ),
+    }
+}
+
+// Target and power against the target come from the lap summary, the whole
+// ride doesn't have them.
+fn stats_values(
+    label: String,
+    stats: &Stats,
+    lap: Option<&LapSummary>,
+    compliance: Option<&Compliance>,
+) -> Vec<String> {
+    let watts = |w: Option<u16>| w.map_or_else(|| String::from("-"), |w| format!("{}W", w));
+    let decimal = |value: Option<f32>, places: usize| {
+        value.map_or_else(|| String::from("-"), |v| format!("{:.*}", places, v))
+    };
+    let optional = |value: Option<u8>| value.map_or_else(|| String::from("-"), |v| v.to_string());
+    vec![
+        label,
+        Duration(stats.duration).to_string(),
+        watts(stats.average_power),
+        watts(stats.max_power),
+        watts(stats.normalized_power),
+        decimal(stats.intensity_factor, 2),
+        decimal(stats.tss, 0),
+        decimal(stats.work, 0),
+        decimal(stats.variability_index, 2),
+        optional(stats.average_heart_rate),
+        optional(stats.max_heart_rate),
+        optional(stats.average_cadence),
+        watts(lap.and_then(|lap| lap.average_target)),
+        lap.and_then(|lap| lap.target_percentage())
+            .map_or_else(|| String::from("-"), |p| format!("{:.0}%", p)),
+        compliance.map_or_else(|| String::from("-"), |c| format!("{:.0}%", c.in_band)),
+        compliance.map_or_else(
+            || String::from("-"),
+            |c| format!("{:.0}W", c.average_deviation),
+        ),
+    ]
+}
+
+// Lap 2  5:00  312W  340W  315W  1.26  13  94  1.01  168  174  98  310W  101%  88%  7W
+fn stats_row<'a>(values: Vec<String>) -> Element<'a, Message> {
+    const WIDTHS: [u16; 16] = [
+        60, 70, 60, 60, 60, 50, 50, 50, 50, 50, 60, 60, 60, 70, 60, 60,
+    ];
+    let mut row = Row::new().spacing(10);
+    for (value, width) in values.into_iter().zip(WIDTHS.iter()) {
+        row = row.push(Text::new(value).size(16).width(Length::Units(*width)));
+    }
+    row.into()
+}
+
+// Best power as a bar for each duration, labelled with the duration and
+// watts underneath.
+fn power_curve_chart<'a>(curve: &PowerCurve) -> Element<'a, Message> {
+    let max = curve
+        .iter()
+        .map(|(_, watts)| watts)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let mut chart = Row::new()
+        .spacing(5)
+        .align_items(iced::Align::End)
+        .width(Length::Fill);
+    for (seconds, watts) in curve.iter() {
+        let height = (u32::from(watts) * u32::from(CHART_HEIGHT) / u32::from(max)) as u16;
+        chart = chart.push(
+            Column::new()
+                .width(Length::Fill)
+                .spacing(2)
+                .align_items(iced::Align::Center)
+                .push(
+                    Container::new(Space::new(Length::Fill, Length::Fill))
+                        .width(Length::Fill)
+                        .height(Length::Units(height.max(1)))
+                        .style(ZoneStyle(zone_color(Some(1)))),
+                )
+                .push(Text::new(duration_label(seconds)).size(14))
+                .push(Text::new(format!("{}W", watts)).size(14)),
+        );
+    }
+    chart.into()
+}
+
+// Fitness and fatigue are drawn as a dot for each day with form as a bar
+// above or below the middle of a chart under them. Days after today are a
+// projection of resting and are faded.
+fn training_load_chart<'a>(days: &[DailyLoad], today: chrono::NaiveDate) -> Element<'a, Message> {
+    const DOT: u16 = 3;
+    let max = days
+        .iter()
+        .map(|day| day.ctl.max(day.atl))
+        .fold(1.0, f32::max);
+    let max_form = days.iter().map(|day| day.tsb.abs()).fold(1.0, f32::max);
+    let color = |r: f32, g: f32, b: f32, projected: bool| {
+        Color::from_rgba(r, g, b, if projected { 0.35 } else { 1.0 })
+    };
+    let block = |height: u16, color: Color| {
+        Container::new(Space::new(Length::Fill, Length::Fill))
+            .width(Length::Fill)
+            .height(Length::Units(height))
+            .style(ZoneStyle(color))
+    };
+
+    let mut load = Row::new()
+        .width(Length::Fill)
+        .height(Length::Units(LOAD_HEIGHT));
+    let mut form = Row::new()
+        .width(Length::Fill)
+        .height(Length::Units(FORM_HEIGHT));
+    for day in days {
+        let projected = day.date > today;
+        // Dots are placed from the top of the chart down.
+        let mut dots = vec![
+            (day.ctl, color(0.2, 0.5, 0.9, projected)),
+            (day.atl, color(0.85, 0.3, 0.6, projected)),
+        ]
+        .into_iter()
+        .map(|(value, color)| {
+            let top = LOAD_HEIGHT - (value / max * f32::from(LOAD_HEIGHT)) as u16;
+            (top.min(LOAD_HEIGHT - DOT), color)
+        })
+        .collect::<Vec<(u16, Color)>>();
+        dots.sort_by_key(|(top, _)| *top);
+        let mut column = Column::new().width(Length::Fill);
+        let mut y = 0;
+        for (top, color) in dots {
+            let top = top.max(y);
+            column = column
+                .push(Space::new(Length::Fill, Length::Units(top - y)))
+                .push(block(DOT, color));
+            y = top + DOT;
+        }
+        load = load.push(column);
+
+        let half = FORM_HEIGHT / 2;
+        let height = ((day.tsb.abs() / max_form) * f32::from(half)) as u16;
+        let column = match day.tsb >= 0.0 {
+            true => Column::new()
+                .push(Space::new(Length::Fill, Length::Units(half - height)))
+                .push(block(height, color(0.2, 0.7, 0.3, projected))),
+            false => Column::new()
+                .push(Space::new(Length::Fill, Length::Units(half)))
+                .push(block(height, color(0.95, 0.5, 0.1, projected))),
+        };
+        form = form.push(column.width(Length::Fill));
+    }
+
+    let current = days.iter().find(|day| day.date == today);
+    let legend = match current {
+        Some(day) => format!(
+            "Fitness (CTL) {:.0}   Fatigue (ATL) {:.0}   Form (TSB) {:.0}",
+            day.ctl, day.atl, day.tsb
+        ),
+        None => String::new(),
+    };
+    Column::new()
+        .spacing(5)
+        .push(load)
+        .push(form)
+        .push(Text::new(legend).size(14))
+        .into()
+}
+
+fn summary(entry: &HistoryEntry) -> Vec<String> {
+    let watts = |w: Option<u16>| w.map_or_else(|| String::from("-"), |w| format!("{}W", w));
+    vec![
+        entry.started_at(),
+        entry.workout.clone(),
+        Duration(entry.duration).to_string(),
+        watts(entry.average_power),
+        watts(entry.normalized_power),
+        entry
+            .tss
+            .map_or_else(|| String::from("-"), |tss| format!("{:.0}", tss)),
+        entry
+            .in_band
+            .map_or_else(|| String::from("-"), |in_band| format!("{:.0}%", in_band)),
+    ]
+}
+
+// 2021-10-18 06:30  Metcalfe  1:00:00  182W  205W  67  91%
+fn entry_row<'a>(values: Vec<String>) -> Element<'a, Message> {
+    const WIDTHS: [u16; 7] = [150, 250, 90, 70, 70, 50, 70];
+    let mut row = Row::new().spacing(10);
+    for (value, width) in values.into_iter().zip(WIDTHS.iter()) {
+        row = row.push(Text::new(value).size(16).width(Length::Units(*width)));
+    }
+    row.into()
+}
@@ -0,0 +1,317 @@
+// The Library screen lists the workouts in the library. Selecting a workout
+// shows a chart of the workout with each segment coloured by power zone and
+// the list of segments labelled with their zone, a button to ride it, and a
+// date to plan it for so it's projected in the profile's training load.
+// Anything that failed to load into the library is listed at the top of the
+// screen.
+use crate::application::library::Library;
+use crate::application::types::Duration;
+use crate::application::user_profile::UserProfile;
+use crate::application::workout::{Workout, WorkoutSegment};
+use crate::application::zones::PowerZones;
+use iced::{
+    button, container, scrollable, text_input, Align, Background, Button, Color, Column, Container,
+    Element, Length, Row, Scrollable, Space, Text, TextInput,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    selected: Option<String>,
+    workout_buttons: Vec<button::State>,
+    workouts_scroll: scrollable::State,
+    segments_scroll: scrollable::State,
+    ride_button: button::State,
+    // Day to plan the selected workout for as YYYY-MM-DD.
+    plan_date_input: String,
+    plan_date_field: text_input::State,
+    plan_button: button::State,
+}
+
+// Riding a workout is handled by the application, which starts the ride and
+// shows the Ride screen. Planning a workout is handled by the application as
+// it saves the profile.
+#[derive(Debug, Clone)]
+pub enum Message {
+    WorkoutSelected(String),
+    Ride(String),
+    PlanDateChanged(String),
+    Plan(String, chrono::NaiveDate),
+}
+
+// Height of the workout chart. Segments are scaled to the highest target in
+// the workout.
+const CHART_HEIGHT: u16 = 200;
+
+impl State {
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::WorkoutSelected(name) => self.selected = Some(name),
+            Message::PlanDateChanged(value) => {
+                if value.len() <= 10 {
+                    self.plan_date_input = value;
+                }
+            }
+            Message::Plan(..) => self.plan_date_input.clear(),
+            Message::Ride(_) => {}
+        }
+    }
+
+    pub fn view<'a>(
+        &'a mut self,
+        library: &Library,
+        profile: &UserProfile,
+        diagnostics: &[String],
+        can_ride: bool,
+    ) -> Element<'a, Message> {
+        self.workout_buttons
+            .resize_with(library.workouts.len(), Default::default);
+        let mut workouts = Scrollable::new(&mut self.workouts_scroll)
+            .spacing(5)
+            .width(Length::Units(250))
+            .height(Length::Fill)
+            .push(Text::new("Workouts").size(24));
+        for (name, state) in library.workouts.keys().zip(self.workout_buttons.iter_mut()) {
+            workouts = workouts.push(
+                Button::new(state, Text::new(name.as_str()).size(16))
+                    .padding(8)
+                    .width(Length::Fill)
+                    .on_press(Message::WorkoutSelected(name.clone())),
+            );
+        }
+
+        let mut detail = Column::new().spacing(10).width(Length::Fill);
+        for diagnostic in diagnostics {
+            detail = detail.push(
+                Text::new(diagnostic.as_str())
+                    .size(16)
+                    .color(Color::from_rgb(0.8, 0.2, 0.2)),
+            );
+        }
+        let selected = self
+            .selected
+            .as_ref()
+            .and_then(|name| library.workout(name));
+        if let Some(template) = selected {
+            let workout = Workout::new(template, &profile.thresholds());
+            let zones = &profile.power_zones;
+            let mut segments = Scrollable::new(&mut self.segments_scroll)
+                .spacing(5)
+                .height(Length::Fill);
+            for segment in &workout.segments {
+                segments = segments.push(segment_row(segment, zones, profile.ftp));
+            }
+            // There's nothing to ride with until ANT+ sensors are supported
+            // unless simulated sensors were asked for.
+            let mut ride =
+                Button::new(&mut self.ride_button, Text::new("Ride").size(16)).padding(8);
+            let note = match (can_ride, profile.ftp) {
+                (false, _) => "Start 2by20 with --simulate to ride with simulated sensors",
+                (true, 0) => "Set an FTP to ride",
+                (true, _) => {
+                    ride = ride.on_press(Message::Ride(workout.name.clone()));
+                    ""
+                }
+            };
+            // Workouts are planned for a day after today by a profile that's
+            // been saved.
+            let mut plan =
+                Button::new(&mut self.plan_button, Text::new("Plan").size(16)).padding(8);
+            let today = chrono::Local::now().naive_local().date();
+            let date = chrono::NaiveDate::parse_from_str(&self.plan_date_input, "%Y-%m-%d");
+            let plan_note = match (date, profile.id) {
+                (_, None) => "Save the profile to plan workouts",
+                (Ok(date), Some(_)) if date > today => {
+                    plan = plan.on_press(Message::Plan(workout.name.clone(), date));
+                    ""
+                }
+                (Ok(_), Some(_)) => "Workouts are planned after today",
+                (Err(_), Some(_)) if self.plan_date_input.is_empty() => "",
+                (Err(_), Some(_)) => "Plan for YYYY-MM-DD",
+            };
+            detail = detail
+                .push(
+                    Row::new()
+                        .spacing(20)
+                        .push(Text::new(workout.name.as_str()).size(30))
+                        .push(ride)
+                        .push(Text::new(note).size(16)),
+                )
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .align_items(Align::Center)
+                        .push(
+                            TextInput::new(
+                                &mut self.plan_date_field,
+                                "YYYY-MM-DD",
+                                &self.plan_date_input,
+                                Message::PlanDateChanged,
+                            )
+                            .padding(8)
+                            .width(Length::Units(120))
+                            .size(16),
+                        )
+                        .push(plan)
+                        .push(Text::new(plan_note).size(16)),
+                )
+                .push(Text::new(workout.description.as_str()).size(16))
+                .push(Text::new(format!("Duration: {}", Duration(workout.duration))).size(16))
+                .push(chart(&workout, zones, profile.ftp))
+                .push(legend(zones, profile.ftp))
+                .push(segments);
+        }
+
+        Container::new(Row::new().spacing(20).push(workouts).push(detail))
+            .padding(20)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .into()
+    }
+}
+
+// The chart is a row of bars, one for each segment, with the width of the bar
+// the duration of the segment and the height the average target of the
+// segment. Range targets are solid up to the low end of the range and shaded
+// up to the high end. Free ride segments are drawn as a short grey bar.
+fn chart<'a>(workout: &Workout, zones: &PowerZones, ftp: u16) -> Element<'a, Message> {
+    let max = workout
+        .segments
+        .iter()
+        .filter_map(|segment| average_band(segment).map(|(_, high)| high))
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let height = |watts: u16| (u32::from(watts) * u32::from(CHART_HEIGHT) / u32::from(max)) as u16;
+    let mut chart = Row::new()
+        .width(Length::Fill)
+        .height(Length::Units(CHART_HEIGHT));
+    for segment in &workout.segments {
+        let ((low, high), color) = match (average_band(segment), average_watts(segment)) {
+            (Some((low, high)), Some(watts)) => (
+                (height(low).max(1), height(high).max(1)),
+                zone_color(zones.zone(watts, ftp)),
+            ),
+            _ => ((CHART_HEIGHT / 10, CHART_HEIGHT / 10), zone_color(None)),
+        };
+        let block = |height: u16, color: Color| {
+            Container::new(Space::new(Length::Fill, Length::Fill))
+                .width(Length::Fill)
+                .height(Length::Units(height))
+                .style(ZoneStyle(color))
+        };
+        chart = chart.push(
+            Column::new()
+                .width(Length::FillPortion(
+                    segment.duration.clamp(1, u32::from(u16::MAX)) as u16,
+                ))
+                .height(Length::Units(CHART_HEIGHT))
+                .push(Space::with_height(Length::Units(CHART_HEIGHT - high)))
+                .push(block(high - low, band_color(color)))
+                .push(block(low, color)),
+        );
+    }
+    chart.into()
+}
+
+fn legend<'a>(zones: &PowerZones, ftp: u16) -> Element<'a, Message> {
+    let mut legend = Row::new().spacing(15);
+    for (i, (zone, (low, high))) in zones.zones().iter().zip(zones.watts(ftp)).enumerate() {
+        let watts = match (ftp, high) {
+            (0, _) => String::new(),
+            (_, Some(high)) => format!(" {}-{}W", low, high),
+            (_, None) => format!(" {}W+", low),
+        };
+        legend = legend.push(
+            Row::new()
+                .spacing(5)
+                .push(
+                    Container::new(Space::new(Length::Fill, Length::Fill))
+                        .width(Length::Units(12))
+                        .height(Length::Units(12))
+                        .style(ZoneStyle(zone_color(Some(i)))),
+                )
+                .push(Text::new(format!("Z{} {}{}", i + 1, zone.name, watts)).size(14)),
+        );
+    }
+    legend.into()
+}
+
+// 0:00  5:00  Warmup  100W  100W  Z1 Active Recovery
+fn segment_row<'a>(segment: &WorkoutSegment, zones: &PowerZones, ftp: u16) -> Element<'a, Message> {
+    let cell = |text: String, width: u16| Text::new(text).size(16).width(Length::Units(width));
+    let zone = average_watts(segment)
+        .and_then(|watts| zones.zone(watts, ftp))
+        .map(|i| format!("Z{} {}", i + 1, zones.zones()[i].name))
+        .unwrap_or_default();
+    let target = match segment.power_start == segment.power_end {
+        true => segment.power_start.to_string(),
+        false => format!("{} > {}", segment.power_start, segment.power_end),
+    };
+    let watts = match (segment.watts_start, segment.watts_end) {
+        (Some(start), Some(end)) if start == end => format!("{}W", start),
+        (Some(start), Some(end)) => format!("{}W > {}W", start, end),
+        _ => String::new(),
+    };
+    Row::new()
+        .spacing(10)
+        .push(cell(Duration(segment.start).to_string(), 70))
+        .push(cell(Duration(segment.duration).to_string(), 70))
+        .push(cell(segment.interval.clone().unwrap_or_default(), 150))
+        .push(cell(target, 120))
+        .push(cell(watts, 120))
+        .push(cell(zone, 200))
+        .into()
+}
+
+fn average_watts(segment: &WorkoutSegment) -> Option<u16> {
+    let (start, end) = (segment.watts_start?, segment.watts_end?);
+    Some(((u32::from(start) + u32::from(end)) / 2) as u16)
+}
+
+// Average low and high watts of the target band of a segment.
+fn average_band(segment: &WorkoutSegment) -> Option<(u16, u16)> {
+    let (start, end) = (segment.band_start?, segment.band_end?);
+    let average = |start: u16, end: u16| ((u32::from(start) + u32::from(end)) / 2) as u16;
+    Some((average(start.0, end.0), average(start.1, end.1)))
+}
+
+// Colour of the shading between the low and high end of a range target.
+pub fn band_color(color: Color) -> Color {
+    Color {
+        a: color.a * 0.4,
+        ..color
+    }
+}
+
+// Colours for zones 1 to 7 going from grey through to purple. Zones past 7
+// in custom zone models reuse the last colour. Anything without a zone is
+// light grey.
+pub fn zone_color(zone: Option<usize>) -> Color {
+    const COLORS: [(f32, f32, f32); 7] = [
+        (0.6, 0.6, 0.6),
+        (0.2, 0.5, 0.9),
+        (0.2, 0.7, 0.3),
+        (0.95, 0.8, 0.1),
+        (0.95, 0.5, 0.1),
+        (0.85, 0.15, 0.15),
+        (0.5, 0.2, 0.7),
+    ];
+    match zone {
+        Some(zone) => {
+            let (r, g, b) = COLORS[zone.min(COLORS.len() - 1)];
+            Color::from_rgb(r, g, b)
+        }
+        None => Color::from_rgb(0.85, 0.85, 0.85),
+    }
+}
+
+pub struct ZoneStyle(pub Color);
+
+impl container::StyleSheet for ZoneStyle {
+    fn style(&self) -> container::Style {
+        container::Style {
+            background: Some(Background::Color(self.0)),
+            ..container::Style::default()
+        }
+    }
+}
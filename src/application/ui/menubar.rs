@@ -1,4 +1,4 @@
-use crate::application::user_profile::UserProfile;
+use crate::application::profile_store::{ProfileId, ProfileStore};
 use crate::application::Message;
 use iced::{
     button, pick_list, Button, Container, HorizontalAlignment, Length, PickList, Row, Space, Text,
@@ -8,13 +8,39 @@ use iced::{
 #[derive(Debug, Default, Clone)]
 pub struct MenuBar {
     library_button: button::State,
+    history_button: button::State,
+    ride_button: button::State,
     devices_button: button::State,
     userprofile_button: button::State,
-    userprofiles_picklist: pick_list::State<UserProfile>,
+    userprofiles_picklist: pick_list::State<ProfileChoice>,
+}
+
+// Entries in the profile pick list, each profile by name and an entry to
+// create a profile.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ProfileChoice {
+    Profile(ProfileId, String),
+    New,
+}
+
+impl std::fmt::Display for ProfileChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Profile(_, name) => write!(f, "{}", name),
+            Self::New => write!(f, "New..."),
+        }
+    }
 }
 
 impl MenuBar {
-    pub fn view(&mut self, profiles: &[UserProfile], active_user_profile: usize) -> Row<Message> {
+    // The selected profile is None while a profile is being created. The Ride
+    // button is only shown while there's a ride to go back to.
+    pub fn view(
+        &mut self,
+        profiles: &ProfileStore,
+        selected: Option<ProfileId>,
+        riding: bool,
+    ) -> Row<Message> {
         let menu_button = |state, label, message| {
             let label = Text::new(label)
                 .size(16)
@@ -29,14 +55,27 @@ impl MenuBar {
                 .on_press(message)
         };
 
+        let mut buttons = Row::new()
+            .width(Length::Fill)
+            .push(menu_button(
+                &mut self.library_button,
+                "Library",
+                Message::ShowLibrary,
+            ))
+            .push(menu_button(
+                &mut self.history_button,
+                "History",
+                Message::ShowHistory,
+            ));
+        if riding {
+            buttons = buttons.push(menu_button(
+                &mut self.ride_button,
+                "Ride",
+                Message::ShowRide,
+            ));
+        }
         let c1 = Container::new(
-            Row::new()
-                .width(Length::Fill)
-                .push(menu_button(
-                    &mut self.library_button,
-                    "Library",
-                    Message::ShowLibrary,
-                ))
+            buttons
                 .push(menu_button(
                     &mut self.devices_button,
                     "Devices",
@@ -51,8 +90,16 @@ impl MenuBar {
         .width(Length::FillPortion(3))
         .height(Length::Fill);
 
-        // TODO: Add pick list of available user profiles. Display the default
-        // profile and list other available profiles to choose from.
+        let mut choices: Vec<ProfileChoice> = profiles
+            .iter()
+            .map(|(id, profile)| ProfileChoice::Profile(id, profile.name.clone()))
+            .collect();
+        let selected = choices
+            .iter()
+            .find(|choice| matches!(choice, ProfileChoice::Profile(id, _) if Some(*id) == selected))
+            .cloned()
+            .unwrap_or(ProfileChoice::New);
+        choices.push(ProfileChoice::New);
         let c3 = Container::new(
             Row::new()
                 .width(Length::Fill)
@@ -64,8 +111,8 @@ impl MenuBar {
                 )
                 .push(PickList::new(
                     &mut self.userprofiles_picklist,
-                    profiles.to_owned(),
-                    Some(profiles[active_user_profile].clone()),
+                    choices,
+                    Some(selected),
                     Message::UserProfileSelected,
                 )),
         )
@@ -0,0 +1,627 @@
+// The Ride screen shows a workout being ridden. The workout is charted with
+// the power ridden drawn as a line over the targets as each second is
+// recorded, with tiles for what's being ridden now and what's coming up, and
+// a summary of the lap being ridden and the lap before it. Once
+// the ride is over the screen shows a summary of the ride, and the result of
+// an FTP test with the option to update the profile's FTP.
+//
+// The ride can be controlled from the keyboard with the keys in the profile's
+// settings, which are listed on the screen when the help key is pressed.
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::application::compliance::Compliance;
+use crate::application::ftp_test::FtpTest;
+use crate::application::power_curve::duration_label;
+use crate::application::profile_settings::{RideAction, RideMetric};
+use crate::application::profile_store::ProfileId;
+use crate::application::sensors::Sensors;
+use crate::application::session::Session;
+use crate::application::stats::{LapSummary, Stats};
+use crate::application::types::Duration;
+use crate::application::ui::library::{band_color, zone_color, ZoneStyle};
+use crate::application::user_profile::UserProfile;
+use crate::application::workout::WorkoutSegment;
+use iced::{
+    button, Button, Color, Column, Container, Element, Length, Row, Space, Text, VerticalAlignment,
+};
+
+// The chart is drawn as bars, each the average of an equal slice of the
+// workout.
+const CHART_BARS: u32 = 120;
+const CHART_HEIGHT: u16 = 250;
+// Thickness of the line drawn at the power ridden.
+const LINE: u16 = 3;
+// Watts and percent each press of a target or intensity key moves targets by.
+const TARGET_STEP: i16 = 5;
+const INTENSITY_STEP: i16 = 1;
+
+// Ride metric tiles shown side by side before starting another row.
+const METRICS_PER_ROW: usize = 5;
+// Widths of the columns of the lap table.
+const LAP_WIDTHS: [u16; 8] = [100, 70, 70, 70, 70, 50, 50, 70];
+
+// A workout being ridden, or the summary of the ride once it's over.
+pub enum Ride {
+    Riding {
+        // Profile riding, kept so the ride is saved for the right profile if
+        // the active profile changes during the ride.
+        profile: ProfileId,
+        // The profile as it was when the ride started. The ride is saved
+        // with it if the profile can't be found when the ride ends.
+        rider: Box<UserProfile>,
+        session: Session,
+        sensors: Box<dyn Sensors>,
+        // When the next second of the ride is due.
+        next_step: Instant,
+    },
+    Finished(Summary),
+}
+
+pub struct Summary {
+    pub profile: ProfileId,
+    pub workout: String,
+    // Day the ride was ridden, which an FTP from the ride takes effect on.
+    pub date: Option<chrono::NaiveDate>,
+    pub stats: Stats,
+    pub compliance: Option<Compliance>,
+    // Path the ride was saved to, or why it couldn't be saved.
+    pub saved: Result<PathBuf, String>,
+    // Durations the ride set a new best for.
+    pub records: Vec<(u32, u16)>,
+    // The test and FTP from it when the workout was an FTP test, and whether
+    // the profile has been updated with it.
+    pub ftp_test: Option<(FtpTest, u16)>,
+    pub ftp_updated: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    pause_button: button::State,
+    lap_button: button::State,
+    erg_button: button::State,
+    skip_button: button::State,
+    help_button: button::State,
+    end_button: button::State,
+    ftp_button: button::State,
+    done_button: button::State,
+    // Whether the keys for the ride are listed.
+    show_help: bool,
+}
+
+// Ending the ride, updating FTP and leaving the summary are handled by the
+// application as they save the ride and profiles.
+#[derive(Debug, Clone)]
+pub enum Message {
+    TogglePause,
+    Lap,
+    ToggleErg,
+    AdjustTarget(i16),
+    AdjustIntensity(i16),
+    SkipSegment,
+    ToggleHelp,
+    End,
+    UpdateFtp,
+    Done,
+}
+
+impl Message {
+    pub fn from_action(action: RideAction) -> Self {
+        match action {
+            RideAction::Pause => Self::TogglePause,
+            RideAction::TargetUp => Self::AdjustTarget(TARGET_STEP),
+            RideAction::TargetDown => Self::AdjustTarget(-TARGET_STEP),
+            RideAction::IntensityUp => Self::AdjustIntensity(INTENSITY_STEP),
+            RideAction::IntensityDown => Self::AdjustIntensity(-INTENSITY_STEP),
+            RideAction::Lap => Self::Lap,
+            RideAction::SkipSegment => Self::SkipSegment,
+            RideAction::Erg => Self::ToggleErg,
+            RideAction::Help => Self::ToggleHelp,
+        }
+    }
+}
+
+impl State {
+    pub fn update(&mut self, message: Message, ride: &mut Ride) {
+        if let Message::ToggleHelp = message {
+            self.show_help = !self.show_help;
+        }
+        if let Ride::Riding { session, .. } = ride {
+            match message {
+                Message::TogglePause => session.toggle_pause(),
+                Message::Lap => session.new_lap(),
+                Message::ToggleErg => session.set_erg(!session.is_erg()),
+                Message::AdjustTarget(watts) => session.adjust_target(watts),
+                Message::AdjustIntensity(percent) => session.adjust_intensity(percent),
+                Message::SkipSegment => session.skip_segment(),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn view<'a>(&'a mut self, ride: &Ride, profile: &UserProfile) -> Element<'a, Message> {
+        let content = match ride {
+            Ride::Riding { session, .. } => riding(self, session, profile),
+            Ride::Finished(summary) => finished(self, summary, profile),
+        };
+        Container::new(content)
+            .padding(20)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .into()
+    }
+}
+
+fn riding<'a>(
+    state: &'a mut State,
+    session: &Session,
+    profile: &UserProfile,
+) -> Element<'a, Message> {
+    let watts = |w: Option<u16>| w.map_or_else(|| String::from("-"), |w| format!("{}W", w));
+    let optional = |value: Option<u8>| value.map_or_else(|| String::from("-"), |v| v.to_string());
+    let sample = session
+        .records()
+        .last()
+        .map(|r| r.sample)
+        .unwrap_or_default();
+    let segment = session.segment().map(|i| &session.workout.segments[i]);
+    let next = session.segment().and_then(|i| {
+        session.workout.segments[i + 1..]
+            .iter()
+            .find(|s| s.duration > 0)
+    });
+    let mut status = match (session.is_paused(), session.is_erg()) {
+        (true, _) => String::from("Paused"),
+        (false, false) => String::from("ERG off"),
+        (false, true) => String::new(),
+    };
+    // Targets moved by the rider, i.e. +10W 98%
+    let adjustment = match session.adjustment() {
+        (0, 0) => String::new(),
+        (0, percent) => format!("{}%", 100 + percent),
+        (watts, 0) => format!("{:+}W", watts),
+        (watts, percent) => format!("{:+}W {}%", watts, 100 + percent),
+    };
+    if !adjustment.is_empty() {
+        status = format!("{}  Target {}", status, adjustment)
+            .trim()
+            .to_string();
+    }
+
+    // The profile's ride metrics are shown in the order listed, a row of tiles
+    // at a time.
+    let power = session.smoothed_power(profile.settings.power_smoothing.seconds());
+    let mut metrics = Column::new().spacing(10);
+    for row in profile.settings.ride_metrics.chunks(METRICS_PER_ROW) {
+        let mut tiles = Row::new().spacing(10);
+        for metric in row {
+            let value = match metric {
+                RideMetric::Elapsed => Duration(session.elapsed()).to_string(),
+                RideMetric::Remaining => Duration(session.remaining()).to_string(),
+                RideMetric::Lap => (session.lap() + 1).to_string(),
+                RideMetric::LapPower => watts(
+                    session
+                        .lap_summary(session.lap())
+                        .and_then(|lap| lap.stats.average_power),
+                ),
+                RideMetric::Target => watts(session.target()),
+                RideMetric::Power => watts(power),
+                RideMetric::WattsPerKg => power
+                    .and_then(|p| profile.watts_per_kg(p))
+                    .map_or_else(|| String::from("-"), |w| format!("{:.1}", w)),
+                RideMetric::HeartRate => optional(sample.heart_rate),
+                RideMetric::Cadence => optional(sample.cadence),
+                RideMetric::WPrimeBalance => session
+                    .w_prime_balance(profile.cp, profile.w_prime)
+                    .map_or_else(|| String::from("-"), |j| format!("{:.1}kJ", j / 1000.0)),
+            };
+            tiles = tiles.push(tile(&metric.to_string(), value));
+        }
+        metrics = metrics.push(tiles);
+    }
+    let ahead =
+        Row::new()
+            .spacing(10)
+            .push(tile(
+                "Segment left",
+                segment.map_or_else(
+                    || String::from("-"),
+                    |s| Duration(s.end().saturating_sub(session.elapsed())).to_string(),
+                ),
+            ))
+            .push(tile("3s power", watts(session.smoothed_power(3))))
+            .push(
+                Container::new(
+                    Column::new().push(Text::new("Targets").size(16)).push(
+                        Text::new(
+                            segment
+                                .map(segment_targets)
+                                .filter(|targets| !targets.is_empty())
+                                .map_or_else(|| String::from("-"), |targets| targets.join(" ")),
+                        )
+                        .size(24),
+                    ),
+                )
+                .padding(10)
+                .width(Length::Fill),
+            )
+            .push(
+                Container::new(Column::new().push(Text::new("Next").size(16)).push(
+                    Text::new(next.map_or_else(|| String::from("-"), next_segment)).size(24),
+                ))
+                .padding(10)
+                .width(Length::FillPortion(2)),
+            );
+
+    let button = |state, label: &str, message| {
+        Button::new(state, Text::new(label).size(16))
+            .padding(8)
+            .on_press(message)
+    };
+    let pause = if session.is_paused() {
+        "Resume"
+    } else {
+        "Pause"
+    };
+    let erg = if session.is_erg() {
+        "ERG off"
+    } else {
+        "ERG on"
+    };
+    let help = if state.show_help { "Hide keys" } else { "Keys" };
+    let show_help = state.show_help;
+    let controls = Row::new()
+        .spacing(10)
+        .push(button(&mut state.pause_button, pause, Message::TogglePause))
+        .push(button(&mut state.lap_button, "Lap", Message::Lap))
+        .push(button(&mut state.erg_button, erg, Message::ToggleErg))
+        .push(button(
+            &mut state.skip_button,
+            "Skip segment",
+            Message::SkipSegment,
+        ))
+        .push(button(&mut state.help_button, help, Message::ToggleHelp))
+        .push(button(&mut state.end_button, "End ride", Message::End));
+
+    // The keys are listed in place of the tiles while they're shown.
+    if show_help {
+        return Column::new()
+            .spacing(15)
+            .push(Text::new(session.workout.name.as_str()).size(30))
+            .push(chart(session, profile))
+            .push(keys(profile))
+            .push(controls)
+            .into();
+    }
+    Column::new()
+        .spacing(15)
+        .push(
+            Row::new()
+                .spacing(20)
+                .push(Text::new(session.workout.name.as_str()).size(30))
+                .push(
+                    Text::new(status)
+                        .size(24)
+                        .vertical_alignment(VerticalAlignment::Bottom),
+                ),
+        )
+        .push(chart(session, profile))
+        .push(metrics)
+        .push(ahead)
+        .push(laps(session))
+        .push(controls)
+        .into()
+}
+
+// The lap being ridden and the lap before it.
+fn laps<'a>(session: &Session) -> Element<'a, Message> {
+    let mut laps = Column::new().spacing(5).push(lap_row(
+        ["Lap", "Time", "Avg", "Max", "NP", "HR", "Cad", "In band"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect(),
+    ));
+    let current = session.lap();
+    for lap in (current.saturating_sub(1)..=current).rev() {
+        if let Some(summary) = session.lap_summary(lap) {
+            laps = laps.push(lap_row(lap_values(&summary)));
+        }
+    }
+    Container::new(laps).padding(10).into()
+}
+
+// Lap 3  2:00  250W  310W  262W  150  90  85%
+fn lap_values(lap: &LapSummary) -> Vec<String> {
+    let watts = |w: Option<u16>| w.map_or_else(|| String::from("-"), |w| format!("{}W", w));
+    let optional = |value: Option<u8>| value.map_or_else(|| String::from("-"), |v| v.to_string());
+    let stats = &lap.stats;
+    vec![
+        format!("Lap {}", lap.lap + 1),
+        Duration(stats.duration).to_string(),
+        watts(stats.average_power),
+        watts(stats.max_power),
+        watts(stats.normalized_power),
+        optional(stats.average_heart_rate),
+        optional(stats.average_cadence),
+        lap.compliance
+            .map_or_else(|| String::from("-"), |c| format!("{:.0}%", c.in_band)),
+    ]
+}
+
+fn lap_row<'a>(values: Vec<String>) -> Element<'a, Message> {
+    let mut row = Row::new().spacing(10);
+    for (value, width) in values.into_iter().zip(LAP_WIDTHS.iter()) {
+        row = row.push(Text::new(value).size(16).width(Length::Units(*width)));
+    }
+    row.into()
+}
+
+fn finished<'a>(
+    state: &'a mut State,
+    summary: &Summary,
+    profile: &UserProfile,
+) -> Element<'a, Message> {
+    let watts = |w: Option<u16>| w.map_or_else(|| String::from("-"), |w| format!("{}W", w));
+    let decimal = |value: Option<f32>, places: usize| {
+        value.map_or_else(|| String::from("-"), |v| format!("{:.*}", places, v))
+    };
+    let mut content = Column::new()
+        .spacing(15)
+        .push(Text::new(summary.workout.as_str()).size(30));
+    content = content.push(match &summary.saved {
+        Ok(path) => Text::new(format!("Saved ride to {}", path.display())).size(16),
+        Err(e) => Text::new(format!("Unable to save ride: {}", e))
+            .size(16)
+            .color(Color::from_rgb(0.8, 0.2, 0.2)),
+    });
+    let stats = &summary.stats;
+    content = content
+        .push(
+            Row::new()
+                .spacing(10)
+                .push(tile("Time", Duration(stats.duration).to_string()))
+                .push(tile("Avg", watts(stats.average_power)))
+                .push(tile("NP", watts(stats.normalized_power)))
+                .push(tile("IF", decimal(stats.intensity_factor, 2)))
+                .push(tile("TSS", decimal(stats.tss, 0))),
+        )
+        .push(
+            Row::new()
+                .spacing(10)
+                .push(tile("kJ", decimal(stats.work, 0)))
+                .push(tile(
+                    "Heart rate",
+                    stats
+                        .average_heart_rate
+                        .map_or_else(|| String::from("-"), |hr| hr.to_string()),
+                ))
+                .push(tile(
+                    "In band",
+                    decimal(summary.compliance.map(|c| c.in_band), 0),
+                ))
+                .push(tile(
+                    "Off by",
+                    summary.compliance.map_or_else(
+                        || String::from("-"),
+                        |c| format!("{:.0}W", c.average_deviation),
+                    ),
+                ))
+                .push(Space::new(Length::Fill, Length::Shrink)),
+        );
+    if !summary.records.is_empty() {
+        let records: Vec<String> = summary
+            .records
+            .iter()
+            .map(|(seconds, watts)| format!("{} {}W", duration_label(*seconds), watts))
+            .collect();
+        content = content.push(
+            Text::new(format!("New bests: {}", records.join(", ")))
+                .size(16)
+                .color(Color::from_rgb(0.2, 0.6, 0.3)),
+        );
+    }
+    if let Some((_, ftp)) = summary.ftp_test {
+        content = content.push(match summary.ftp_updated {
+            true => Element::from(Text::new(format!("Updated FTP to {}W", ftp)).size(16)),
+            false => Row::new()
+                .spacing(10)
+                .push(
+                    Text::new(format!(
+                        "FTP test result is {}W, {}'s FTP is {}W.",
+                        ftp, profile.name, profile.ftp
+                    ))
+                    .size(16)
+                    .vertical_alignment(VerticalAlignment::Center),
+                )
+                .push(
+                    Button::new(&mut state.ftp_button, Text::new("Update FTP").size(16))
+                        .padding(8)
+                        .on_press(Message::UpdateFtp),
+                )
+                .into(),
+        });
+    }
+    content
+        .push(
+            Button::new(&mut state.done_button, Text::new("Done").size(16))
+                .padding(8)
+                .on_press(Message::Done),
+        )
+        .into()
+}
+
+// space  Pause or resume
+fn keys<'a>(profile: &UserProfile) -> Element<'a, Message> {
+    let mut keys = Column::new().spacing(5).push(Text::new("Keys").size(20));
+    for action in RideAction::ALL.iter() {
+        keys = keys.push(
+            Row::new()
+                .spacing(10)
+                .push(
+                    Text::new(profile.settings.ride_keys.key(*action))
+                        .size(16)
+                        .width(Length::Units(100)),
+                )
+                .push(Text::new(action.to_string()).size(16)),
+        );
+    }
+    Container::new(keys).padding(10).into()
+}
+
+// Label over a big number.
+fn tile<'a>(label: &str, value: String) -> Element<'a, Message> {
+    Container::new(
+        Column::new()
+            .push(Text::new(label).size(16))
+            .push(Text::new(value).size(40)),
+    )
+    .padding(10)
+    .width(Length::Fill)
+    .into()
+}
+
+// On/Off 0:30 @ 300W 95rpm, or 5:00 150W > 250W for a ramp. A segment
+// without any target is free ride.
+fn next_segment(segment: &WorkoutSegment) -> String {
+    let power = match (segment.watts_start, segment.watts_end) {
+        (Some(start), Some(end)) if start == end => Some(format!("@ {}W", start)),
+        (Some(start), Some(end)) => Some(format!("{}W > {}W", start, end)),
+        _ => None,
+    };
+    let targets: Vec<String> = power.into_iter().chain(segment_targets(segment)).collect();
+    let targets = match targets.is_empty() {
+        true => String::from("free ride"),
+        false => targets.join(" "),
+    };
+    let interval = segment
+        .interval
+        .as_ref()
+        .map_or_else(String::new, |name| format!("{} ", name));
+    format!("{}{} {}", interval, Duration(segment.duration), targets)
+}
+
+// Cadence, heart rate and RPE targets of a segment, i.e. 90rpm 150-160bpm
+// RPE 7. Heart rate targets relative to LTHR are shown in bpm once the
+// profile has an LTHR.
+fn segment_targets(segment: &WorkoutSegment) -> Vec<String> {
+    let heart_rate = match segment.heart_rate_bpm {
+        Some((low, high)) if low == high => Some(format!("{}bpm", low)),
+        Some((low, high)) => Some(format!("{}-{}bpm", low, high)),
+        None => segment.heart_rate.map(|hr| hr.to_string()),
+    };
+    segment
+        .cadence
+        .map(|cadence| cadence.to_string())
+        .into_iter()
+        .chain(heart_rate)
+        .chain(segment.rpe.map(|rpe| format!("RPE {}", rpe.0)))
+        .collect()
+}
+
+// Targets are drawn as bars coloured by zone with the power ridden as a line
+// across each bar, and range targets shaded between the low and high end of
+// the range. The part of the workout still to ride is faded. Free ride is
+// drawn as a short grey bar like on the Library screen.
+fn chart<'a>(session: &Session, profile: &UserProfile) -> Element<'a, Message> {
+    let workout = &session.workout;
+    let size = workout.duration / CHART_BARS + 1;
+    let average = |sum: u32, count: u32| match count {
+        0 => None,
+        n => Some((sum / n) as u16),
+    };
+    // (target, low, high) of each bar.
+    let targets: Vec<Option<(u16, u16, u16)>> = (0..workout.duration)
+        .step_by(size as usize)
+        .map(|start| {
+            let seconds = start..(start + size).min(workout.duration);
+            let watts: Vec<(u32, u32, u32)> = seconds
+                .filter_map(|second| {
+                    let (low, high) = workout.band_at(second)?;
+                    let watts = workout.watts_at(second)?;
+                    Some((u32::from(watts), u32::from(low), u32::from(high)))
+                })
+                .collect();
+            let count = watts.len() as u32;
+            Some((
+                average(watts.iter().map(|w| w.0).sum(), count)?,
+                average(watts.iter().map(|w| w.1).sum(), count)?,
+                average(watts.iter().map(|w| w.2).sum(), count)?,
+            ))
+        })
+        .collect();
+    // Records are bucketed by the second of the workout they were ridden at.
+    let mut ridden = vec![(0, 0); targets.len()];
+    for record in session.records() {
+        let bar = (record.elapsed / size) as usize;
+        if let (Some(watts), Some(sum)) = (record.sample.power, ridden.get_mut(bar)) {
+            *sum = (sum.0 + u32::from(watts), sum.1 + 1);
+        }
+    }
+    let ridden: Vec<Option<u16>> = ridden
+        .into_iter()
+        .map(|(sum, count)| average(sum, count))
+        .collect();
+    let max = targets
+        .iter()
+        .flatten()
+        .map(|(_, _, high)| *high)
+        .chain(ridden.iter().flatten().copied())
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let height = |watts: u16| (u32::from(watts) * u32::from(CHART_HEIGHT) / u32::from(max)) as u16;
+
+    let mut chart = Row::new()
+        .width(Length::Fill)
+        .height(Length::Units(CHART_HEIGHT));
+    for (bar, (target, power)) in targets.into_iter().zip(ridden).enumerate() {
+        let (target, color) = match target {
+            Some((watts, low, high)) => (
+                (height(low), height(high)),
+                zone_color(profile.power_zones.zone(watts, profile.ftp)),
+            ),
+            None => ((CHART_HEIGHT / 10, CHART_HEIGHT / 10), zone_color(None)),
+        };
+        let color = match bar as u32 * size >= session.elapsed() {
+            true => Color { a: 0.35, ..color },
+            false => color,
+        };
+        chart = chart.push(bar_column(target, color, power.map(height)));
+    }
+    chart.into()
+}
+
+// A bar of the chart built from blocks stacked from the top of the chart
+// down: empty space above the target, the shaded range of a range target, the
+// target, and the line at the power ridden which may be above or inside the
+// target.
+fn bar_column<'a>(target: (u16, u16), color: Color, power: Option<u16>) -> Element<'a, Message> {
+    let target_top = CHART_HEIGHT - target.0.min(CHART_HEIGHT);
+    let band_top = CHART_HEIGHT - target.1.min(CHART_HEIGHT);
+    let line_top = power.map(|power| CHART_HEIGHT - power.clamp(LINE, CHART_HEIGHT));
+    let mut edges = vec![0, band_top, target_top, CHART_HEIGHT];
+    if let Some(top) = line_top {
+        edges.push(top);
+        edges.push(top + LINE);
+    }
+    edges.sort_unstable();
+    edges.dedup();
+    let mut column = Column::new()
+        .width(Length::Fill)
+        .height(Length::Units(CHART_HEIGHT));
+    for edge in edges.windows(2) {
+        let (top, bottom) = (edge[0], edge[1]);
+        let block = Container::new(Space::new(Length::Fill, Length::Fill))
+            .width(Length::Fill)
+            .height(Length::Units(bottom - top));
+        column = column.push(match line_top {
+            Some(line) if top >= line && top < line + LINE => {
+                block.style(ZoneStyle(Color::from_rgb(0.1, 0.1, 0.1)))
+            }
+            _ if top >= target_top => block.style(ZoneStyle(color)),
+            _ if top >= band_top => block.style(ZoneStyle(band_color(color))),
+            _ => block,
+        });
+    }
+    column.into()
+}
@@ -4,63 +4,333 @@
 // the application. Once a user profile exists, then the starting screen will
 // default to the Library screen.
 //
-use crate::application::user_profile::UserProfile;
+use crate::application::profile_settings::{PowerSmoothing, ProfileSettings, RideMetric, Units};
+use crate::application::types::{Weight, WeightUnit};
+use crate::application::user_profile::{FtpSource, UserProfile};
+use crate::application::zones::HeartRateZoneModel;
 use iced::{
-    button, text_input, Align, Button, Column, Container, Element, Length, Row, Text, TextInput,
+    button, text_input, Align, Button, Checkbox, Color, Column, Container, Element, Length, Radio,
+    Row, Text, TextInput,
 };
+use std::convert::TryFrom;
 
 #[derive(Debug, Clone, Default)]
 pub struct State {
     name_input: String,
     ftp_input: String,
+    // Date as YYYY-MM-DD and source of the FTP entered, which is added to the
+    // FTP history when it's changed. An empty date is today.
+    ftp_date_input: String,
+    ftp_source: Option<FtpSource>,
+    ftp_changed: bool,
+    // Dates of FTP history entries removed while editing.
+    removed_ftp: Vec<chrono::NaiveDate>,
+    // MAP and CP in watts and W' in joules.
+    map_input: String,
+    cp_input: String,
+    w_prime_input: String,
+    max_hr_input: String,
+    resting_hr_input: String,
+    lthr_input: String,
+    // Weights are entered in the weight unit, height in cm and the birth date
+    // as YYYY-MM-DD.
+    weight_input: String,
+    bike_weight_input: String,
+    height_input: String,
+    birth_date_input: String,
+    // Zone model and settings picked while editing. None shows the
+    // profile's setting.
+    heart_rate_zones: Option<HeartRateZoneModel>,
+    settings: Option<ProfileSettings>,
     name_input_field: text_input::State,
     ftp_input_field: text_input::State,
+    ftp_date_input_field: text_input::State,
+    remove_ftp_buttons: Vec<button::State>,
+    map_input_field: text_input::State,
+    cp_input_field: text_input::State,
+    w_prime_input_field: text_input::State,
+    max_hr_input_field: text_input::State,
+    resting_hr_input_field: text_input::State,
+    lthr_input_field: text_input::State,
+    weight_input_field: text_input::State,
+    bike_weight_input_field: text_input::State,
+    height_input_field: text_input::State,
+    birth_date_input_field: text_input::State,
     save_button: button::State,
     delete_button: button::State,
     editing: bool,
+    // Why the last save failed.
+    save_error: Option<&'static str>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     NameInputChanged(String),
     FtpInputChanged(String),
-    SaveProfile(String, u16),
+    FtpDateInputChanged(String),
+    FtpSourceSelected(FtpSource),
+    RemoveFtp(chrono::NaiveDate),
+    MapInputChanged(String),
+    CpInputChanged(String),
+    WPrimeInputChanged(String),
+    MaxHrInputChanged(String),
+    RestingHrInputChanged(String),
+    LthrInputChanged(String),
+    HeartRateZonesSelected(HeartRateZoneModel),
+    WeightInputChanged(String),
+    BikeWeightInputChanged(String),
+    HeightInputChanged(String),
+    BirthDateInputChanged(String),
+    UnitsSelected(Units),
+    ErgModeToggled(bool),
+    PowerSmoothingSelected(u8),
+    RideMetricToggled(RideMetric, bool),
+    // The edited profile to save.
+    SaveProfile(Box<UserProfile>),
+    // The profile couldn't be saved.
+    SaveFailed(&'static str),
     DeleteProfile,
     Editing(bool),
     Clear,
 }
 
+// Only accept input that is empty or a number of type T.
+fn numeric_input<T: std::str::FromStr>(input: &mut String, value: String) {
+    if value.is_empty() || value.parse::<T>().is_ok() {
+        *input = value;
+    }
+}
+
+// Weight from a weight field. An empty field is no weight.
+fn weight_input(input: &str, unit: WeightUnit) -> Result<Option<Weight>, &'static str> {
+    match input {
+        "" => Ok(None),
+        input => input
+            .parse()
+            .map_err(|_| "Weight must be a number")
+            .and_then(|value| Weight::new(value, unit))
+            .map(Some),
+    }
+}
+
+// Re-enters a weight field in another unit.
+fn convert_weight_input(input: &mut String, from: WeightUnit, to: WeightUnit) {
+    if let Ok(Some(weight)) = weight_input(input, from) {
+        *input = weight.value(to).to_string();
+    }
+}
+
 impl State {
     pub fn update(&mut self, message: Message) {
+        // A failed save is shown until something else happens on the screen.
+        self.save_error = None;
         match message {
+            Message::SaveFailed(e) => self.save_error = Some(e),
             Message::NameInputChanged(value) => {
                 self.editing = true;
                 self.name_input = value;
             }
             Message::FtpInputChanged(value) => {
                 self.editing = true;
-                match value.parse::<u16>() {
-                    Ok(_) => self.ftp_input = value,
-                    Err(_) => {
-                        if value.is_empty() {
-                            self.ftp_input = value;
-                        }
-                    }
+                self.ftp_changed = true;
+                numeric_input::<u16>(&mut self.ftp_input, value);
+            }
+            Message::FtpDateInputChanged(value) => {
+                self.editing = true;
+                if value.len() <= 10 {
+                    self.ftp_changed = true;
+                    self.ftp_date_input = value;
+                }
+            }
+            Message::FtpSourceSelected(source) => {
+                self.editing = true;
+                self.ftp_changed = true;
+                self.ftp_source = Some(source);
+            }
+            Message::RemoveFtp(date) => {
+                // The FTP goes back to the entry before, which is shown once
+                // the profile is saved.
+                self.editing = true;
+                self.removed_ftp.push(date);
+                if !self.ftp_changed {
+                    self.ftp_input.clear();
+                }
+            }
+            Message::MapInputChanged(value) => {
+                self.editing = true;
+                numeric_input::<u16>(&mut self.map_input, value);
+            }
+            Message::CpInputChanged(value) => {
+                self.editing = true;
+                numeric_input::<u16>(&mut self.cp_input, value);
+            }
+            Message::WPrimeInputChanged(value) => {
+                self.editing = true;
+                numeric_input::<u32>(&mut self.w_prime_input, value);
+            }
+            Message::MaxHrInputChanged(value) => {
+                self.editing = true;
+                numeric_input::<u8>(&mut self.max_hr_input, value);
+            }
+            Message::RestingHrInputChanged(value) => {
+                self.editing = true;
+                numeric_input::<u8>(&mut self.resting_hr_input, value);
+            }
+            Message::LthrInputChanged(value) => {
+                self.editing = true;
+                numeric_input::<u8>(&mut self.lthr_input, value);
+            }
+            Message::HeartRateZonesSelected(model) => {
+                self.editing = true;
+                self.heart_rate_zones = Some(model);
+            }
+            Message::WeightInputChanged(value) => {
+                self.editing = true;
+                numeric_input::<f32>(&mut self.weight_input, value);
+            }
+            Message::BikeWeightInputChanged(value) => {
+                self.editing = true;
+                numeric_input::<f32>(&mut self.bike_weight_input, value);
+            }
+            Message::HeightInputChanged(value) => {
+                self.editing = true;
+                numeric_input::<u16>(&mut self.height_input, value);
+            }
+            Message::BirthDateInputChanged(value) => {
+                self.editing = true;
+                if value.len() <= 10 {
+                    self.birth_date_input = value;
+                }
+            }
+            Message::UnitsSelected(units) => {
+                // Weights already entered are converted so they don't change
+                // when the units do. The settings are only known once the
+                // screen has been drawn.
+                if let Some(settings) = self.settings.as_mut() {
+                    let (from, to) = (settings.units.weight_unit(), units.weight_unit());
+                    convert_weight_input(&mut self.weight_input, from, to);
+                    convert_weight_input(&mut self.bike_weight_input, from, to);
+                    settings.units = units;
+                    self.editing = true;
+                }
+            }
+            Message::ErgModeToggled(erg_mode) => {
+                if let Some(settings) = self.settings.as_mut() {
+                    settings.erg_mode = erg_mode;
+                    self.editing = true;
+                }
+            }
+            Message::PowerSmoothingSelected(seconds) => {
+                if let (Some(settings), Ok(smoothing)) =
+                    (self.settings.as_mut(), PowerSmoothing::try_from(seconds))
+                {
+                    settings.power_smoothing = smoothing;
+                    self.editing = true;
+                }
+            }
+            Message::RideMetricToggled(metric, show) => {
+                if let Some(settings) = self.settings.as_mut() {
+                    settings.show_metric(metric, show);
+                    self.editing = true;
                 }
             }
             Message::Clear => {
                 self.editing = false;
                 self.name_input.clear();
                 self.ftp_input.clear();
+                self.clear_ftp_entry();
+                self.map_input.clear();
+                self.cp_input.clear();
+                self.w_prime_input.clear();
+                self.max_hr_input.clear();
+                self.resting_hr_input.clear();
+                self.lthr_input.clear();
+                self.weight_input.clear();
+                self.bike_weight_input.clear();
+                self.height_input.clear();
+                self.birth_date_input.clear();
+                self.heart_rate_zones = None;
+                self.settings = None;
+            }
+            Message::Editing(editing) => {
+                // The FTP entered has been saved.
+                if !editing {
+                    self.clear_ftp_entry();
+                }
+                self.editing = editing;
             }
-            Message::Editing(editing) => self.editing = editing,
             _ => {}
         }
     }
 
+    fn clear_ftp_entry(&mut self) {
+        self.ftp_date_input.clear();
+        self.ftp_source = None;
+        self.ftp_changed = false;
+        self.removed_ftp.clear();
+    }
+
+    // The profile with the values in the form applied. Empty optional fields
+    // unset the value. Removed FTP history entries are dropped, and a changed
+    // FTP is added to the FTP history from the date and source entered, which
+    // default to today and manual. Fails when a field can't be read.
+    fn edited(&self, profile: &UserProfile) -> Result<UserProfile, &'static str> {
+        let settings = self
+            .settings
+            .clone()
+            .unwrap_or_else(|| profile.settings.clone());
+        let weight_unit = settings.units.weight_unit();
+        let birth_date = match self.birth_date_input.as_str() {
+            "" => None,
+            input => Some(
+                chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                    .map_err(|_| "Birth date must be YYYY-MM-DD")?,
+            ),
+        };
+        let mut edited = UserProfile {
+            name: self.name_input.clone(),
+            map: self.map_input.parse().ok(),
+            cp: self.cp_input.parse().ok(),
+            w_prime: self.w_prime_input.parse().ok(),
+            max_hr: self.max_hr_input.parse().ok(),
+            resting_hr: self.resting_hr_input.parse().ok(),
+            lthr: self.lthr_input.parse().ok(),
+            heart_rate_zones: self.heart_rate_zones.unwrap_or(profile.heart_rate_zones),
+            weight: weight_input(&self.weight_input, weight_unit)?,
+            bike_weight: weight_input(&self.bike_weight_input, weight_unit)?,
+            settings,
+            height: self.height_input.parse().ok(),
+            birth_date,
+            ..profile.clone()
+        };
+        for date in &self.removed_ftp {
+            edited.remove_ftp(*date);
+        }
+        if self.ftp_changed {
+            let today = chrono::Local::now().naive_local().date();
+            let date = match self.ftp_date_input.as_str() {
+                "" => today,
+                input => chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                    .map_err(|_| "FTP date must be YYYY-MM-DD")?,
+            };
+            if date > today {
+                return Err("FTP date can't be in the future");
+            }
+            let ftp = self.ftp_input.parse().unwrap_or(0);
+            if ftp == 0 {
+                return Err("FTP is required");
+            }
+            // Retyping the current FTP doesn't add it again.
+            if ftp != edited.ftp || !self.ftp_date_input.is_empty() || self.ftp_source.is_some() {
+                edited.set_ftp(ftp, date, self.ftp_source.unwrap_or(FtpSource::Manual));
+            }
+        }
+        Ok(edited)
+    }
+
     pub fn view(&mut self, profile: &UserProfile) -> Element<Message> {
         // Closure for consistent label for text fields in the view
-        let field_text = |text| Text::new(text).size(16).width(Length::Units(50));
+        let field_text = |text| Text::new(text).size(16).width(Length::Units(90));
 
         // Set fields to current user profile if editing is false.
         if !self.editing {
@@ -70,86 +340,400 @@ impl State {
             if self.ftp_input.is_empty() && profile.ftp != 0 {
                 self.ftp_input = profile.ftp.to_string();
             }
+            fn fill<T: ToString>(input: &mut String, value: Option<T>) {
+                if let (true, Some(value)) = (input.is_empty(), value) {
+                    *input = value.to_string();
+                }
+            }
+            fill(&mut self.map_input, profile.map);
+            fill(&mut self.cp_input, profile.cp);
+            fill(&mut self.w_prime_input, profile.w_prime);
+            fill(&mut self.max_hr_input, profile.max_hr);
+            fill(&mut self.resting_hr_input, profile.resting_hr);
+            fill(&mut self.lthr_input, profile.lthr);
+            let weight_unit = profile.settings.units.weight_unit();
+            let weight = |weight: Option<Weight>| weight.map(|w| w.value(weight_unit).to_string());
+            fill(&mut self.weight_input, weight(profile.weight));
+            fill(&mut self.bike_weight_input, weight(profile.bike_weight));
+            fill(
+                &mut self.height_input,
+                profile.height.map(|h| h.to_string()),
+            );
+            fill(
+                &mut self.birth_date_input,
+                profile.birth_date.map(|d| d.format("%Y-%m-%d").to_string()),
+            );
         }
+        let settings = self
+            .settings
+            .get_or_insert_with(|| profile.settings.clone())
+            .clone();
 
         // Closure for creating a button in order to specify options once.
         let button = |state, label| Button::new(state, Text::new(label).size(16)).padding(8);
+        // Only enable the save button if the edited profile is valid and is
+        // different from the current user profile. Otherwise show why the
+        // profile can't be saved.
+        let edited = self.edited(profile);
         let mut save_button = button(&mut self.save_button, "Save");
-
-        // Only enable the save button if there is data in both the name and ftp
-        // fields as long as the data is different from current user profile. If
-        // any field isn't filled out, do not enable the button.
-        if (!self.name_input.is_empty()
-            && !self.ftp_input.is_empty()
-            && self.name_input != profile.name
-            && self.ftp_input != profile.ftp.to_string())
-            || (self.name_input != profile.name
-                && !self.name_input.is_empty()
-                && self.ftp_input == profile.ftp.to_string())
-            || (self.name_input == profile.name
-                && self.ftp_input != profile.ftp.to_string()
-                && !self.ftp_input.is_empty())
-        {
-            save_button = save_button.on_press(Message::SaveProfile(
-                self.name_input.clone(),
-                self.ftp_input.parse::<u16>().unwrap_or(0),
-            ))
+        let error = match edited.and_then(|edited| edited.validate().map(|_| edited)) {
+            Ok(edited) => {
+                if edited != *profile {
+                    save_button = save_button.on_press(Message::SaveProfile(Box::new(edited)));
+                }
+                None
+            }
+            Err(e) if self.editing => Some(e),
+            Err(_) => None,
         }
+        .or(self.save_error);
 
         let mut delete_button = button(&mut self.delete_button, "Delete");
         if !profile.name.is_empty() {
             delete_button = delete_button.on_press(Message::DeleteProfile);
         }
 
-        Container::new(
-            Column::new()
-                .spacing(10)
-                .width(Length::Units(300))
-                .height(Length::Shrink)
-                .push(Text::new("User Profile").size(30))
-                .push(
-                    Row::new()
-                        .push(field_text("Name:"))
-                        .push(
-                            TextInput::new(
-                                &mut self.name_input_field,
-                                "John Doe",
-                                &self.name_input,
-                                Message::NameInputChanged,
-                            )
-                            .padding(8)
-                            .width(Length::Units(100))
-                            .size(16),
+        // Power zones are shown in watts for the saved FTP. Custom zones are
+        // defined in the profiles file.
+        let mut zones = Column::new()
+            .spacing(5)
+            .push(Text::new("Power Zones").size(20));
+        let zone_watts = profile.power_zones.watts(profile.ftp);
+        for (i, (zone, (low, high))) in profile
+            .power_zones
+            .zones()
+            .iter()
+            .zip(zone_watts)
+            .enumerate()
+        {
+            let watts = match (profile.ftp, high) {
+                (0, _) => String::new(),
+                (_, Some(high)) => format!("{}-{}W", low, high),
+                (_, None) => format!("{}W+", low),
+            };
+            zones = zones.push(
+                Row::new()
+                    .spacing(10)
+                    .push(
+                        Text::new(format!("Z{} {}", i + 1, zone.name))
+                            .size(16)
+                            .width(Length::Units(200)),
+                    )
+                    .push(Text::new(watts).size(16)),
+            );
+        }
+
+        // FTP history, latest first. The FTP entered above takes effect on
+        // the date entered here, so past tests can be added, and entries
+        // can be removed. Removed entries are dropped when the profile is
+        // saved.
+        let mut ftp_history = Column::new()
+            .spacing(5)
+            .push(Text::new("FTP History").size(20))
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Align::Center)
+                    .push(field_text("FTP date:"))
+                    .push(
+                        TextInput::new(
+                            &mut self.ftp_date_input_field,
+                            "Today",
+                            &self.ftp_date_input,
+                            Message::FtpDateInputChanged,
                         )
-                        .spacing(10)
-                        .align_items(Align::Center)
-                        .width(Length::Units(200)),
+                        .padding(8)
+                        .width(Length::Units(100))
+                        .size(16),
+                    ),
+            );
+        let source = self.ftp_source.unwrap_or(FtpSource::Manual);
+        for choice in FtpSource::ALL.iter() {
+            ftp_history = ftp_history.push(
+                Radio::new(
+                    *choice,
+                    choice.to_string(),
+                    Some(source),
+                    Message::FtpSourceSelected,
                 )
-                .push(
-                    Row::new()
-                        .push(field_text("FTP:"))
-                        .push(
-                            TextInput::new(
-                                &mut self.ftp_input_field,
-                                "200",
-                                &self.ftp_input,
-                                Message::FtpInputChanged,
+                .size(16)
+                .text_size(16),
+            );
+        }
+        let removed = &self.removed_ftp;
+        let entries: Vec<_> = profile
+            .ftp_history
+            .iter()
+            .rev()
+            .filter(|e| !removed.contains(&e.date))
+            .collect();
+        self.remove_ftp_buttons
+            .resize_with(entries.len(), Default::default);
+        for (entry, remove) in entries.into_iter().zip(self.remove_ftp_buttons.iter_mut()) {
+            ftp_history = ftp_history.push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Align::Center)
+                    .push(
+                        Text::new(entry.date.format("%Y-%m-%d").to_string())
+                            .size(16)
+                            .width(Length::Units(100)),
+                    )
+                    .push(
+                        Text::new(format!("{}W", entry.ftp))
+                            .size(16)
+                            .width(Length::Units(60)),
+                    )
+                    .push(
+                        Text::new(entry.source.to_string())
+                            .size(16)
+                            .width(Length::Units(130)),
+                    )
+                    .push(
+                        Button::new(remove, Text::new("Remove").size(14))
+                            .padding(4)
+                            .on_press(Message::RemoveFtp(entry.date)),
+                    ),
+            );
+        }
+
+        // Heart rate zones are shown in bpm once the heart rates the zone
+        // model needs are saved.
+        let mut heart_rate_zones = Column::new()
+            .spacing(5)
+            .push(Text::new("Heart Rate Zones").size(20));
+        let selected = self.heart_rate_zones.unwrap_or(profile.heart_rate_zones);
+        for model in [HeartRateZoneModel::Friel, HeartRateZoneModel::Karvonen].iter() {
+            heart_rate_zones = heart_rate_zones.push(
+                Radio::new(
+                    *model,
+                    model.to_string(),
+                    Some(selected),
+                    Message::HeartRateZonesSelected,
+                )
+                .size(16)
+                .text_size(16),
+            );
+        }
+        match profile.heart_rate_zones() {
+            Some(zones) => {
+                for (i, zone) in zones.iter().enumerate() {
+                    let bpm = match zone.high {
+                        Some(high) => format!("{}-{}bpm", zone.low, high),
+                        None => format!("{}bpm+", zone.low),
+                    };
+                    heart_rate_zones = heart_rate_zones.push(
+                        Row::new()
+                            .spacing(10)
+                            .push(
+                                Text::new(format!("Z{} {}", i + 1, zone.name))
+                                    .size(16)
+                                    .width(Length::Units(200)),
                             )
-                            .padding(8)
-                            .width(Length::Units(100))
-                            .size(16),
-                        )
-                        .spacing(10)
-                        .align_items(Align::Center)
-                        .width(Length::Units(200)),
+                            .push(Text::new(bpm).size(16)),
+                    );
+                }
+            }
+            None => {
+                let needs = match profile.heart_rate_zones {
+                    HeartRateZoneModel::Friel => "Set LTHR to see heart rate zones",
+                    HeartRateZoneModel::Karvonen => {
+                        "Set max and resting HR to see heart rate zones"
+                    }
+                };
+                heart_rate_zones = heart_rate_zones.push(Text::new(needs).size(16));
+            }
+        }
+
+        // Closure for a labelled text input field.
+        let field = |label, state, placeholder, value: &String, message: fn(String) -> Message| {
+            Row::new()
+                .push(field_text(label))
+                .push(
+                    TextInput::new(state, placeholder, value, message)
+                        .padding(8)
+                        .width(Length::Units(100))
+                        .size(16),
+                )
+                .spacing(10)
+                .align_items(Align::Center)
+                .width(Length::Units(200))
+        };
+        let mut form = Column::new()
+            .spacing(10)
+            .width(Length::Units(300))
+            .height(Length::Shrink)
+            .push(Text::new("User Profile").size(30))
+            .push(field(
+                "Name:",
+                &mut self.name_input_field,
+                "John Doe",
+                &self.name_input,
+                Message::NameInputChanged,
+            ))
+            .push(field(
+                "FTP:",
+                &mut self.ftp_input_field,
+                "200",
+                &self.ftp_input,
+                Message::FtpInputChanged,
+            ))
+            .push(field(
+                "MAP:",
+                &mut self.map_input_field,
+                "300",
+                &self.map_input,
+                Message::MapInputChanged,
+            ))
+            .push(field(
+                "CP:",
+                &mut self.cp_input_field,
+                "210",
+                &self.cp_input,
+                Message::CpInputChanged,
+            ))
+            .push(field(
+                "W' (J):",
+                &mut self.w_prime_input_field,
+                "20000",
+                &self.w_prime_input,
+                Message::WPrimeInputChanged,
+            ))
+            .push(field(
+                "Max HR:",
+                &mut self.max_hr_input_field,
+                "190",
+                &self.max_hr_input,
+                Message::MaxHrInputChanged,
+            ))
+            .push(field(
+                "Resting HR:",
+                &mut self.resting_hr_input_field,
+                "50",
+                &self.resting_hr_input,
+                Message::RestingHrInputChanged,
+            ))
+            .push(field(
+                "LTHR:",
+                &mut self.lthr_input_field,
+                "170",
+                &self.lthr_input,
+                Message::LthrInputChanged,
+            ))
+            .push(field(
+                "Height (cm):",
+                &mut self.height_input_field,
+                "175",
+                &self.height_input,
+                Message::HeightInputChanged,
+            ))
+            .push(field(
+                "Birth date:",
+                &mut self.birth_date_input_field,
+                "1985-04-12",
+                &self.birth_date_input,
+                Message::BirthDateInputChanged,
+            ))
+            .push(field(
+                "Weight:",
+                &mut self.weight_input_field,
+                "70",
+                &self.weight_input,
+                Message::WeightInputChanged,
+            ))
+            .push(field(
+                "Bike weight:",
+                &mut self.bike_weight_input_field,
+                "8",
+                &self.bike_weight_input,
+                Message::BikeWeightInputChanged,
+            ));
+        if let Some(watts_per_kg) = profile.watts_per_kg(profile.ftp) {
+            form = form.push(Text::new(format!("FTP: {:.1} W/kg", watts_per_kg)).size(16));
+        }
+        if let Some(error) = error {
+            form = form.push(
+                Text::new(error)
+                    .size(16)
+                    .color(Color::from_rgb(0.8, 0.2, 0.2)),
+            );
+        }
+
+        // Settings are saved with the rest of the profile.
+        let radio_row = |row: Row<'static, Message>| row.spacing(20).align_items(Align::Center);
+        let mut units = radio_row(Row::new().push(field_text("Units:")));
+        for choice in [Units::Metric, Units::Imperial].iter() {
+            units = units.push(
+                Radio::new(
+                    *choice,
+                    choice.to_string(),
+                    Some(settings.units),
+                    Message::UnitsSelected,
+                )
+                .size(16)
+                .text_size(16),
+            );
+        }
+        let mut smoothing = radio_row(Row::new().push(field_text("Power:")));
+        for seconds in PowerSmoothing::CHOICES.iter() {
+            smoothing = smoothing.push(
+                Radio::new(
+                    *seconds,
+                    format!("{}s", seconds),
+                    Some(settings.power_smoothing.seconds()),
+                    Message::PowerSmoothingSelected,
+                )
+                .size(16)
+                .text_size(16),
+            );
+        }
+        let mut settings_column = Column::new()
+            .spacing(5)
+            .push(Text::new("Settings").size(20))
+            .push(units)
+            .push(smoothing)
+            .push(
+                Checkbox::new(
+                    settings.erg_mode,
+                    "Start rides in ERG mode",
+                    Message::ErgModeToggled,
                 )
+                .size(16)
+                .text_size(16),
+            )
+            .push(Text::new("Shown while riding").size(16));
+        for metric in RideMetric::ALL.iter().copied() {
+            settings_column = settings_column.push(
+                Checkbox::new(
+                    settings.ride_metrics.contains(&metric),
+                    metric.to_string(),
+                    move |show| Message::RideMetricToggled(metric, show),
+                )
+                .size(16)
+                .text_size(16),
+            );
+        }
+
+        Container::new(
+            Row::new()
+                .spacing(40)
                 .push(
-                    Row::new()
+                    form.push(
+                        Row::new()
+                            .spacing(20)
+                            .width(Length::Units(200))
+                            .push(save_button)
+                            .push(delete_button),
+                    )
+                    .push(zones),
+                )
+                .push(
+                    Column::new()
                         .spacing(20)
-                        .width(Length::Units(200))
-                        .push(save_button)
-                        .push(delete_button),
-                ),
+                        .push(heart_rate_zones)
+                        .push(ftp_history),
+                )
+                .push(settings_column),
         )
         .width(Length::Fill)
         .height(Length::Fill)
@@ -1,26 +1,123 @@
 // UserProfile to allow multiple users of the software. Allows for a user
 // to easily have workouts adjusted based on their FTP setting.
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use chrono::NaiveDate;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::application::profile_settings::ProfileSettings;
+use crate::application::profile_store::ProfileId;
+use crate::application::types::{Thresholds, Weight};
+use crate::application::zones::{HeartRateZone, HeartRateZoneModel, PowerZones};
 
 // TODO Improve the styling.
 // TODO Capture tabs to change focus of input fields
-#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct UserProfile {
-    // First three fields will be serialized into a TOML file
+    // Set by the ProfileStore the first time the profile is added to it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<ProfileId>,
     // Name field
     pub name: String,
-    // FTP field
+    // FTP field. Kept as the FTP of the latest entry in the FTP history once
+    // there is one.
     pub ftp: u16,
+    // Every FTP the user has had and when it took effect, oldest first.
+    pub ftp_history: Vec<FtpEntry>,
+    // Optional thresholds for workouts prescribed as a percentage of MAP
+    // (maximal aerobic power) or CP (critical power). W' (anaerobic work
+    // capacity above CP) is held in joules alongside CP.
+    pub map: Option<u16>,
+    pub cp: Option<u16>,
+    pub w_prime: Option<u32>,
+    // Power zones by percentage of FTP. Defaults to the Coggan 7-zone model.
+    pub power_zones: PowerZones,
+    // Heart rates in bpm used for heart rate zones, heart rate targets
+    // relative to LTHR (lactate threshold heart rate), and TRIMP.
+    pub max_hr: Option<u8>,
+    pub resting_hr: Option<u8>,
+    pub lthr: Option<u8>,
+    // How heart rate zones are worked out. Defaults to percentages of LTHR.
+    pub heart_rate_zones: HeartRateZoneModel,
+    // Rider and bike weight for W/kg and speed. Weights are always stored in
+    // kg and entered and shown in the units from the profile's settings.
+    pub weight: Option<Weight>,
+    pub bike_weight: Option<Weight>,
+    // Height in cm.
+    pub height: Option<u16>,
+    // Birth date as YYYY-MM-DD.
+    pub birth_date: Option<NaiveDate>,
+    pub settings: ProfileSettings,
+    // Workouts planned ahead to project training load, in date order.
+    pub planned: Vec<PlannedWorkout>,
     // Active field for setting active profile when SavingState. Default will
     // be last active profile.
     pub active: bool,
 }
 
-impl std::fmt::Display for UserProfile {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        //One issue here is that any time Display is called for this
-        //struct, the initial default user will be displayed as "New..."
-        //This can be worked around by only displaying profiles from [1..]
-        //if we ever need to loop through profiles and display them.
+// Where an FTP came from.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FtpSource {
+    Manual,
+    RampTest,
+    TwentyMinuteTest,
+    EightMinuteTest,
+    Estimated,
+}
+
+impl FtpSource {
+    pub const ALL: [FtpSource; 5] = [
+        Self::Manual,
+        Self::RampTest,
+        Self::TwentyMinuteTest,
+        Self::EightMinuteTest,
+        Self::Estimated,
+    ];
+}
+
+impl fmt::Display for FtpSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Manual => write!(f, "Manual"),
+            Self::RampTest => write!(f, "Ramp test"),
+            Self::TwentyMinuteTest => write!(f, "20 minute test"),
+            Self::EightMinuteTest => write!(f, "8 minute test"),
+            Self::Estimated => write!(f, "Estimated"),
+        }
+    }
+}
+
+// An FTP and the date it took effect.
+//
+// [[ profiles.ftp_history ]]
+// ftp = 285
+// date = "2021-10-18"
+// source = "ramp_test"
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct FtpEntry {
+    pub ftp: u16,
+    pub date: NaiveDate,
+    pub source: FtpSource,
+}
+
+// A workout planned for a day.
+//
+// [[ profiles.planned ]]
+// date = "2021-10-20"
+// workout = "Metcalfe"
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct PlannedWorkout {
+    pub date: NaiveDate,
+    pub workout: String,
+}
+
+impl fmt::Display for UserProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let name = if self.name.is_empty() {
             "New..."
         } else {
@@ -37,11 +134,191 @@ impl UserProfile {
             ..Default::default()
         }
     }
+
+    // Records a new FTP taking effect on a date. A second FTP on the same date
+    // replaces the first. The current FTP is the latest entry, so an FTP
+    // backdated before the latest entry doesn't change it. The first FTP
+    // recorded for a profile that already had an FTP starts the history with
+    // the FTP it had, in effect up to the day before, so older rides keep
+    // being scored against it.
+    pub fn set_ftp(&mut self, ftp: u16, date: NaiveDate, source: FtpSource) {
+        if self.ftp_history.is_empty() && self.ftp != 0 && self.ftp != ftp {
+            self.ftp_history.push(FtpEntry {
+                ftp: self.ftp,
+                date: date - chrono::Duration::days(1),
+                source: FtpSource::Manual,
+            });
+        }
+        let entry = FtpEntry { ftp, date, source };
+        match self.ftp_history.binary_search_by_key(&date, |e| e.date) {
+            Ok(i) => self.ftp_history[i] = entry,
+            Err(i) => self.ftp_history.insert(i, entry),
+        }
+        self.update_ftp();
+    }
+
+    // Removes the FTP that took effect on a date, i.e. one entered by
+    // mistake. The current FTP goes back to the latest entry left.
+    pub fn remove_ftp(&mut self, date: NaiveDate) {
+        self.ftp_history.retain(|e| e.date != date);
+        self.update_ftp();
+    }
+
+    // Keeps the current FTP in step with the FTP history. Profiles without an
+    // FTP history keep their FTP.
+    fn update_ftp(&mut self) {
+        self.ftp_history.sort_by_key(|e| e.date);
+        if let Some(latest) = self.ftp_history.last() {
+            self.ftp = latest.ftp;
+        }
+    }
+
+    // Plans a workout for a day. Planned days that have passed are dropped
+    // as they no longer project anything.
+    pub fn plan(&mut self, date: NaiveDate, workout: &str, today: NaiveDate) {
+        self.planned.retain(|p| p.date > today);
+        let i = self.planned.partition_point(|p| p.date <= date);
+        self.planned.insert(
+            i,
+            PlannedWorkout {
+                date,
+                workout: workout.to_string(),
+            },
+        );
+    }
+
+    pub fn unplan(&mut self, i: usize) {
+        if i < self.planned.len() {
+            self.planned.remove(i);
+        }
+    }
+
+    // FTP in effect on a date so rides are scored against the FTP the rider
+    // had at the time. Dates before the FTP history starts use the first
+    // FTP recorded.
+    pub fn ftp_on(&self, date: NaiveDate) -> u16 {
+        let effective = self
+            .ftp_history
+            .iter()
+            .take_while(|e| e.date <= date)
+            .last()
+            .or_else(|| self.ftp_history.first());
+        effective.map_or(self.ftp, |e| e.ftp)
+    }
+
+    // Thresholds used to resolve workout power targets to watts for this user.
+    pub fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            ftp: self.ftp,
+            map: self.map,
+            cp: self.cp,
+            lthr: self.lthr,
+        }
+    }
+
+    // Heart rate zones in bpm. None until the heart rates the zone model
+    // needs are set.
+    pub fn heart_rate_zones(&self) -> Option<Vec<HeartRateZone>> {
+        self.heart_rate_zones
+            .zones(self.max_hr, self.resting_hr, self.lthr)
+    }
+
+    // Watts relative to the rider's weight. None without a weight.
+    pub fn watts_per_kg(&self, watts: u16) -> Option<f32> {
+        self.weight.map(|weight| f32::from(watts) / weight.kg())
+    }
+
+    // Checks the profile is complete, the heart rates make sense together and
+    // the rider's details are believable.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.name.is_empty() {
+            return Err("Name is required");
+        }
+        if self.ftp == 0 {
+            return Err("FTP is required");
+        }
+        if self.w_prime.is_some() && self.cp.is_none() {
+            return Err("CP is required with W'");
+        }
+        if let (Some(max_hr), Some(resting_hr)) = (self.max_hr, self.resting_hr) {
+            if resting_hr >= max_hr {
+                return Err("Resting HR must be lower than max HR");
+            }
+        }
+        if let (Some(max_hr), Some(lthr)) = (self.max_hr, self.lthr) {
+            if lthr > max_hr {
+                return Err("LTHR can't be higher than max HR");
+            }
+        }
+        if let (Some(resting_hr), Some(lthr)) = (self.resting_hr, self.lthr) {
+            if lthr <= resting_hr {
+                return Err("LTHR must be higher than resting HR");
+            }
+        }
+        if let Some(weight) = self.weight {
+            if !(20.0..=300.0).contains(&weight.kg()) {
+                return Err("Weight must be between 20kg and 300kg");
+            }
+        }
+        if let Some(bike_weight) = self.bike_weight {
+            if bike_weight.kg() > 50.0 {
+                return Err("Bike weight must be under 50kg");
+            }
+        }
+        if let Some(height) = self.height {
+            if !(50..=250).contains(&height) {
+                return Err("Height must be between 50cm and 250cm");
+            }
+        }
+        if let Some(birth_date) = self.birth_date {
+            if birth_date > chrono::Local::now().naive_local().date() {
+                return Err("Birth date can't be in the future");
+            }
+        }
+        Ok(())
+    }
+}
+
+// Profiles are stored in a single TOML file as an array of tables.
+//
+// [[ profiles ]]
+// name = "Justina"
+// ftp = 285
+// active = true
+#[derive(Deserialize, Serialize)]
+struct Profiles {
+    profiles: Vec<UserProfile>,
+}
+
+pub fn load_profiles(path: &Path) -> Result<Vec<UserProfile>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut profiles: Profiles = toml::from_str(&contents).map_err(|e| e.to_string())?;
+    for profile in profiles.profiles.iter_mut() {
+        profile.update_ftp();
+    }
+    Ok(profiles.profiles)
+}
+
+pub fn save_profiles(path: &Path, profiles: &[UserProfile]) -> Result<(), String> {
+    let profiles = Profiles {
+        profiles: profiles.to_vec(),
+    };
+    // Going through a toml::Value writes values ahead of tables (power zones)
+    // which TOML requires no matter the order of the fields.
+    let value = toml::Value::try_from(&profiles).map_err(|e| e.to_string())?;
+    let contents = toml::to_string(&value).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, contents).map_err(|e| e.to_string())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::application::profile_store::ProfileStore;
+    use crate::application::types::WeightUnit;
+    use crate::application::TestDir;
 
     #[test]
     fn test_user_profile_create_active() {
@@ -54,4 +331,265 @@ mod test {
         let user = UserProfile::new(false);
         assert!(!user.active);
     }
+
+    #[test]
+    fn test_user_profile_deserialize() {
+        let s = r#"
+        [[ profiles ]]
+        name = "Justina"
+        ftp = 285
+        active = true
+
+        [[ profiles ]]
+        name = "Sam"
+        ftp = 240
+        cp = 250
+        "#;
+        let profiles: Profiles = toml::from_str(s).unwrap();
+        assert_eq!(profiles.profiles.len(), 2);
+        assert!(profiles.profiles[0].active);
+        assert_eq!(profiles.profiles[1].cp, Some(250));
+        assert!(!profiles.profiles[1].active);
+    }
+
+    #[test]
+    fn test_user_profile_save_load() {
+        let mut profiles = vec![
+            UserProfile {
+                name: String::from("Justina"),
+                ftp: 285,
+                cp: Some(295),
+                ..UserProfile::new(true)
+            },
+            UserProfile {
+                name: String::from("Sam"),
+                ftp: 240,
+                ..UserProfile::new(false)
+            },
+        ];
+        profiles[1].set_ftp(230, date("2021-08-01"), FtpSource::RampTest);
+        profiles[1].set_ftp(240, date("2021-10-01"), FtpSource::Manual);
+        profiles[1].plan(date("2021-10-20"), "Metcalfe", date("2021-10-18"));
+        // IDs are saved once the store has given them out.
+        let profiles = ProfileStore::from_profiles(profiles).to_profiles();
+        assert!(profiles.iter().all(|p| p.id.is_some()));
+        let dir = TestDir::new("profile");
+        let path = dir.join("profiles.toml");
+        save_profiles(&path, &profiles).unwrap();
+        let loaded = load_profiles(&path);
+        assert_eq!(loaded.unwrap(), profiles);
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_user_profile_ftp_history() {
+        let mut user = UserProfile {
+            ftp: 250,
+            ..UserProfile::new(true)
+        };
+        assert_eq!(user.ftp_on(date("2021-01-01")), 250);
+
+        user.set_ftp(260, date("2021-06-01"), FtpSource::TwentyMinuteTest);
+        user.set_ftp(280, date("2021-09-01"), FtpSource::RampTest);
+        assert_eq!(user.ftp, 280);
+        // Backdating an FTP doesn't change the current FTP.
+        user.set_ftp(270, date("2021-07-15"), FtpSource::Estimated);
+        assert_eq!(user.ftp, 280);
+        // The FTP the profile had before its first entry is kept.
+        assert_eq!(user.ftp_history.len(), 4);
+        assert_eq!(user.ftp_history[0].ftp, 250);
+        assert_eq!(user.ftp_history[0].date, date("2021-05-31"));
+        assert_eq!(user.ftp_on(date("2021-01-01")), 250);
+        assert_eq!(user.ftp_on(date("2021-06-01")), 260);
+        assert_eq!(user.ftp_on(date("2021-07-14")), 260);
+        assert_eq!(user.ftp_on(date("2021-07-15")), 270);
+        assert_eq!(user.ftp_on(date("2021-10-18")), 280);
+        // A retest on the same day replaces the earlier entry.
+        user.set_ftp(285, date("2021-09-01"), FtpSource::Manual);
+        assert_eq!(user.ftp_history.len(), 4);
+        assert_eq!(user.ftp, 285);
+        // Removing the latest entry goes back to the one before it.
+        user.remove_ftp(date("2021-09-01"));
+        assert_eq!(user.ftp_history.len(), 3);
+        assert_eq!(user.ftp, 270);
+        assert_eq!(user.ftp_on(date("2021-10-18")), 270);
+    }
+
+    #[test]
+    fn test_user_profile_ftp_history_deserialize() {
+        let s = r#"
+        [[ profiles ]]
+        name = "Justina"
+        ftp = 200
+
+        [[ profiles.ftp_history ]]
+        ftp = 285
+        date = "2021-10-01"
+        source = "ramp_test"
+
+        [[ profiles.ftp_history ]]
+        ftp = 270
+        date = "2021-08-01"
+        source = "twenty_minute_test"
+        "#;
+        let dir = TestDir::new("ftp");
+        let path = dir.join("profiles.toml");
+        fs::write(&path, s).unwrap();
+        let loaded = load_profiles(&path);
+        let user = &loaded.unwrap()[0];
+        // The FTP is taken from the latest entry in the history.
+        assert_eq!(user.ftp, 285);
+        assert_eq!(user.ftp_history[0].source, FtpSource::TwentyMinuteTest);
+    }
+
+    #[test]
+    fn test_user_profile_plan() {
+        let mut user = UserProfile::new(true);
+        let today = date("2021-10-18");
+        user.plan(date("2021-10-21"), "Metcalfe", date("2021-10-15"));
+        user.plan(date("2021-10-17"), "Ramp Test", date("2021-10-15"));
+        user.plan(date("2021-10-20"), "Recovery", today);
+        // Days that have passed are dropped.
+        let planned: Vec<&str> = user.planned.iter().map(|p| p.workout.as_str()).collect();
+        assert_eq!(planned, vec!["Recovery", "Metcalfe"]);
+        user.unplan(0);
+        user.unplan(5);
+        assert_eq!(user.planned.len(), 1);
+        assert_eq!(user.planned[0].date, date("2021-10-21"));
+    }
+
+    #[test]
+    fn test_user_profile_thresholds() {
+        let user = UserProfile {
+            ftp: 250,
+            map: Some(340),
+            ..UserProfile::new(true)
+        };
+        let thresholds = user.thresholds();
+        assert_eq!(thresholds.ftp, 250);
+        assert_eq!(thresholds.map, Some(340));
+        assert_eq!(thresholds.cp, None);
+    }
+
+    #[test]
+    fn test_user_profile_heart_rate() {
+        let s = r#"
+        [[ profiles ]]
+        name = "Justina"
+        ftp = 285
+        max_hr = 190
+        resting_hr = 50
+        heart_rate_zones = "karvonen"
+        "#;
+        let profiles: Profiles = toml::from_str(s).unwrap();
+        let user = &profiles.profiles[0];
+        assert_eq!(user.heart_rate_zones, HeartRateZoneModel::Karvonen);
+        assert_eq!(user.heart_rate_zones().unwrap().len(), 5);
+        assert_eq!(user.thresholds().lthr, None);
+        assert_eq!(UserProfile::default().heart_rate_zones(), None);
+    }
+
+    #[test]
+    fn test_user_profile_validate() {
+        let user = UserProfile {
+            name: String::from("Justina"),
+            ftp: 285,
+            max_hr: Some(190),
+            resting_hr: Some(50),
+            lthr: Some(172),
+            ..UserProfile::new(true)
+        };
+        assert_eq!(user.validate(), Ok(()));
+        let invalid = |user: UserProfile| user.validate().unwrap_err();
+        assert_eq!(
+            invalid(UserProfile {
+                name: String::new(),
+                ..user.clone()
+            }),
+            "Name is required"
+        );
+        assert_eq!(
+            invalid(UserProfile {
+                resting_hr: Some(190),
+                ..user.clone()
+            }),
+            "Resting HR must be lower than max HR"
+        );
+        assert_eq!(
+            invalid(UserProfile {
+                lthr: Some(195),
+                ..user.clone()
+            }),
+            "LTHR can't be higher than max HR"
+        );
+        assert_eq!(
+            invalid(UserProfile {
+                w_prime: Some(20000),
+                ..user.clone()
+            }),
+            "CP is required with W'"
+        );
+        assert_eq!(
+            invalid(UserProfile {
+                max_hr: None,
+                lthr: Some(45),
+                ..user
+            }),
+            "LTHR must be higher than resting HR"
+        );
+    }
+
+    #[test]
+    fn test_user_profile_weight() {
+        let s = r#"
+        [[ profiles ]]
+        name = "Justina"
+        ftp = 290
+        weight = 72.5
+        bike_weight = 8
+        height = 170
+        birth_date = "1985-04-12"
+
+        [ profiles.settings ]
+        units = "imperial"
+        "#;
+        let profiles: Profiles = toml::from_str(s).unwrap();
+        let user = &profiles.profiles[0];
+        assert_eq!(user.weight.unwrap().kg(), 72.5);
+        assert_eq!(
+            user.bike_weight
+                .unwrap()
+                .display(user.settings.units.weight_unit()),
+            "17.6lb"
+        );
+        assert_eq!(user.watts_per_kg(290), Some(4.0));
+        assert_eq!(user.validate(), Ok(()));
+        assert_eq!(user.birth_date, "1985-04-12".parse().ok());
+
+        let invalid = |user: UserProfile| user.validate().unwrap_err();
+        assert_eq!(
+            invalid(UserProfile {
+                weight: Some(Weight::new(10.0, WeightUnit::Kg).unwrap()),
+                ..user.clone()
+            }),
+            "Weight must be between 20kg and 300kg"
+        );
+        assert_eq!(
+            invalid(UserProfile {
+                height: Some(300),
+                ..user.clone()
+            }),
+            "Height must be between 50cm and 250cm"
+        );
+        assert_eq!(
+            invalid(UserProfile {
+                birth_date: "3000-01-01".parse().ok(),
+                ..user.clone()
+            }),
+            "Birth date can't be in the future"
+        );
+    }
 }
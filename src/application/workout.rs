@@ -0,0 +1,437 @@
+// A Workout is a WorkoutTemplate expanded for a user. Intervals and repeats are
+// flattened into a list of segments with absolute start times, power targets
+// are resolved to watts from the user's thresholds, and lap boundaries are
+// worked out from lap_each_interval and lap_each_segment. This is what gets
+// ridden, displayed and scored rather than the template itself.
+use serde_derive::Serialize;
+
+use crate::application::ftp_test::FtpTest;
+use crate::application::library::WorkoutTemplate;
+use crate::application::session::{Record, Sample};
+use crate::application::stats::Stats;
+use crate::application::types::{
+    CadenceTarget, HeartRateTarget, PowerTarget, Quantity, Rpe, Thresholds,
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WorkoutSegment {
+    // Name of the interval the segment is part of. Segments defined directly
+    // in a workout don't have an interval name.
+    pub interval: Option<String>,
+    // Start time and duration in seconds from the start of the workout.
+    pub start: u32,
+    pub duration: u32,
+    pub power_start: PowerTarget,
+    pub power_end: PowerTarget,
+    // Watts at the start and end of the segment. None for free ride segments
+    // or targets relative to a threshold the user hasn't set.
+    pub watts_start: Option<u16>,
+    pub watts_end: Option<u16>,
+    // Low and high watts of the band at the start and end of the segment.
+    // Single targets have a band of zero width.
+    pub band_start: Option<(u16, u16)>,
+    pub band_end: Option<(u16, u16)>,
+    pub cadence: Option<CadenceTarget>,
+    pub heart_rate: Option<HeartRateTarget>,
+    // Low and high bpm of the heart rate target.
+    pub heart_rate_bpm: Option<(u8, u8)>,
+    pub rpe: Option<Rpe>,
+    // Lap the segment is in starting from 0.
+    pub lap: usize,
+}
+
+impl WorkoutSegment {
+    pub fn end(&self) -> u32 {
+        self.start + self.duration
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Workout {
+    pub name: String,
+    pub description: String,
+    pub duration: u32,
+    pub segments: Vec<WorkoutSegment>,
+    // Start time of each lap in seconds.
+    pub laps: Vec<u32>,
+    pub ftp_test: Option<FtpTest>,
+}
+
+impl Workout {
+    pub fn new(template: &WorkoutTemplate, thresholds: &Thresholds) -> Self {
+        let mut segments = Vec::new();
+        let mut laps = vec![0];
+        let mut start = 0;
+        for interval in &template.intervals {
+            for _ in 0..interval.repeat.unwrap_or(Quantity(1)).0 {
+                for (i, segment) in interval.segments.iter().enumerate() {
+                    // A lap starts with each interval, and each repeat of it, when
+                    // lap_each_interval is set or the interval starts a lap of an
+                    // included workout, and with each segment when
+                    // lap_each_segment is set.
+                    let new_lap = match i {
+                        0 => {
+                            interval.lap_start.unwrap_or(template.lap_each_interval)
+                                || interval.lap_each_segment
+                        }
+                        _ => interval.lap_each_segment,
+                    };
+                    if new_lap && start > 0 && laps.last() != Some(&start) {
+                        laps.push(start);
+                    }
+                    segments.push(WorkoutSegment {
+                        interval: interval.name.clone(),
+                        start,
+                        duration: segment.duration.0,
+                        power_start: segment.power_start,
+                        power_end: segment.power_end,
+                        watts_start: segment.power_start.watts(thresholds),
+                        watts_end: segment.power_end.watts(thresholds),
+                        band_start: segment.power_start.band(thresholds),
+                        band_end: segment.power_end.band(thresholds),
+                        cadence: segment.cadence,
+                        heart_rate: segment.heart_rate,
+                        heart_rate_bpm: segment.heart_rate.and_then(|hr| hr.bpm(thresholds.lthr)),
+                        rpe: segment.rpe,
+                        lap: laps.len() - 1,
+                    });
+                    start += segment.duration.0;
+                }
+            }
+        }
+        Self {
+            name: template.name.clone(),
+            description: template.description.clone(),
+            duration: start,
+            segments,
+            laps,
+            ftp_test: template.ftp_test,
+        }
+    }
+
+    // Segment being ridden at a number of seconds into the workout.
+    pub fn segment_at(&self, second: u32) -> Option<&WorkoutSegment> {
+        let i = match self.segments.binary_search_by(|s| s.start.cmp(&second)) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        // Zero length segments share a start time with the next segment.
+        self.segments[i..]
+            .iter()
+            .find(|s| s.start <= second && second < s.end())
+    }
+
+    // Target watts at a number of seconds into the workout. Ramps are
+    // interpolated between the start and end watts of the segment.
+    pub fn watts_at(&self, second: u32) -> Option<u16> {
+        let segment = self.segment_at(second)?;
+        Some(interpolate(
+            segment,
+            second,
+            segment.watts_start?,
+            segment.watts_end?,
+        ))
+    }
+
+    // Low and high watts of the target band at a number of seconds into the
+    // workout, interpolated the same way as the target watts.
+    pub fn band_at(&self, second: u32) -> Option<(u16, u16)> {
+        let segment = self.segment_at(second)?;
+        let (start, end) = (segment.band_start?, segment.band_end?);
+        Some((
+            interpolate(segment, second, start.0, end.0),
+            interpolate(segment, second, start.1, end.1),
+        ))
+    }
+
+    pub fn lap_at(&self, second: u32) -> Option<usize> {
+        self.segment_at(second).map(|s| s.lap)
+    }
+
+    // TSS of the workout ridden on target, used to plan training load. Free
+    // ride segments don't have a target to ride, so they're counted at the
+    // intensity of an FTP test's efforts, or the intensity of their RPE.
+    // Other free ride segments are left out rather than guessed at.
+    pub fn tss(&self, ftp: u16) -> Option<f32> {
+        let records: Vec<Record> = (0..self.duration)
+            .filter_map(|second| {
+                let watts = self
+                    .watts_at(second)
+                    .or_else(|| self.estimated_watts(second, ftp))?;
+                Some(Record {
+                    elapsed: second,
+                    target: Some(watts),
+                    band: None,
+                    adjustment: None,
+                    lap: 0,
+                    sample: Sample {
+                        power: Some(watts),
+                        ..Default::default()
+                    },
+                })
+            })
+            .collect();
+        Stats::new(&records, ftp).tss
+    }
+
+    // Watts a free ride segment is expected to be ridden at.
+    fn estimated_watts(&self, second: u32, ftp: u16) -> Option<u16> {
+        let segment = self.segment_at(second)?;
+        if segment.power_start != PowerTarget::Free {
+            return None;
+        }
+        let intensity = self
+            .ftp_test
+            .map(FtpTest::effort_intensity)
+            .or_else(|| segment.rpe.map(Rpe::intensity))?;
+        Some((f32::from(ftp) * intensity).round() as u16)
+    }
+}
+
+// Watts at a second of a segment going from the start watts to the end watts.
+fn interpolate(segment: &WorkoutSegment, second: u32, start: u16, end: u16) -> u16 {
+    let elapsed = i64::from(second - segment.start);
+    let change = (i64::from(end) - i64::from(start)) * elapsed / i64::from(segment.duration);
+    (i64::from(start) + change) as u16
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::library::{Library, ShadowLibrary, Source};
+
+    fn workout(s: &str, ftp: u16) -> Workout {
+        let mut library = Library::default();
+        let sl: ShadowLibrary = toml::from_str(s).unwrap();
+        assert!(library.load(sl, Source::User).is_empty());
+        let template = library.workouts.values().find(|w| w.source == Source::User);
+        Workout::new(
+            template.unwrap(),
+            &Thresholds {
+                ftp,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_workout_segments_and_laps() {
+        let s = r#"
+        [[ intervals ]]
+        name = "On/Off"
+        duration = "2m"
+        segments = ['30s @ 1.2', '30s @ 0.5']
+        repeat = 2
+
+        [[ workouts ]]
+        name = "Test"
+        description = "Test"
+        duration = "17m"
+        lap_each_interval = true
+        intervals = [
+          'Warmup',
+          'On/Off',
+          { duration = "5m", power_start = 0.5, power_end = 0.4 },
+        ]
+        "#;
+        let workout = workout(s, 200);
+        assert_eq!(workout.duration, 17 * 60);
+        // Warmup has 5 segments, On/Off 2 segments repeated twice
+        assert_eq!(workout.segments.len(), 10);
+        // Each repeat of On/Off is a lap with lap_each_interval.
+        assert_eq!(workout.laps, vec![0, 600, 660, 720]);
+        assert_eq!(workout.segments[5].interval, Some(String::from("On/Off")));
+        assert_eq!(workout.segments[5].start, 600);
+        assert_eq!(workout.segments[5].watts_start, Some(240));
+        assert_eq!(workout.segments[7].lap, 2);
+        assert_eq!(workout.segments[9].interval, None);
+        assert_eq!(workout.segments[9].lap, 3);
+    }
+
+    #[test]
+    fn test_workout_lap_each_segment() {
+        let s = r#"
+        [[ intervals ]]
+        name = "On/Off"
+        duration = "2m"
+        segments = ['30s @ 1.2', '30s @ 0.5']
+        repeat = 2
+        lap_each_segment = true
+
+        [[ workouts ]]
+        name = "Test"
+        description = "Test"
+        duration = "2m"
+        lap_each_interval = false
+        intervals = ['On/Off']
+        "#;
+        assert_eq!(workout(s, 200).laps, vec![0, 30, 60, 90]);
+    }
+
+    #[test]
+    fn test_workout_repeats_without_laps() {
+        let s = r#"
+        [[ intervals ]]
+        name = "On/Off"
+        duration = "2m"
+        segments = ['30s @ 1.2', '30s @ 0.5']
+        repeat = 2
+
+        [[ workouts ]]
+        name = "Test"
+        description = "Test"
+        duration = "2m"
+        lap_each_interval = false
+        intervals = ['On/Off']
+        "#;
+        assert_eq!(workout(s, 200).laps, vec![0]);
+    }
+
+    #[test]
+    fn test_workout_included_laps() {
+        let s = r#"
+        [[ workouts ]]
+        name = "Openers"
+        description = "Laps each interval"
+        duration = "13m"
+        lap_each_interval = true
+        intervals = ['10m @ 0.6', '1m @ 1.2', '2m @ 0.6']
+
+        [[ workouts ]]
+        name = "Long Warmup"
+        description = "One lap"
+        duration = "10m"
+        lap_each_interval = false
+        intervals = ['5m @ 0.5', '5m @ 0.6']
+
+        [[ workouts ]]
+        name = "Threshold"
+        description = "Doesn't lap each interval"
+        duration = "43m"
+        lap_each_interval = false
+        intervals = ['5m @ 0.5', { workout = "Openers" }, '20m @ 0.95', '5m @ 0.5']
+
+        [[ workouts ]]
+        name = "VO2"
+        description = "Laps each interval"
+        duration = "20m"
+        lap_each_interval = true
+        intervals = [{ workout = "Long Warmup" }, '5m @ 1.2', '5m @ 0.5']
+        "#;
+        let mut library = Library::default();
+        assert!(library
+            .load(toml::from_str(s).unwrap(), Source::User)
+            .is_empty());
+        let thresholds = Thresholds {
+            ftp: 250,
+            ..Default::default()
+        };
+        // The included workout laps each of its intervals and laps start
+        // where it starts and ends.
+        let threshold = Workout::new(&library.workouts["Threshold"], &thresholds);
+        assert_eq!(threshold.laps, vec![0, 300, 900, 960, 1080]);
+        // The included workout is one lap.
+        let vo2 = Workout::new(&library.workouts["VO2"], &thresholds);
+        assert_eq!(vo2.laps, vec![0, 600, 900]);
+    }
+
+    #[test]
+    fn test_workout_targets_at() {
+        let s = r#"
+        [[ workouts ]]
+        name = "Test"
+        description = "Test"
+        duration = "2m10s"
+        lap_each_interval = true
+        intervals = [
+          { duration = "1m", power_start = 100, power_end = 160 },
+          '1m @ 0.9',
+          '10s @ free',
+        ]
+        "#;
+        let workout = workout(s, 250);
+        assert_eq!(workout.watts_at(0), Some(100));
+        assert_eq!(workout.watts_at(30), Some(130));
+        assert_eq!(workout.watts_at(59), Some(159));
+        assert_eq!(workout.watts_at(60), Some(225));
+        assert_eq!(workout.band_at(30), Some((130, 130)));
+        assert_eq!(workout.watts_at(125), None);
+        assert_eq!(workout.band_at(125), None);
+        assert_eq!(workout.lap_at(125), Some(2));
+        assert!(workout.segment_at(130).is_none());
+    }
+
+    #[test]
+    fn test_workout_band_at() {
+        let s = r#"
+        [[ workouts ]]
+        name = "Test"
+        description = "Test"
+        duration = "2m"
+        lap_each_interval = true
+        intervals = [
+          '1m @ 0.88..0.94',
+          { duration = "1m", power_start = "200..220", power_end = "300..320" },
+        ]
+        "#;
+        let workout = workout(s, 250);
+        assert_eq!(workout.band_at(0), Some((220, 235)));
+        assert_eq!(workout.watts_at(0), Some(227));
+        assert_eq!(workout.band_at(60), Some((200, 220)));
+        assert_eq!(workout.band_at(90), Some((250, 270)));
+    }
+
+    #[test]
+    fn test_workout_tss() {
+        let s = r#"
+        [[ workouts ]]
+        name = "Test"
+        description = "Test"
+        duration = "1h"
+        lap_each_interval = true
+        intervals = ['1h @ 0.8']
+        "#;
+        let tss = workout(s, 250).tss(250).unwrap();
+        assert!((tss - 64.0).abs() < 0.1);
+        assert_eq!(workout(s, 250).tss(0), None);
+
+        // Free riding without an RPE doesn't add to the TSS.
+        let s = r#"
+        [[ workouts ]]
+        name = "Test"
+        description = "Test"
+        duration = "1h"
+        lap_each_interval = true
+        intervals = ['30m @ 0.8', '30m @ free']
+        "#;
+        let tss = workout(s, 250).tss(250).unwrap();
+        assert!((tss - 32.0).abs() < 0.1);
+
+        // Free riding by feel counts at the intensity of the RPE.
+        let s = r#"
+        [[ workouts ]]
+        name = "Test"
+        description = "Test"
+        duration = "1h"
+        lap_each_interval = true
+        intervals = ['30m @ 0.8', '30m @ free 7rpe']
+        "#;
+        let tss = workout(s, 250).tss(250).unwrap();
+        assert!((tss - 73.3).abs() < 0.1);
+
+        // The efforts of an FTP test count at the test's intensity, 105% for
+        // the 20 minute test.
+        let s = r#"
+        [[ workouts ]]
+        name = "Test"
+        description = "Test"
+        duration = "20m"
+        lap_each_interval = true
+        ftp_test = "twenty_minute"
+        intervals = ['20m @ free 5rpe']
+        "#;
+        let tss = workout(s, 250).tss(250).unwrap();
+        assert!((tss - 36.9).abs() < 0.1);
+    }
+}
@@ -0,0 +1,341 @@
+// Power zones split power into training zones by percentage of FTP. Each zone
+// is defined by its upper boundary with the last zone having no upper
+// boundary. The Coggan 7-zone model is the default, but a profile can define
+// its own zones.
+//
+// [[ profiles ]]
+// name = "Justina"
+// ftp = 285
+// power_zones = [
+//   { name = "Easy", max = 75 },
+//   { name = "Tempo", max = 90 },
+//   { name = "Threshold", max = 105 },
+//   { name = "Hard" },
+// ]
+//
+// Heart rate zones are worked out from the profile's heart rates using either
+// percentages of LTHR (Friel) or percentages of heart rate reserve between
+// resting and max heart rate (Karvonen).
+//
+// [[ profiles ]]
+// name = "Justina"
+// max_hr = 188
+// resting_hr = 48
+// lthr = 172
+// heart_rate_zones = "karvonen"
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct PowerZone {
+    pub name: String,
+    // Upper boundary as a whole percentage of FTP, inclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<u16>,
+}
+
+impl PowerZone {
+    fn new(name: &str, max: Option<u16>) -> Self {
+        Self {
+            name: name.to_string(),
+            max,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "Vec<PowerZone>", into = "Vec<PowerZone>")]
+pub struct PowerZones(Vec<PowerZone>);
+
+impl Default for PowerZones {
+    fn default() -> Self {
+        Self(vec![
+            PowerZone::new("Active Recovery", Some(55)),
+            PowerZone::new("Endurance", Some(75)),
+            PowerZone::new("Tempo", Some(90)),
+            PowerZone::new("Lactate Threshold", Some(105)),
+            PowerZone::new("VO2 Max", Some(120)),
+            PowerZone::new("Anaerobic Capacity", Some(150)),
+            PowerZone::new("Neuromuscular Power", None),
+        ])
+    }
+}
+
+impl TryFrom<Vec<PowerZone>> for PowerZones {
+    type Error = &'static str;
+
+    fn try_from(zones: Vec<PowerZone>) -> Result<Self, Self::Error> {
+        let (last, zones_with_max) = zones.split_last().ok_or("at least one zone is required")?;
+        if last.max.is_some() {
+            return Err("last zone must not have a max");
+        }
+        let mut previous = 0;
+        for zone in zones_with_max {
+            match zone.max {
+                Some(max) if max > previous => previous = max,
+                Some(_) => return Err("zone max must be greater than the previous zone max"),
+                None => return Err("only the last zone can be without a max"),
+            }
+        }
+        Ok(Self(zones))
+    }
+}
+
+impl From<PowerZones> for Vec<PowerZone> {
+    fn from(zones: PowerZones) -> Self {
+        zones.0
+    }
+}
+
+impl PowerZones {
+    pub fn zones(&self) -> &[PowerZone] {
+        &self.0
+    }
+
+    // Index of the zone watts fall in. There are no zones without an FTP.
+    pub fn zone(&self, watts: u16, ftp: u16) -> Option<usize> {
+        if ftp == 0 {
+            return None;
+        }
+        let percentage = u32::from(watts) * 100;
+        self.0.iter().position(|zone| match zone.max {
+            Some(max) => percentage <= u32::from(max) * u32::from(ftp),
+            None => true,
+        })
+    }
+
+    // Low and high watts of each zone for an FTP. The last zone has no high.
+    pub fn watts(&self, ftp: u16) -> Vec<(u16, Option<u16>)> {
+        let mut low = 0;
+        self.0
+            .iter()
+            .map(|zone| {
+                let high = zone
+                    .max
+                    .map(|max| (u32::from(max) * u32::from(ftp) / 100) as u16);
+                let range = (low, high);
+                low = high.map_or(low, |h| h + 1);
+                range
+            })
+            .collect()
+    }
+
+    // Seconds spent in each zone from one power reading per second. Seconds
+    // without a power reading aren't counted.
+    pub fn time_in_zone<I>(&self, power: I, ftp: u16) -> Vec<u32>
+    where
+        I: IntoIterator<Item = Option<u16>>,
+    {
+        let mut time = vec![0; self.0.len()];
+        for zone in power.into_iter().filter_map(|watts| self.zone(watts?, ftp)) {
+            time[zone] += 1;
+        }
+        time
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeartRateZoneModel {
+    Friel,
+    Karvonen,
+}
+
+impl Default for HeartRateZoneModel {
+    fn default() -> Self {
+        Self::Friel
+    }
+}
+
+impl fmt::Display for HeartRateZoneModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Friel => write!(f, "% of LTHR (Friel)"),
+            Self::Karvonen => write!(f, "% of HR reserve (Karvonen)"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HeartRateZone {
+    pub name: &'static str,
+    pub low: u8,
+    // Upper boundary in bpm, inclusive. The last Friel zone has no upper
+    // boundary.
+    pub high: Option<u8>,
+}
+
+impl HeartRateZoneModel {
+    // Heart rate zones in bpm. Friel zones need an LTHR and Karvonen zones
+    // need both a resting and max heart rate.
+    pub fn zones(
+        &self,
+        max_hr: Option<u8>,
+        resting_hr: Option<u8>,
+        lthr: Option<u8>,
+    ) -> Option<Vec<HeartRateZone>> {
+        let zones: Vec<(&'static str, Option<u8>)> = match self {
+            Self::Friel => {
+                let lthr = u32::from(lthr?);
+                let bpm = |max: u32| Some((lthr * max / 100).min(255) as u8);
+                vec![
+                    ("Recovery", bpm(80)),
+                    ("Aerobic", bpm(89)),
+                    ("Tempo", bpm(93)),
+                    ("Sub Threshold", bpm(99)),
+                    ("Super Threshold", bpm(102)),
+                    ("Aerobic Capacity", bpm(106)),
+                    ("Anaerobic Capacity", None),
+                ]
+            }
+            Self::Karvonen => {
+                let (max_hr, resting_hr) = (u32::from(max_hr?), u32::from(resting_hr?));
+                if resting_hr >= max_hr {
+                    return None;
+                }
+                let bpm = |max: u32| Some((resting_hr + (max_hr - resting_hr) * max / 100) as u8);
+                vec![
+                    ("Recovery", bpm(60)),
+                    ("Endurance", bpm(70)),
+                    ("Tempo", bpm(80)),
+                    ("Threshold", bpm(90)),
+                    ("Maximum", bpm(100)),
+                ]
+            }
+        };
+        let mut low = 0;
+        Some(
+            zones
+                .into_iter()
+                .map(|(name, high)| {
+                    let zone = HeartRateZone { name, low, high };
+                    low = high.map_or(low, |h| h.saturating_add(1));
+                    zone
+                })
+                .collect(),
+        )
+    }
+}
+
+// Banister's training impulse (TRIMP) from one heart rate reading per second.
+// Each minute is weighted by the fraction of heart rate reserve it was ridden
+// at, so an hour easy scores around 50 and an hour at threshold around 150.
+// Seconds without a heart rate aren't counted. The weighting used is the one
+// Banister gives for men.
+pub fn trimp<I>(heart_rate: I, max_hr: u8, resting_hr: u8) -> Option<f32>
+where
+    I: IntoIterator<Item = Option<u8>>,
+{
+    if resting_hr >= max_hr {
+        return None;
+    }
+    let reserve = f32::from(max_hr - resting_hr);
+    let trimp = heart_rate
+        .into_iter()
+        .flatten()
+        .map(|hr| {
+            let ratio = ((f32::from(hr) - f32::from(resting_hr)) / reserve).clamp(0.0, 1.0);
+            ratio * 0.64 * (1.92 * ratio).exp() / 60.0
+        })
+        .sum();
+    Some(trimp)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_power_zones_default() {
+        let zones = PowerZones::default();
+        assert_eq!(zones.zones().len(), 7);
+        assert_eq!(zones.zone(137, 250), Some(0));
+        assert_eq!(zones.zone(138, 250), Some(1));
+        assert_eq!(zones.zone(250, 250), Some(3));
+        assert_eq!(zones.zone(263, 250), Some(4));
+        assert_eq!(zones.zone(1000, 250), Some(6));
+        assert_eq!(zones.zone(200, 0), None);
+    }
+
+    #[test]
+    fn test_power_zones_watts() {
+        let watts = PowerZones::default().watts(200);
+        assert_eq!(watts[0], (0, Some(110)));
+        assert_eq!(watts[1], (111, Some(150)));
+        assert_eq!(watts[6], (301, None));
+    }
+
+    #[test]
+    fn test_power_zones_custom() {
+        let zones: PowerZones = toml::from_str::<toml::Value>(
+            r#"zones = [{ name = "Easy", max = 75 }, { name = "Hard" }]"#,
+        )
+        .unwrap()["zones"]
+            .clone()
+            .try_into()
+            .unwrap();
+        assert_eq!(zones.zones().len(), 2);
+        assert_eq!(zones.zone(200, 250), Some(1));
+
+        let err = |zones: Vec<PowerZone>| PowerZones::try_from(zones).unwrap_err();
+        assert_eq!(err(vec![]), "at least one zone is required");
+        assert_eq!(
+            err(vec![PowerZone::new("Easy", Some(75))]),
+            "last zone must not have a max"
+        );
+        assert_eq!(
+            err(vec![
+                PowerZone::new("Easy", Some(75)),
+                PowerZone::new("Easier", Some(55)),
+                PowerZone::new("Hard", None)
+            ]),
+            "zone max must be greater than the previous zone max"
+        );
+    }
+
+    #[test]
+    fn test_time_in_zone() {
+        let zones = PowerZones::default();
+        let power = vec![Some(100), Some(100), None, Some(250), Some(400)];
+        assert_eq!(zones.time_in_zone(power, 250), vec![2, 0, 0, 1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_heart_rate_zones_friel() {
+        let model = HeartRateZoneModel::Friel;
+        assert_eq!(model.zones(Some(190), Some(50), None), None);
+        let zones = model.zones(None, None, Some(170)).unwrap();
+        assert_eq!(zones.len(), 7);
+        assert_eq!((zones[0].low, zones[0].high), (0, Some(136)));
+        assert_eq!((zones[1].low, zones[1].high), (137, Some(151)));
+        assert_eq!((zones[4].low, zones[4].high), (169, Some(173)));
+        assert_eq!((zones[6].low, zones[6].high), (181, None));
+    }
+
+    #[test]
+    fn test_heart_rate_zones_karvonen() {
+        let model = HeartRateZoneModel::Karvonen;
+        assert_eq!(model.zones(Some(190), None, Some(170)), None);
+        assert_eq!(model.zones(Some(50), Some(60), None), None);
+        let zones = model.zones(Some(190), Some(50), None).unwrap();
+        assert_eq!(zones.len(), 5);
+        assert_eq!((zones[0].low, zones[0].high), (0, Some(134)));
+        assert_eq!((zones[1].low, zones[1].high), (135, Some(148)));
+        assert_eq!((zones[4].low, zones[4].high), (177, Some(190)));
+    }
+
+    #[test]
+    fn test_trimp() {
+        // An hour at half of heart rate reserve.
+        let heart_rate = vec![Some(120); 3600];
+        let trimp = trimp(heart_rate, 190, 50).unwrap();
+        assert!((trimp - 50.1).abs() < 0.1, "{}", trimp);
+
+        let heart_rate = vec![None, Some(40), Some(190)];
+        let trimp = super::trimp(heart_rate, 190, 50).unwrap();
+        assert!((trimp - 0.64 * 1.92_f32.exp() / 60.0).abs() < 0.001);
+        assert_eq!(super::trimp(vec![Some(120)], 50, 50), None);
+    }
+}
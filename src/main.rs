@@ -4,12 +4,16 @@
 // on screen based on a predescribed workout. The workout will be recorded and
 // be able to be exported to a .fit file for uploading to external applications
 // or sites.
+use std::path::PathBuf;
 use std::str::FromStr;
 
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
 
 mod application;
 
+// Most days of training load shown from the command line, about ten years.
+const MAX_LOAD_DAYS: u32 = 3650;
+
 // Configure command line options for the application.
 fn app() -> App<'static, 'static> {
     App::new("2by20")
@@ -26,6 +30,122 @@ fn app() -> App<'static, 'static> {
                 .value_name("LOG_LEVEL_LIBANT")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("team-library")
+                .long("team-library")
+                .value_name("DIR")
+                .help("Directory of shared team workouts")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("simulate")
+                .long("simulate")
+                .help("Rides with simulated sensors until ANT+ sensors are supported"),
+        )
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Validates workout library files")
+                .arg(
+                    Arg::with_name("files")
+                        .value_name("FILE")
+                        .required(true)
+                        .multiple(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Lists the intervals and workouts in the library")
+                .arg(Arg::with_name("json").long("json").help("Prints JSON")),
+        )
+        .subcommand(
+            SubCommand::with_name("show")
+                .about("Shows a workout expanded into segments, watts and laps")
+                .arg(
+                    Arg::with_name("workout")
+                        .value_name("WORKOUT")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("ftp")
+                        .long("ftp")
+                        .value_name("FTP")
+                        .takes_value(true)
+                        .validator(|v| {
+                            v.parse::<u16>()
+                                .map(|_| ())
+                                .map_err(|_| String::from("FTP must be a number of watts"))
+                        }),
+                )
+                .arg(
+                    Arg::with_name("lthr")
+                        .long("lthr")
+                        .value_name("LTHR")
+                        .takes_value(true)
+                        .validator(|v| {
+                            v.parse::<u8>()
+                                .map(|_| ())
+                                .map_err(|_| String::from("LTHR must be a number of bpm"))
+                        }),
+                )
+                .arg(Arg::with_name("json").long("json").help("Prints JSON"))
+                .arg(
+                    Arg::with_name("seconds")
+                        .long("seconds")
+                        .help("Prints the target for every second of the workout"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("ride")
+                .about("Rides a workout in the terminal")
+                .arg(
+                    Arg::with_name("workout")
+                        .value_name("WORKOUT")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("profile")
+                        .long("profile")
+                        .value_name("NAME")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("simulate")
+                        .long("simulate")
+                        .help("Rides with simulated sensors instead of ANT+"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("load")
+                .about("Shows fitness, fatigue and form from ride history")
+                .arg(
+                    Arg::with_name("profile")
+                        .long("profile")
+                        .value_name("NAME")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("days")
+                        .long("days")
+                        .value_name("DAYS")
+                        .takes_value(true)
+                        .default_value("14")
+                        .validator(|v| match v.parse::<u32>() {
+                            Ok(days) if (1..=MAX_LOAD_DAYS).contains(&days) => Ok(()),
+                            _ => Err(format!("Days must be a number from 1 to {}", MAX_LOAD_DAYS)),
+                        }),
+                )
+                .arg(
+                    Arg::with_name("plan")
+                        .long("plan")
+                        .value_name("DATE=WORKOUT")
+                        .help("Workout planned for a day, i.e. 2021-10-20=Metcalfe")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                ),
+        )
 }
 
 fn main() {
@@ -36,9 +156,14 @@ fn main() {
     let log_level_libant = matches.value_of("log-level-libant").unwrap_or("info");
     let log_level_filter = log::LevelFilter::from_str(log_level).unwrap();
     let log_level_libant_filter = log::LevelFilter::from_str(log_level_libant).unwrap();
-    // Configure application level logging.
+    // Configure application level logging. Subcommands print their output to
+    // stdout, which may be JSON for another program, so they log to stderr.
     // TODO: Switch from stdout logging to logging to file once the
     // application is stable enough.
+    let log_output: fern::Output = match matches.subcommand_name() {
+        Some(_) => std::io::stderr().into(),
+        None => std::io::stdout().into(),
+    };
     fern::Dispatch::new()
         .format(|out, message, record| {
             out.finish(format_args!(
@@ -51,9 +176,41 @@ fn main() {
         })
         .level(log_level_filter)
         .level_for("libant", log_level_libant_filter)
-        .chain(std::io::stdout())
+        .chain(log_output)
         .apply()
         .unwrap();
 
-    application::run()
+    let team_library = matches.value_of("team-library").map(PathBuf::from);
+    match matches.subcommand() {
+        ("validate", Some(m)) => {
+            let files: Vec<PathBuf> = m.values_of("files").unwrap().map(PathBuf::from).collect();
+            std::process::exit(application::cli::validate(&files))
+        }
+        ("list", Some(m)) => {
+            std::process::exit(application::cli::list(team_library, m.is_present("json")))
+        }
+        ("show", Some(m)) => std::process::exit(application::cli::show(
+            m.value_of("workout").unwrap(),
+            team_library,
+            m.value_of("ftp").map(|ftp| ftp.parse().unwrap()),
+            m.value_of("lthr").map(|lthr| lthr.parse().unwrap()),
+            m.is_present("json"),
+            m.is_present("seconds"),
+        )),
+        ("ride", Some(m)) => std::process::exit(application::cli::ride(
+            m.value_of("workout").unwrap(),
+            m.value_of("profile").unwrap(),
+            team_library,
+            m.is_present("simulate"),
+        )),
+        ("load", Some(m)) => std::process::exit(application::cli::load(
+            m.value_of("profile").unwrap(),
+            team_library,
+            m.value_of("days").unwrap().parse().unwrap(),
+            &m.values_of("plan")
+                .map(|v| v.collect::<Vec<&str>>())
+                .unwrap_or_default(),
+        )),
+        _ => application::run(team_library, matches.is_present("simulate")),
+    }
 }